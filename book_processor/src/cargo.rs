@@ -0,0 +1,141 @@
+use crate::error::ErrorKind;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A dependency of the examples, as declared in the root `Cargo.toml`
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub name: String,
+    pub version: String,
+    pub features: Vec<String>,
+    pub default_features: bool,
+    pub path: Option<PathBuf>, // The library of the book itself
+}
+
+impl Dependency {
+    // How the crate is written in `use` paths
+    pub fn ident(&self) -> String {
+        self.name.replace('-', "_")
+    }
+
+    // The evcxr line to import the crate, e.g. `:dep polars = { version = "0.52", features = ["lazy"] }`
+    pub fn dep_line(&self) -> String {
+        if let Some(path) = &self.path {
+            return format!(":dep {} = {{ path = \"{}\" }}", self.name, path.display());
+        }
+
+        let mut spec = vec![format!("version = \"{}\"", self.version)];
+        if !self.default_features {
+            spec.push("default-features = false".to_owned());
+        }
+        if !self.features.is_empty() {
+            let features: Vec<String> = self.features.iter().map(|f| format!("\"{f}\"")).collect();
+            spec.push(format!("features = [{}]", features.join(", ")));
+        }
+
+        if spec.len() == 1 {
+            format!(":dep {} = \"{}\"", self.name, self.version)
+        } else {
+            format!(":dep {} = {{ {} }}", self.name, spec.join(", "))
+        }
+    }
+}
+
+// Read the `[dependencies]` of the root `Cargo.toml`, followed by the library of the book
+// (`lib/lib.rs`) when there is one
+pub fn read_dependencies(root: &Path) -> Result<Vec<Dependency>, ErrorKind> {
+    let path = root.join("Cargo.toml");
+    let manifest = read_manifest(&path)?;
+
+    let no_dependencies = toml::Table::new();
+    let dependencies = match manifest.get("dependencies") {
+        Some(toml::Value::Table(dependencies)) => dependencies,
+        _ => &no_dependencies,
+    };
+
+    let mut dependency_vec = vec![];
+    for (name, spec) in dependencies {
+        let dependency = match spec {
+            toml::Value::String(version) => Dependency {
+                name: name.clone(),
+                version: version.clone(),
+                features: vec![],
+                default_features: true,
+                path: None,
+            },
+            toml::Value::Table(spec) => Dependency {
+                name: name.clone(),
+                version: spec
+                    .get("version")
+                    .and_then(toml::Value::as_str)
+                    .unwrap_or("*")
+                    .to_owned(),
+                features: spec
+                    .get("features")
+                    .and_then(toml::Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(toml::Value::as_str)
+                    .map(str::to_owned)
+                    .collect(),
+                default_features: spec
+                    .get("default-features")
+                    .and_then(toml::Value::as_bool)
+                    .unwrap_or(true),
+                path: None,
+            },
+            _ => {
+                let reason = format!("unexpected specification for {name}");
+                return Err(ErrorKind::Manifest(path, reason));
+            }
+        };
+        dependency_vec.push(dependency);
+    }
+
+    if let Some(library) = library(root, &manifest)? {
+        dependency_vec.push(library);
+    }
+
+    Ok(dependency_vec)
+}
+
+// The package of the root `Cargo.toml` as a dependency, if it has a library (at `[lib] path`,
+// or Cargo's default `src/lib.rs`)
+fn library(root: &Path, manifest: &toml::Table) -> Result<Option<Dependency>, ErrorKind> {
+    let lib = manifest
+        .get("lib")
+        .and_then(|l| l.get("path"))
+        .and_then(toml::Value::as_str)
+        .unwrap_or("src/lib.rs");
+    if !root.join(lib).exists() {
+        return Ok(None);
+    }
+    let package = manifest.get("package");
+    let field = |name: &str| {
+        package
+            .and_then(|p| p.get(name))
+            .and_then(toml::Value::as_str)
+    };
+    let (Some(name), Some(version)) = (field("name"), field("version")) else {
+        return Ok(None);
+    };
+
+    // Absolute, as the notebooks and the doctest crate live elsewhere
+    let path = root
+        .canonicalize()
+        .map_err(|e| ErrorKind::Io(root.to_path_buf(), e))?;
+    Ok(Some(Dependency {
+        name: name.to_owned(),
+        version: version.to_owned(),
+        features: vec![],
+        default_features: true,
+        path: Some(path),
+    }))
+}
+
+pub fn read_manifest(path: &Path) -> Result<toml::Table, ErrorKind> {
+    let content = fs::read_to_string(path).map_err(|e| ErrorKind::Io(path.to_path_buf(), e))?;
+    content.parse().map_err(|e: toml::de::Error| {
+        ErrorKind::Manifest(path.to_path_buf(), e.message().to_owned())
+    })
+}
@@ -0,0 +1,127 @@
+use crate::directive::Directive;
+use crate::error::{Error, ErrorKind};
+use crate::example::{ExampleIndex, STRUCTURAL_CHUNKS};
+use crate::list_files;
+use crate::references::check_references;
+use crate::summary::check_summary;
+use crate::versions::check_versions;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+// Report every broken directive, link and reference, unused chunk and malformed marker,
+// without writing anything
+pub fn check_book(root: &Path) -> Vec<Error> {
+    let mut errors = vec![];
+
+    let mut files = vec![];
+    if let Err(e) = list_files(&mut files, &root.join("src"), "md") {
+        errors.push(e);
+    }
+    files.sort();
+
+    let index = match ExampleIndex::load(root) {
+        Ok(index) => index,
+        Err(e) => {
+            errors.push(e);
+            ExampleIndex {
+                root: root.to_path_buf(),
+                examples: BTreeMap::new(),
+            }
+        }
+    };
+
+    // Every (program, chunk) pair referenced from the book
+    let mut used: HashSet<(String, String)> = HashSet::new();
+
+    for file in &files {
+        eprintln!("Checking: {}", file.display());
+        let content = match fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(e) => {
+                errors.push(Error::io(file, e));
+                continue;
+            }
+        };
+
+        for (i, line) in content.lines().enumerate() {
+            let directive = match Directive::parse(line) {
+                None => continue,
+                Some(Ok(directive)) => directive,
+                Some(Err(kind)) => {
+                    errors.push(Error::at(file, i + 1, line, kind));
+                    continue;
+                }
+            };
+
+            let program = directive.program();
+            let example = match index.get(program) {
+                Ok(example) => example,
+                Err(kind) => {
+                    errors.push(Error::at(file, i + 1, line, kind));
+                    continue;
+                }
+            };
+
+            for chunk in directive.chunks() {
+                match example.chunk(chunk) {
+                    Ok(_) => {
+                        used.insert((program.to_owned(), chunk.to_owned()));
+                    }
+                    Err(kind) => errors.push(Error::at(file, i + 1, line, kind)),
+                }
+            }
+        }
+    }
+
+    errors.extend(check_references(root, &files, &index));
+    errors.extend(check_summary(root, &index));
+    errors.extend(check_versions(root, &files, &index));
+
+    for example in index.examples.into_values() {
+        for chunk in &example.chunks {
+            let key = (example.name.clone(), chunk.name.clone());
+            if STRUCTURAL_CHUNKS.contains(&chunk.name.as_str()) || used.contains(&key) {
+                continue;
+            }
+            let kind = ErrorKind::UnusedChunk {
+                program: example.name.clone(),
+                chunk: chunk.name.clone(),
+            };
+            let marker = format!("// === {}", chunk.name);
+            errors.push(Error::at(&example.path, chunk.line, &marker, kind));
+        }
+        errors.extend(example.problems);
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_directives_of_unknown_examples() {
+        let root = std::env::temp_dir().join(format!("check_unknown_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("examples")).unwrap();
+        fs::write(
+            root.join("src/chapter.md"),
+            "# Chapter\n\n```rust\n=== Rust 1_1_1_missing block_1\n```\n",
+        )
+        .unwrap();
+
+        let errors = check_book(&root);
+        let unknown: Vec<_> = errors
+            .iter()
+            .filter(
+                |e| matches!(&e.kind, ErrorKind::UnknownExample(name) if name == "1_1_1_missing"),
+            )
+            .collect();
+        assert_eq!(unknown.len(), 1, "{errors:?}");
+        assert_eq!(unknown[0].line, Some(4));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use crate::error::ErrorKind;
+use std::ops::RangeInclusive;
+
+// A `=== Kind program chunk[+chunk...] [option=value...]` line in the markdown
+#[derive(Debug, Clone, PartialEq)]
+pub enum Directive {
+    Rust {
+        program: String,
+        chunks: Vec<String>,
+        options: ChunkOptions,
+    },
+    Output {
+        program: String,
+        chunk: String,
+    },
+}
+
+// How to cut down a code chunk, all line numbers are those shown without any option (from 1)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkOptions {
+    pub lines: Option<RangeInclusive<usize>>, // `lines=3..10`
+    pub collapse: Vec<RangeInclusive<usize>>, // `collapse=4..8,12`
+    pub hide: Vec<RangeInclusive<usize>>,     // `hide=1..2`
+}
+
+impl Directive {
+    // `None` if the line is not a directive at all
+    pub fn parse(line: &str) -> Option<Result<Directive, ErrorKind>> {
+        let rest = line.strip_prefix("=== ")?;
+        Some(parse_args(rest))
+    }
+
+    pub fn program(&self) -> &str {
+        match self {
+            Directive::Rust { program, .. } | Directive::Output { program, .. } => program,
+        }
+    }
+
+    pub fn chunks(&self) -> Vec<&str> {
+        match self {
+            Directive::Rust { chunks, .. } => chunks.iter().map(String::as_str).collect(),
+            Directive::Output { chunk, .. } => vec![chunk],
+        }
+    }
+}
+
+fn parse_args(rest: &str) -> Result<Directive, ErrorKind> {
+    let mut args = rest.split_whitespace();
+    let kind = args.next().unwrap_or_default();
+    let (Some(program), Some(chunks)) = (args.next(), args.next()) else {
+        return Err(malformed("expected `=== Kind program chunk`"));
+    };
+    let program = program.to_owned();
+
+    match kind {
+        "Rust" => {
+            let chunks: Vec<String> = chunks.split('+').map(str::to_owned).collect();
+            if chunks.iter().any(String::is_empty) {
+                return Err(malformed("empty chunk name"));
+            }
+
+            let mut options = ChunkOptions::default();
+            for arg in args {
+                let Some((key, value)) = arg.split_once('=') else {
+                    return Err(malformed(&format!(
+                        "expected `option=value`, found `{arg}`"
+                    )));
+                };
+                let ranges = parse_ranges(value)?;
+                match key {
+                    "lines" if ranges.len() == 1 => options.lines = ranges.into_iter().next(),
+                    "lines" => return Err(malformed("`lines` takes a single range")),
+                    "collapse" => options.collapse.extend(ranges),
+                    "hide" => options.hide.extend(ranges),
+                    _ => return Err(malformed(&format!("unknown option `{key}`"))),
+                }
+            }
+
+            Ok(Directive::Rust {
+                program,
+                chunks,
+                options,
+            })
+        }
+        "Output" => {
+            if let Some(extra) = args.next() {
+                return Err(malformed(&format!(
+                    "unexpected `{extra}` after the chunk name"
+                )));
+            }
+            if chunks.contains('+') {
+                return Err(malformed("Output takes a single chunk"));
+            }
+
+            Ok(Directive::Output {
+                program,
+                chunk: chunks.to_owned(),
+            })
+        }
+        _ => Err(malformed(&format!("unknown directive `{kind}`"))),
+    }
+}
+
+// `4`, `4..8` or a comma separated list of them
+fn parse_ranges(value: &str) -> Result<Vec<RangeInclusive<usize>>, ErrorKind> {
+    let mut ranges = vec![];
+    for range in value.split(',') {
+        let (start, end) = range.split_once("..").unwrap_or((range, range));
+        let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) else {
+            return Err(malformed(&format!("invalid line range `{range}`")));
+        };
+        if start == 0 || end < start {
+            return Err(malformed(&format!("invalid line range `{range}`")));
+        }
+        ranges.push(start..=end);
+    }
+
+    Ok(ranges)
+}
+
+fn malformed(reason: &str) -> ErrorKind {
+    ErrorKind::MalformedDirective(reason.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Directive, String> {
+        Directive::parse(line).unwrap().map_err(|e| match e {
+            ErrorKind::MalformedDirective(reason) => reason,
+            e => panic!("{e:?}"),
+        })
+    }
+
+    #[test]
+    fn parses_rust_directives() {
+        assert!(Directive::parse("Some text").is_none());
+        assert_eq!(
+            parse("=== Rust 3_4_1_pivot imports+block_2 lines=1..8 collapse=3..5,7 hide=1"),
+            Ok(Directive::Rust {
+                program: "3_4_1_pivot".to_owned(),
+                chunks: vec!["imports".to_owned(), "block_2".to_owned()],
+                options: ChunkOptions {
+                    lines: Some(1..=8),
+                    collapse: vec![3..=5, 7..=7],
+                    hide: vec![1..=1],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn parses_output_directives() {
+        assert_eq!(
+            parse("=== Output 4_1_1_summary block_3"),
+            Ok(Directive::Output {
+                program: "4_1_1_summary".to_owned(),
+                chunk: "block_3".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse("=== Output 4_1_1_summary block_1+block_2"),
+            Err("Output takes a single chunk".to_owned())
+        );
+        assert!(parse("=== Output 4_1_1_summary block_1 lines=1").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_directives() {
+        assert!(parse("=== Rust 3_4_1_pivot").is_err());
+        assert!(parse("=== Python 3_4_1_pivot block_1").is_err());
+        assert!(parse("=== Rust 3_4_1_pivot block_1+").is_err());
+        assert!(parse("=== Rust 3_4_1_pivot block_1 lines").is_err());
+        assert!(parse("=== Rust 3_4_1_pivot block_1 colour=1").is_err());
+        // Not supported: mdBook would ignore it
+        assert_eq!(
+            parse("=== Rust 3_4_1_pivot block_1 highlight=2"),
+            Err("unknown option `highlight`".to_owned())
+        );
+        assert!(parse("=== Rust 3_4_1_pivot block_1 lines=1..2,4").is_err());
+    }
+
+    #[test]
+    fn parses_line_ranges() {
+        assert_eq!(parse_ranges("4").unwrap(), [4..=4]);
+        assert_eq!(parse_ranges("4..8,10").unwrap(), [4..=8, 10..=10]);
+        for invalid in ["0", "8..4", "a", "1..", "", "1,,2"] {
+            assert!(parse_ranges(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::cargo::{read_dependencies, read_manifest};
+use crate::directive::Directive;
+use crate::error::{Error, ErrorKind};
+use crate::example::{Example, ExampleIndex};
+use crate::list_files;
+use crate::snippet::chunk_code;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// A code block of the book, as the reader sees it
+struct Snippet {
+    file: PathBuf,
+    line: usize,
+    directives: Vec<String>,
+    program: String, // The standalone Rust program
+}
+
+// Type-check every code block of the book as its own program, in a scratch crate
+pub fn check_snippets(root: &Path, scratch: &Path) -> Vec<Error> {
+    let mut errors = vec![];
+
+    let src = root.join("src");
+    let mut files = vec![];
+    if let Err(e) = list_files(&mut files, &src, "md") {
+        return vec![e];
+    }
+    files.sort();
+
+    let index = match ExampleIndex::load(root) {
+        Ok(index) => index,
+        Err(e) => return vec![e],
+    };
+
+    let mut snippets = vec![];
+    for file in files {
+        match chapter_snippets(&index, &file) {
+            Ok(chapter) => snippets.extend(chapter),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if let Err(e) = write_scratch_crate(root, scratch, &snippets) {
+        errors.push(e);
+        return errors;
+    }
+
+    eprintln!(
+        "Checking {} snippets in {}",
+        snippets.len(),
+        scratch.display()
+    );
+    let messages = match cargo_check(scratch) {
+        Ok(messages) => messages,
+        Err(e) => {
+            errors.push(e);
+            return errors;
+        }
+    };
+
+    for (i, snippet) in snippets.iter().enumerate() {
+        if let Some(message) = messages.get(&bin_name(i)) {
+            let kind = ErrorKind::DoctestFailed(message.clone());
+            let text = snippet.directives.join("\n    ");
+            errors.push(Error::at(&snippet.file, snippet.line, &text, kind));
+        }
+    }
+
+    errors
+}
+
+// Every code block of a chapter, each with the code shown before it from the same example
+fn chapter_snippets(index: &ExampleIndex, file: &Path) -> Result<Vec<Snippet>, Error> {
+    let content = fs::read_to_string(file).map_err(|e| Error::io(file, e))?;
+
+    let mut shown: HashMap<String, Vec<String>> = HashMap::new(); // Chunks shown so far, by example
+
+    let mut snippets = vec![];
+    let mut block: Vec<(usize, String, Directive)> = vec![];
+    let mut in_fence = false;
+
+    for (i, line) in content.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+
+        match Directive::parse(line) {
+            Some(directive) => {
+                let located = |kind| Error::at(file, i + 1, line, kind);
+                let directive = directive.map_err(located)?;
+                if matches!(directive, Directive::Rust { .. }) {
+                    block.push((i + 1, line.to_owned(), directive));
+                }
+                if in_fence {
+                    continue;
+                }
+            }
+            None if in_fence => continue,
+            None => {}
+        }
+
+        // The end of a code block (or a directive outside of one)
+        if block.is_empty() {
+            continue;
+        }
+
+        let (first_line, _, _) = block[0];
+        let mut programs: Vec<(String, Vec<String>)> = vec![]; // Directives of the block, by example
+        for (_, text, directive) in block.drain(..) {
+            let program = directive.program().to_owned();
+            let chunks = shown.entry(program.clone()).or_default();
+            for chunk in directive.chunks() {
+                if !chunks.iter().any(|c| c == chunk) {
+                    chunks.push(chunk.to_owned());
+                }
+            }
+            match programs.iter_mut().find(|(p, _)| *p == program) {
+                Some((_, texts)) => texts.push(text),
+                None => programs.push((program, vec![text])),
+            }
+        }
+
+        for (program, directives) in programs {
+            let located = |kind| Error::at(file, first_line, &directives.join("\n"), kind);
+            let example = index.get(&program).map_err(located)?;
+            let source = standalone_program(example, &shown[&program]).map_err(located)?;
+            snippets.push(Snippet {
+                file: file.to_path_buf(),
+                line: first_line,
+                directives,
+                program: source,
+            });
+        }
+    }
+
+    Ok(snippets)
+}
+
+// Put the top-level chunks first and the others inside the `main` of the example
+fn standalone_program(example: &Example, chunk_names: &[String]) -> Result<String, ErrorKind> {
+    let mut items = vec![];
+    let mut body = vec![];
+    for chunk_name in chunk_names {
+        let chunk = example.chunk(chunk_name)?;
+        if chunk.top_level {
+            items.push(chunk_code(chunk));
+        } else {
+            body.push(chunk_code(chunk));
+        }
+    }
+
+    // Keep the signature of the example (e.g. `async fn main`), if it has a `main` marker
+    let main = example
+        .chunk("main")
+        .map(chunk_code)
+        .unwrap_or_else(|_| "fn main() {".to_owned());
+
+    let body: Vec<String> = body
+        .join("\n")
+        .lines()
+        .map(|l| format!("    {l}"))
+        .collect();
+
+    Ok(format!(
+        "#![allow(unused)]\n{}\n\n{main}\n{}\n}}\n",
+        items.join("\n"),
+        body.join("\n")
+    ))
+}
+
+fn bin_name(i: usize) -> String {
+    format!("snippet_{i:03}")
+}
+
+// A crate with the dependencies of the examples and one binary per snippet
+fn write_scratch_crate(root: &Path, scratch: &Path, snippets: &[Snippet]) -> Result<(), Error> {
+    let manifest_path = root.join("Cargo.toml");
+    let manifest =
+        read_manifest(&manifest_path).map_err(|kind| Error::file(&manifest_path, kind))?;
+
+    let mut scratch_manifest = toml::Table::new();
+    let mut package = toml::Table::new();
+    package.insert("name".to_owned(), "book_doctest".into());
+    package.insert("version".to_owned(), "0.0.0".into());
+    package.insert("edition".to_owned(), "2024".into());
+    package.insert("publish".to_owned(), false.into());
+    scratch_manifest.insert("package".to_owned(), package.into());
+    scratch_manifest.insert("workspace".to_owned(), toml::Table::new().into());
+    let mut dependencies = match manifest.get("dependencies") {
+        Some(toml::Value::Table(dependencies)) => dependencies.clone(),
+        _ => toml::Table::new(),
+    };
+
+    // The library of the book, which the snippets use like the examples do
+    let crates = read_dependencies(root).map_err(|kind| Error::file(&manifest_path, kind))?;
+    for dependency in crates {
+        if let Some(path) = dependency.path {
+            let mut spec = toml::Table::new();
+            spec.insert("path".to_owned(), path.display().to_string().into());
+            dependencies.insert(dependency.name, spec.into());
+        }
+    }
+    scratch_manifest.insert("dependencies".to_owned(), dependencies.into());
+
+    let bin = scratch.join("src").join("bin");
+    let _ = fs::remove_dir_all(&bin); // Snippets from a previous run
+    fs::create_dir_all(&bin).map_err(|e| Error::io(&bin, e))?;
+
+    let scratch_manifest_path = scratch.join("Cargo.toml");
+    let scratch_manifest = toml::to_string(&scratch_manifest).expect("a manifest is always valid");
+    fs::write(&scratch_manifest_path, scratch_manifest)
+        .map_err(|e| Error::io(&scratch_manifest_path, e))?;
+
+    // Use the same versions as the examples
+    let lock = root.join("Cargo.lock");
+    if lock.exists() {
+        let scratch_lock = scratch.join("Cargo.lock");
+        fs::copy(&lock, &scratch_lock).map_err(|e| Error::io(&scratch_lock, e))?;
+    }
+
+    for (i, snippet) in snippets.iter().enumerate() {
+        let path = bin.join(format!("{}.rs", bin_name(i)));
+        fs::write(&path, &snippet.program).map_err(|e| Error::io(&path, e))?;
+    }
+
+    Ok(())
+}
+
+// The rendered compiler errors, by binary
+fn cargo_check(scratch: &Path) -> Result<HashMap<String, String>, Error> {
+    let output = Command::new("cargo")
+        .args(["check", "--bins", "--keep-going", "--message-format=json"])
+        .current_dir(scratch)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| Error::io(Path::new("cargo"), e))?;
+
+    let mut messages: HashMap<String, String> = HashMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Ok(message) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        if message["reason"] != "compiler-message" || message["message"]["level"] != "error" {
+            continue;
+        }
+        let (Some(target), Some(rendered)) = (
+            message["target"]["name"].as_str(),
+            message["message"]["rendered"].as_str(),
+        ) else {
+            continue;
+        };
+        messages
+            .entry(target.to_owned())
+            .or_default()
+            .push_str(rendered);
+    }
+
+    // Cargo itself failed (e.g. a dependency could not be resolved)
+    if !output.status.success() && messages.is_empty() {
+        let kind = ErrorKind::DoctestFailed("cargo check failed".to_owned());
+        return Err(Error::file(&scratch.join("Cargo.toml"), kind));
+    }
+
+    Ok(messages)
+}
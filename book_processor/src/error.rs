@@ -0,0 +1,118 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Everything that can go wrong while processing the book
+#[derive(Debug)]
+pub enum ErrorKind {
+    Io(PathBuf, io::Error),
+    MalformedDirective(String),
+    MalformedMarker(String),
+    MissingChunk { program: String, chunk: String },
+    MissingOutput { program: String, chunk: String },
+    ExampleFailed(String),
+    UnusedChunk { program: String, chunk: String },
+    Protocol(String),
+    Manifest(PathBuf, String),
+    DoctestFailed(String),
+    BrokenLink(String),
+    UnknownExample(String),
+    DanglingDataPath { program: String, path: String },
+    OutOfDate,
+    MisnumberedExample { program: String, chapter: String },
+    VersionDrift(String),
+    UnknownVersion(String),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Io(path, err) => write!(f, "could not access {}: {err}", path.display()),
+            ErrorKind::MalformedDirective(reason) => write!(f, "malformed directive: {reason}"),
+            ErrorKind::MalformedMarker(reason) => write!(f, "malformed marker: {reason}"),
+            ErrorKind::MissingChunk { program, chunk } => {
+                write!(f, "no chunk called {chunk} in {program}")
+            }
+            ErrorKind::MissingOutput { program, chunk } => {
+                write!(f, "no output for chunk {chunk} in {program}")
+            }
+            ErrorKind::ExampleFailed(program) => write!(f, "example {program} failed to run"),
+            ErrorKind::UnusedChunk { program, chunk } => {
+                write!(f, "chunk {chunk} in {program} is never used in the book")
+            }
+            ErrorKind::Protocol(reason) => write!(f, "invalid mdBook input: {reason}"),
+            ErrorKind::DoctestFailed(message) => write!(f, "snippet does not compile\n{message}"),
+            ErrorKind::Manifest(path, reason) => {
+                write!(f, "could not read {}: {reason}", path.display())
+            }
+            ErrorKind::BrokenLink(target) => write!(f, "broken link to {target}"),
+            ErrorKind::UnknownExample(name) => write!(f, "no example called {name}"),
+            ErrorKind::DanglingDataPath { program, path } => {
+                write!(
+                    f,
+                    "{program} reads {path}, which no earlier example produces"
+                )
+            }
+            ErrorKind::OutOfDate => write!(f, "out of date, run `just summary`"),
+            ErrorKind::MisnumberedExample { program, chapter } => {
+                write!(f, "{program} is used in {chapter}, from another section")
+            }
+            ErrorKind::VersionDrift(reason) => write!(f, "version drift: {reason}"),
+            ErrorKind::UnknownVersion(reason) => write!(f, "unknown version: {reason}"),
+        }
+    }
+}
+
+// An error with the location (file, line and text) that caused it
+#[derive(Debug)]
+pub struct Error {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub text: Option<String>,
+    pub kind: ErrorKind,
+}
+
+impl Error {
+    pub fn at(path: &Path, line: usize, text: &str, kind: ErrorKind) -> Error {
+        Error {
+            path: path.to_path_buf(),
+            line: Some(line),
+            text: Some(text.trim().to_owned()),
+            kind,
+        }
+    }
+
+    pub fn file(path: &Path, kind: ErrorKind) -> Error {
+        Error {
+            path: path.to_path_buf(),
+            line: None,
+            text: None,
+            kind,
+        }
+    }
+
+    pub fn io(path: &Path, err: io::Error) -> Error {
+        Error {
+            path: path.to_path_buf(),
+            line: None,
+            text: None,
+            kind: ErrorKind::Io(path.to_path_buf(), err),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ERROR: {}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        write!(f, ": {}", self.kind)?;
+        if let Some(text) = &self.text {
+            write!(f, "\n    {text}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
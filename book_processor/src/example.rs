@@ -0,0 +1,180 @@
+use crate::error::{Error, ErrorKind};
+use crate::list_files;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Copies of the examples made while capturing their output
+pub const TEMPORARY_PREFIX: &str = "book_output_";
+
+// Markers that structure the examples, but are not meant to be shown in the book
+pub const STRUCTURAL_CHUNKS: [&str; 2] = ["main", "end"];
+
+// The code between a `// === name` marker and the next marker
+#[derive(Debug)]
+pub struct Chunk {
+    pub name: String,
+    pub line: usize,
+    pub top_level: bool, // Outside of any function, like `imports`
+    pub lines: Vec<String>,
+}
+
+// An example split into its chunks
+#[derive(Debug)]
+pub struct Example {
+    pub name: String,
+    pub path: PathBuf,
+    pub source: String,
+    pub hash: String, // SHA-256 of the source
+    pub chunks: Vec<Chunk>,
+    pub problems: Vec<Error>,
+}
+
+// Every example of the repository, parsed once
+#[derive(Debug)]
+pub struct ExampleIndex {
+    pub root: PathBuf,
+    pub examples: BTreeMap<String, Example>,
+}
+
+impl ExampleIndex {
+    pub fn load(root: &Path) -> Result<ExampleIndex, Error> {
+        let mut files = vec![];
+        list_files(&mut files, &root.join("examples"), "rs")?;
+
+        let mut examples = BTreeMap::new();
+        for file in files {
+            let Some(name) = file.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if name.starts_with(TEMPORARY_PREFIX) {
+                continue;
+            }
+            let example = Example::read(root, name).map_err(|kind| Error::file(&file, kind))?;
+            examples.insert(name.to_owned(), example);
+        }
+
+        Ok(ExampleIndex {
+            root: root.to_path_buf(),
+            examples,
+        })
+    }
+
+    pub fn get(&self, program_path: &str) -> Result<&Example, ErrorKind> {
+        self.examples
+            .get(program_path)
+            .ok_or_else(|| ErrorKind::UnknownExample(program_path.to_owned()))
+    }
+}
+
+impl Example {
+    pub fn read(root: &Path, program_path: &str) -> Result<Example, ErrorKind> {
+        let path = example_path(root, program_path);
+        let code = fs::read_to_string(&path).map_err(|e| ErrorKind::Io(path.clone(), e))?;
+
+        Ok(Example::parse(program_path, path, code))
+    }
+
+    pub fn parse(program_path: &str, path: PathBuf, code: String) -> Example {
+        let mut chunks: Vec<Chunk> = vec![];
+        let mut problems = vec![];
+
+        for (i, line) in code.lines().enumerate() {
+            let line_number = i + 1;
+            match parse_marker(line) {
+                Some(Ok(name)) => {
+                    if chunks.iter().any(|c| c.name == name) {
+                        let reason = format!("chunk {name} is defined more than once");
+                        problems.push(Error::at(
+                            &path,
+                            line_number,
+                            line,
+                            ErrorKind::MalformedMarker(reason),
+                        ));
+                    }
+                    chunks.push(Chunk {
+                        name,
+                        line: line_number,
+                        top_level: !line.starts_with(char::is_whitespace),
+                        lines: vec![],
+                    });
+                }
+                Some(Err(kind)) => problems.push(Error::at(&path, line_number, line, kind)),
+                None => {
+                    if let Some(chunk) = chunks.last_mut() {
+                        chunk.lines.push(line.to_owned());
+                    }
+                }
+            }
+        }
+
+        Example {
+            name: program_path.to_owned(),
+            path,
+            hash: format!("{:x}", Sha256::digest(code.as_bytes())),
+            source: code,
+            chunks,
+            problems,
+        }
+    }
+
+    pub fn chunk(&self, chunk_name: &str) -> Result<&Chunk, ErrorKind> {
+        self.chunks
+            .iter()
+            .find(|c| c.name == chunk_name)
+            .ok_or_else(|| ErrorKind::MissingChunk {
+                program: self.name.clone(),
+                chunk: chunk_name.to_owned(),
+            })
+    }
+}
+
+pub fn example_path(root: &Path, program_path: &str) -> PathBuf {
+    root.join("examples").join(format!("{program_path}.rs"))
+}
+
+// `None` if the line is not a marker, the chunk name if it is a valid one
+fn parse_marker(line: &str) -> Option<Result<String, ErrorKind>> {
+    let comment = line.trim_start().strip_prefix("//")?;
+    let rest = comment.trim_start().strip_prefix("===")?;
+
+    if !comment.starts_with(" === ") {
+        return Some(Err(ErrorKind::MalformedMarker(
+            "expected `// === name`".to_owned(),
+        )));
+    }
+
+    let mut names = rest.split_whitespace();
+    match (names.next(), names.next()) {
+        (Some(name), None) => Some(Ok(name.to_owned())),
+        (None, _) => Some(Err(ErrorKind::MalformedMarker(
+            "missing chunk name".to_owned(),
+        ))),
+        (Some(_), Some(extra)) => Some(Err(ErrorKind::MalformedMarker(format!(
+            "unexpected `{extra}` after the chunk name"
+        )))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_an_unknown_example_by_name() {
+        let root = PathBuf::from("/book");
+        let code = "// === main\nfn main() {\n    // === block_1\n}\n".to_owned();
+        let example = Example::parse("1_1_1_hello", example_path(&root, "1_1_1_hello"), code);
+        let index = ExampleIndex {
+            root,
+            examples: BTreeMap::from([("1_1_1_hello".to_owned(), example)]),
+        };
+
+        assert!(index.get("1_1_1_hello").is_ok());
+        assert!(matches!(
+            index.get("1_1_2_missing"),
+            Err(ErrorKind::UnknownExample(name)) if name == "1_1_2_missing"
+        ));
+    }
+}
@@ -0,0 +1,595 @@
+use crate::cargo::read_manifest;
+use crate::error::{Error, ErrorKind};
+use crate::example::ExampleIndex;
+use crate::process_chapter;
+use crate::references::{heading_id, normalize};
+use crate::versions::{Versions, expand_versions};
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd, html};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+// Where the images of the chapters are copied, next to the markdown and inside the EPUB
+const ASSETS: &str = "assets";
+
+// A chapter listed in `SUMMARY.md`, once its directives are resolved
+struct Chapter {
+    depth: usize, // 0 for top-level entries
+    title: String,
+    file: PathBuf,
+    markdown: String,
+    anchor: String, // The id of its first heading
+}
+
+// What `book.toml` says about the book
+struct BookInfo {
+    title: String,
+    authors: Vec<String>,
+    language: String,
+}
+
+// Write the whole book as a single markdown document and as an EPUB, for offline reading
+pub fn export_book(root: &Path, out: &Path) -> Result<(), Error> {
+    let info = book_info(root)?;
+    let src = root.join("src");
+    let index = ExampleIndex::load(root)?;
+    let versions = Versions::load(root)?;
+
+    let mut chapters = summary_chapters(&src)?;
+    for chapter in &mut chapters {
+        let content = fs::read_to_string(&chapter.file).map_err(|e| Error::io(&chapter.file, e))?;
+        let content = expand_versions(&chapter.file, &content, &versions)?;
+        let (output, _) = process_chapter(&index, &chapter.file, &content)?;
+        chapter.markdown = plain_code_blocks(&output);
+        chapter.anchor = first_heading_id(&chapter.markdown);
+    }
+
+    fs::create_dir_all(out).map_err(|e| Error::io(out, e))?;
+
+    // Images, by source file, with their path in the output
+    let mut assets: BTreeMap<PathBuf, String> = BTreeMap::new();
+
+    // Links to other chapters become links within the document
+    let markdown: Vec<String> = chapters
+        .iter()
+        .map(|c| {
+            rewrite_targets(c, &chapters, &src, &mut assets, |_, anchor| {
+                format!("#{anchor}")
+            })
+        })
+        .collect();
+    let markdown_path = out.join("book.md");
+    eprintln!("Writing: {}", markdown_path.display());
+    fs::write(&markdown_path, markdown.join("\n\n")).map_err(|e| Error::io(&markdown_path, e))?;
+
+    for (source, asset) in &assets {
+        let target = out.join(asset);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        fs::copy(source, &target).map_err(|e| Error::io(source, e))?;
+    }
+
+    // Links to other chapters point to their own XHTML file
+    let xhtml: Vec<String> = chapters
+        .iter()
+        .map(|c| {
+            let markdown = rewrite_targets(c, &chapters, &src, &mut assets, |i, anchor| {
+                format!("{}#{anchor}", chapter_file(i))
+            });
+            xhtml_page(&c.title, &info.language, &markdown_to_xhtml(&markdown))
+        })
+        .collect();
+    let epub_path = out.join("book.epub");
+    eprintln!("Writing: {}", epub_path.display());
+    write_epub(&epub_path, &info, &chapters, &xhtml, &assets)
+}
+
+fn book_info(root: &Path) -> Result<BookInfo, Error> {
+    let path = root.join("book.toml");
+    let config = read_manifest(&path).map_err(|kind| Error::file(&path, kind))?;
+    let book = config.get("book").and_then(toml::Value::as_table);
+    let field = |name: &str| book.and_then(|b| b.get(name));
+
+    Ok(BookInfo {
+        title: field("title")
+            .and_then(toml::Value::as_str)
+            .unwrap_or("Untitled")
+            .to_owned(),
+        authors: field("authors")
+            .and_then(toml::Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(toml::Value::as_str)
+            .map(str::to_owned)
+            .collect(),
+        language: field("language")
+            .and_then(toml::Value::as_str)
+            .unwrap_or("en")
+            .to_owned(),
+    })
+}
+
+// The chapters of `SUMMARY.md`, in order, skipping drafts (`[Title]()`)
+fn summary_chapters(src: &Path) -> Result<Vec<Chapter>, Error> {
+    let summary = src.join("SUMMARY.md");
+    let content = fs::read_to_string(&summary).map_err(|e| Error::io(&summary, e))?;
+
+    let mut chapters = vec![];
+    for (i, line) in content.lines().enumerate() {
+        let Some((title, rest)) = line.split_once("](") else {
+            continue;
+        };
+        let Some(title) = title.split_once('[').map(|(_, t)| t) else {
+            continue;
+        };
+        let Some((path, _)) = rest.split_once(')') else {
+            let kind = ErrorKind::Protocol("unclosed link in the summary".to_owned());
+            return Err(Error::at(&summary, i + 1, line, kind));
+        };
+        if path.is_empty() {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        chapters.push(Chapter {
+            depth: indent / 4,
+            title: title.to_owned(),
+            file: src.join(path),
+            markdown: String::new(),
+            anchor: String::new(),
+        });
+    }
+
+    Ok(chapters)
+}
+
+// Code blocks as a reader sees them: without hidden lines or mdBook annotations
+fn plain_code_blocks(markdown: &str) -> String {
+    let mut lines = vec![];
+    let mut hidden: Option<Option<String>> = None; // Inside a block, with its hidden line prefix
+
+    for line in markdown.lines() {
+        let content = line.trim_start();
+        if content.starts_with("```") {
+            match hidden {
+                Some(_) => hidden = None,
+                None => {
+                    let info = content.trim_start_matches('`');
+                    let mut annotations = info.split(',');
+                    let language = annotations.next().unwrap_or_default().trim();
+                    let prefix = annotations
+                        .find_map(|a| a.trim().strip_prefix("hidelines="))
+                        .or((language == "rust").then_some("#"));
+                    hidden = Some(prefix.map(str::to_owned));
+
+                    let fence = &line[..line.len() - info.len()];
+                    lines.push(format!("{fence}{language}"));
+                    continue;
+                }
+            }
+        } else if let Some(Some(prefix)) = &hidden {
+            // mdBook hides `# code` and `#` but keeps `#[attribute]`
+            let rest = content.strip_prefix(prefix.as_str());
+            if rest.is_some_and(|r| r.is_empty() || r.starts_with(' ') || prefix != "#") {
+                continue;
+            }
+        }
+        lines.push(line.to_owned());
+    }
+
+    lines.join("\n")
+}
+
+fn first_heading_id(markdown: &str) -> String {
+    let mut in_fence = false;
+    for line in markdown.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        if !in_fence && let Some(heading) = line.strip_prefix('#') {
+            return heading_id(&[], heading.trim_start_matches('#'));
+        }
+    }
+    String::new()
+}
+
+// Point links to chapters with `link(chapter, anchor)` and images to their copy in the assets
+fn rewrite_targets(
+    chapter: &Chapter,
+    chapters: &[Chapter],
+    src: &Path,
+    assets: &mut BTreeMap<PathBuf, String>,
+    link: impl Fn(usize, &str) -> String,
+) -> String {
+    let dir = chapter.file.parent().unwrap_or(Path::new(""));
+
+    let mut rewrite = |target: &str| -> Option<String> {
+        if target.contains("://") || target.starts_with("mailto:") {
+            return None;
+        }
+        let (path, anchor) = target.split_once('#').unwrap_or((target, ""));
+        let linked = match path {
+            "" => chapter.file.clone(),
+            _ => normalize(&dir.join(path)),
+        };
+
+        if let Some(i) = chapters.iter().position(|c| normalize(&c.file) == linked) {
+            let anchor = if anchor.is_empty() {
+                &chapters[i].anchor
+            } else {
+                anchor
+            };
+            return Some(link(i, anchor));
+        }
+
+        if media_type(&linked).is_some() && linked.is_file() {
+            let relative = linked.strip_prefix(normalize(src)).unwrap_or(&linked);
+            let asset = Path::new(ASSETS).join(relative);
+            let asset = asset.to_string_lossy().replace('\\', "/");
+            assets.insert(linked, asset.clone());
+            return Some(asset);
+        }
+
+        None
+    };
+
+    let mut lines = vec![];
+    let mut in_fence = false;
+    for line in chapter.markdown.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        if in_fence {
+            lines.push(line.to_owned());
+            continue;
+        }
+
+        // Only the `](target)` outside of `inline code`
+        let mut rewritten = String::new();
+        let mut rest = line;
+        let mut in_code = false;
+        while let Some(i) = rest.find(['`', ']']) {
+            let (before, after) = rest.split_at(i);
+            rewritten.push_str(before);
+            if after.starts_with('`') {
+                in_code = !in_code;
+            } else if !in_code
+                && let Some(target_start) = after.strip_prefix("](")
+                && let Some(end) = target_start.find(')')
+            {
+                let target = &target_start[..end];
+                let (target, title) = target.split_at(target.find(' ').unwrap_or(target.len()));
+                let target = rewrite(target).unwrap_or_else(|| target.to_owned());
+                rewritten.push_str(&format!("]({target}{title})"));
+                rest = &target_start[end + 1..];
+                continue;
+            }
+            rewritten.push_str(&after[..1]);
+            rest = &after[1..];
+        }
+        rewritten.push_str(rest);
+        lines.push(rewritten);
+    }
+
+    lines.join("\n")
+}
+
+// The media types EPUB readers must support, for the images the chapters use
+fn media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+fn chapter_file(i: usize) -> String {
+    format!("chapter_{:03}.xhtml", i + 1)
+}
+
+// The body of an XHTML page, with the same heading ids as mdBook
+fn markdown_to_xhtml(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut events: Vec<Event> = Parser::new_ext(markdown, options)
+        .filter_map(|event| match event {
+            // Raw HTML blocks (e.g. interactive plots) need a browser and are rarely valid XHTML
+            Event::Html(_) => None,
+            Event::InlineHtml(text) => Some(Event::Text(text)),
+            event => Some(event),
+        })
+        .collect();
+
+    let mut ids: Vec<String> = vec![];
+    for i in 0..events.len() {
+        if !matches!(events[i], Event::Start(Tag::Heading { id: None, .. })) {
+            continue;
+        }
+        let text: String = events[i + 1..]
+            .iter()
+            .take_while(|e| !matches!(e, Event::End(TagEnd::Heading(_))))
+            .filter_map(|e| match e {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+        let id = heading_id(&ids, &text);
+        if let Event::Start(Tag::Heading { id: heading, .. }) = &mut events[i] {
+            *heading = Some(id.clone().into());
+        }
+        ids.push(id);
+    }
+
+    let mut body = String::new();
+    html::push_html(&mut body, events.into_iter());
+    body
+}
+
+fn xhtml_page(title: &str, language: &str, body: &str) -> String {
+    let title = escape(title);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{language}" lang="{language}">
+<head>
+<title>{title}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{body}</body>
+</html>
+"#
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// An EPUB 3 package: the uncompressed `mimetype` first, then the container, package
+// document, navigation, chapters and images
+fn write_epub(
+    path: &Path,
+    info: &BookInfo,
+    chapters: &[Chapter],
+    xhtml: &[String],
+    assets: &BTreeMap<PathBuf, String>,
+) -> Result<(), Error> {
+    let file = File::create(path).map_err(|e| Error::io(path, e))?;
+    let mut epub = ZipWriter::new(file);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut entries: Vec<(String, Vec<u8>, SimpleFileOptions)> = vec![
+        (
+            "mimetype".to_owned(),
+            b"application/epub+zip".to_vec(),
+            stored,
+        ),
+        (
+            "META-INF/container.xml".to_owned(),
+            CONTAINER.as_bytes().to_vec(),
+            deflated,
+        ),
+        (
+            "OEBPS/content.opf".to_owned(),
+            package_document(info, chapters, xhtml, assets).into_bytes(),
+            deflated,
+        ),
+        (
+            "OEBPS/nav.xhtml".to_owned(),
+            navigation(info, chapters).into_bytes(),
+            deflated,
+        ),
+        (
+            "OEBPS/style.css".to_owned(),
+            STYLE.as_bytes().to_vec(),
+            deflated,
+        ),
+    ];
+    for (i, page) in xhtml.iter().enumerate() {
+        let name = format!("OEBPS/{}", chapter_file(i));
+        entries.push((name, page.clone().into_bytes(), deflated));
+    }
+    for (source, asset) in assets {
+        let bytes = fs::read(source).map_err(|e| Error::io(source, e))?;
+        entries.push((format!("OEBPS/{asset}"), bytes, deflated));
+    }
+
+    let zip_error = |e: zip::result::ZipError| Error::io(path, e.into());
+    for (name, bytes, options) in entries {
+        epub.start_file(name, options).map_err(zip_error)?;
+        epub.write_all(&bytes).map_err(|e| Error::io(path, e))?;
+    }
+    epub.finish().map_err(zip_error)?;
+
+    Ok(())
+}
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#;
+
+const STYLE: &str = "body { font-family: serif; line-height: 1.4; }
+pre { white-space: pre-wrap; font-size: 0.85em; background: #f6f7f6; padding: 0.5em; }
+code { font-family: monospace; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.5em; }
+img { max-width: 100%; }
+";
+
+fn package_document(
+    info: &BookInfo,
+    chapters: &[Chapter],
+    xhtml: &[String],
+    assets: &BTreeMap<PathBuf, String>,
+) -> String {
+    let mut metadata = vec![
+        format!(
+            r#"<dc:identifier id="book-id">urn:uuid:{}</dc:identifier>"#,
+            identifier(xhtml)
+        ),
+        format!("<dc:title>{}</dc:title>", escape(&info.title)),
+        format!("<dc:language>{}</dc:language>", escape(&info.language)),
+        format!(r#"<meta property="dcterms:modified">{}</meta>"#, modified()),
+    ];
+    for author in &info.authors {
+        metadata.push(format!("<dc:creator>{}</dc:creator>", escape(author)));
+    }
+
+    let mut manifest = vec![
+        r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#
+            .to_owned(),
+        r#"<item id="style" href="style.css" media-type="text/css"/>"#.to_owned(),
+    ];
+    let mut spine = vec![];
+    for i in 0..chapters.len() {
+        let file = chapter_file(i);
+        let id = file.trim_end_matches(".xhtml");
+        manifest.push(format!(
+            r#"<item id="{id}" href="{file}" media-type="application/xhtml+xml"/>"#
+        ));
+        spine.push(format!(r#"<itemref idref="{id}"/>"#));
+    }
+    for (i, asset) in assets.values().enumerate() {
+        let media_type = media_type(Path::new(asset)).unwrap_or("application/octet-stream");
+        manifest.push(format!(
+            r#"<item id="asset_{i}" href="{}" media-type="{media_type}"/>"#,
+            escape(asset)
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="{}">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{}
+</metadata>
+<manifest>
+{}
+</manifest>
+<spine>
+{}
+</spine>
+</package>
+"#,
+        escape(&info.language),
+        metadata.join("\n"),
+        manifest.join("\n"),
+        spine.join("\n")
+    )
+}
+
+// The table of contents, nested like `SUMMARY.md`
+fn navigation(info: &BookInfo, chapters: &[Chapter]) -> String {
+    let mut list = String::new();
+    let mut open = 0; // Lists opened so far
+    for (i, chapter) in chapters.iter().enumerate() {
+        let level = (chapter.depth + 1).min(open + 1);
+        if level > open {
+            list.push_str("<ol>\n");
+            open += 1;
+        } else {
+            list.push_str("</li>\n");
+            while open > level {
+                list.push_str("</ol>\n</li>\n");
+                open -= 1;
+            }
+        }
+        list.push_str(&format!(
+            r#"<li><a href="{}">{}</a>"#,
+            chapter_file(i),
+            escape(&chapter.title)
+        ));
+    }
+    if open > 0 {
+        list.push_str("</li>\n");
+    }
+    while open > 0 {
+        list.push_str("</ol>\n");
+        open -= 1;
+        if open > 0 {
+            list.push_str("</li>\n");
+        }
+    }
+
+    let body = format!(
+        r#"<nav epub:type="toc" id="toc">
+<h1>{}</h1>
+{list}</nav>
+"#,
+        escape(&info.title)
+    );
+    xhtml_page(&info.title, &info.language, &body)
+}
+
+// A UUID derived from the content, so that the same book keeps the same identifier
+fn identifier(xhtml: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for page in xhtml {
+        hasher.update(page.as_bytes());
+    }
+    let mut bytes: Vec<u8> = hasher.finalize()[..16].to_vec();
+    bytes[6] = (bytes[6] & 0x0f) | 0x50; // Name-based version
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+// `SOURCE_DATE_EPOCH` (for reproducible builds) or now, as `2024-01-31T12:00:00Z`
+fn modified() -> String {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    let (year, month, day) = civil_date(seconds / 86_400);
+    let time = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+// The date of a number of days since 1970-01-01 (Howard Hinnant's `civil_from_days`)
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
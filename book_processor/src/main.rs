@@ -1,85 +1,132 @@
+mod cargo;
+mod check;
+mod directive;
+mod doctest;
+mod error;
+mod example;
+mod export;
+mod manifest;
+mod notebook;
+mod output;
+mod preprocessor;
+mod references;
+mod snippet;
+mod summary;
+mod versions;
+
+use directive::Directive;
+use error::Error;
+use example::ExampleIndex;
+use std::collections::BTreeSet;
 use std::fs;
-use std::fs::File;
-use std::fs::metadata;
-use std::io::prelude::*;
 use std::path::Path;
 use std::path::PathBuf;
-
-fn main() {
-    let path = Path::new("../src_processed/");
-    let mut files = vec![];
-
-    list_files(&mut files, path, "md");
-
-    for file in files {
-        println!("Processing: {}", file.display());
-        let content = fs::read_to_string(&file).unwrap();
-
-        let split_text: Vec<&str> = content.split("=== Rust ").collect();
-
-        let mut output: Vec<String> = vec![];
-        output.push(split_text[0].trim().to_string()); // First chunk
-        for chunk in split_text.into_iter().skip(1) {
-            // All other chunks but first
-            let prog = chunk.split(" ").next().unwrap();
-            let name = chunk.split([' ', '\n']).nth(1).unwrap();
-
-            let code = code_chunk(prog, name); // Fetch code chunk
-
-            output.push(code.trim().to_string());
-
-            let fixed_chunk: Vec<&str> = chunk.split('\n').skip(1).collect(); // Don't print commands
-
-            output.push(fixed_chunk.join("\n").trim().to_string());
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let errors = match args.first().map(String::as_str) {
+        // mdBook asks if a renderer is supported, the directives are rendered the same for all
+        Some("supports") => return ExitCode::SUCCESS,
+        Some("--check") => check::check_book(Path::new(".")),
+        Some("doctest") => {
+            let scratch = Path::new(args.get(1).map_or("target/book_doctest", String::as_str));
+            doctest::check_snippets(Path::new("."), scratch)
         }
+        Some("notebook") => {
+            let out = Path::new(args.get(1).map_or("notebooks", String::as_str));
+            notebook::write_notebooks(Path::new("."), out)
+                .err()
+                .into_iter()
+                .collect()
+        }
+        Some("export") => {
+            let out = Path::new(args.get(1).map_or("target/book_export", String::as_str));
+            export::export_book(Path::new("."), out)
+                .err()
+                .into_iter()
+                .collect()
+        }
+        Some("summary") => summary::write_summary(Path::new("."))
+            .err()
+            .into_iter()
+            .collect(),
+        _ => preprocessor::run().err().into_iter().collect(),
+    };
+
+    if errors.is_empty() {
+        return ExitCode::SUCCESS;
+    }
 
-        let output: String = output.join("\n");
-
-        let mut file = File::create(file).unwrap();
-        file.write_all(output.as_bytes()).unwrap();
+    for error in &errors {
+        eprintln!("{error}");
     }
+    eprintln!("Found {} problem(s)", errors.len());
+    ExitCode::FAILURE
 }
 
-fn list_files(file_vec: &mut Vec<PathBuf>, path: &Path, ext: &str) {
-    if metadata(path).unwrap().is_dir() {
-        let paths = fs::read_dir(path).unwrap();
-        for path_result in paths {
-            let full_path = path_result.unwrap().path();
-            if metadata(&full_path).unwrap().is_dir() {
-                list_files(file_vec, &full_path, ext);
-            } else if full_path.extension().unwrap() == ext {
-                file_vec.push(full_path);
+// Replace every directive in a chapter by the code or output it points to, and list the examples used
+fn process_chapter(
+    index: &ExampleIndex,
+    file: &Path,
+    content: &str,
+) -> Result<(String, BTreeSet<String>), Error> {
+    eprintln!("Processing: {}", file.display());
+
+    let mut programs = BTreeSet::new();
+    let mut output: Vec<String> = vec![];
+    let mut fence: Option<usize> = None; // Opening line of the current code block
+    for (i, line) in content.lines().enumerate() {
+        let Some(directive) = Directive::parse(line) else {
+            if line.trim_start().starts_with("```") {
+                fence = match fence {
+                    Some(_) => None,
+                    None => Some(output.len()),
+                };
+            }
+            output.push(line.to_string());
+            continue;
+        };
+
+        let located = |kind| Error::at(file, i + 1, line, kind);
+        let directive = directive.map_err(located)?;
+        let example = index.get(directive.program()).map_err(located)?;
+        programs.insert(example.name.clone());
+
+        match directive {
+            Directive::Rust {
+                chunks, options, ..
+            } => {
+                let snippet = snippet::code_snippet(example, &chunks, &options).map_err(located)?; // Fetch code chunks
+                if let Some(fence) = fence {
+                    snippet::annotate_fence(&mut output[fence], &snippet);
+                }
+                output.extend(snippet.lines);
+            }
+            Directive::Output { chunk, .. } => {
+                let printed =
+                    output::output_chunk(&index.root, example, &chunk).map_err(located)?; // Fetch captured output
+                output.push(format!("```text\n{printed}\n```"));
             }
         }
     }
-}
-
-fn code_chunk(program_path: &str, chunk_name: &str) -> String {
-    let path = format!("../examples/{program_path}.rs");
-    println!(" ↳ Fetching: {path}");
-    let code: String = fs::read_to_string(path).unwrap();
 
-    let split_text: Vec<&str> = code.split("=== ").collect();
+    Ok((output.join("\n"), programs))
+}
 
-    for chunk in split_text.into_iter().skip(1) {
-        let name = chunk.split([' ', '\n']).next().unwrap();
-        if name == chunk_name {
-            let mut fixed_code: Vec<&str> = chunk.split('\n').skip(1).collect();
-            fixed_code.pop();
-            let mut final_line: Vec<String> = vec![];
-            for line in fixed_code {
-                if line.starts_with("    ") {
-                    // Main indentation
-                    final_line.push(line.chars().skip(4).collect());
-                } else if line.starts_with("// :dep") {
-                    final_line.push(line.chars().skip(3).collect())
-                } else {
-                    final_line.push(line.to_owned())
-                }
+fn list_files(file_vec: &mut Vec<PathBuf>, path: &Path, ext: &str) -> Result<(), Error> {
+    if path.is_dir() {
+        let paths = fs::read_dir(path).map_err(|e| Error::io(path, e))?;
+        for path_result in paths {
+            let full_path = path_result.map_err(|e| Error::io(path, e))?.path();
+            if full_path.is_dir() {
+                list_files(file_vec, &full_path, ext)?;
+            } else if full_path.extension().is_some_and(|e| e == ext) {
+                file_vec.push(full_path);
             }
-            return final_line.join("\n").trim().to_owned();
         }
     }
 
-    panic!("ERROR: No chunk called {chunk_name} in {program_path}")
+    Ok(())
 }
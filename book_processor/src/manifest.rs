@@ -0,0 +1,89 @@
+use crate::error::Error;
+use crate::example::ExampleIndex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+const MANIFEST_PATH: &str = "target/book_processor/manifest.json";
+
+// What a chapter was processed from, and what it became
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChapterEntry {
+    pub markdown: String,                   // SHA-256 of the markdown
+    pub examples: BTreeMap<String, String>, // SHA-256 of every example used, by name
+    pub output: String,
+}
+
+// The examples used by each chapter, to only process the chapters that changed since the last run
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub chapters: BTreeMap<String, ChapterEntry>,
+}
+
+impl Manifest {
+    // A missing or unreadable manifest means that every chapter is processed
+    pub fn load(root: &Path) -> Manifest {
+        fs::read_to_string(root.join(MANIFEST_PATH))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, root: &Path) -> Result<(), Error> {
+        let path = root.join(MANIFEST_PATH);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        let manifest = serde_json::to_string_pretty(self).expect("a manifest is always valid JSON");
+        fs::write(&path, manifest).map_err(|e| Error::io(&path, e))
+    }
+
+    // The previous output of the chapter, if neither it nor its examples changed
+    pub fn unchanged(
+        &self,
+        chapter: &Path,
+        markdown: &str,
+        index: &ExampleIndex,
+    ) -> Option<&ChapterEntry> {
+        let entry = self.chapters.get(&key(chapter))?;
+        let examples_unchanged = entry
+            .examples
+            .iter()
+            .all(|(name, hash)| index.examples.get(name).is_some_and(|e| e.hash == *hash));
+
+        (entry.markdown == hash(markdown) && examples_unchanged).then_some(entry)
+    }
+
+    pub fn record(&mut self, chapter: &Path, entry: ChapterEntry) {
+        self.chapters.insert(key(chapter), entry);
+    }
+}
+
+impl ChapterEntry {
+    pub fn new(
+        markdown: &str,
+        programs: &BTreeSet<String>,
+        index: &ExampleIndex,
+        output: String,
+    ) -> ChapterEntry {
+        ChapterEntry {
+            markdown: hash(markdown),
+            examples: programs
+                .iter()
+                .filter_map(|p| index.examples.get(p))
+                .map(|e| (e.name.clone(), e.hash.clone()))
+                .collect(),
+            output,
+        }
+    }
+}
+
+fn key(chapter: &Path) -> String {
+    chapter.display().to_string()
+}
+
+fn hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
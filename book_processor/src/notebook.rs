@@ -0,0 +1,222 @@
+use crate::cargo::{Dependency, read_dependencies};
+use crate::directive::Directive;
+use crate::error::Error;
+use crate::example::{ExampleIndex, example_path};
+use crate::list_files;
+use crate::snippet::merged_code;
+use crate::versions::{Versions, expand_versions};
+use serde_json::{Value, json};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+// A Jupyter cell, before being written as JSON
+enum Cell {
+    Markdown(Vec<String>),
+    Code(Vec<String>),
+}
+
+// Write an evcxr Jupyter notebook for every chapter that uses examples
+pub fn write_notebooks(root: &Path, out: &Path) -> Result<(), Error> {
+    let manifest = root.join("Cargo.toml");
+    let dependencies = read_dependencies(root).map_err(|kind| Error::file(&manifest, kind))?;
+    let index = ExampleIndex::load(root)?;
+    let versions = Versions::load(root)?;
+
+    let src = root.join("src");
+    let mut files = vec![];
+    list_files(&mut files, &src, "md")?;
+    files.sort();
+
+    for file in files {
+        let content = fs::read_to_string(&file).map_err(|e| Error::io(&file, e))?;
+        let content = expand_versions(&file, &content, &versions)?;
+
+        let (cells, programs) = chapter_cells(&index, &file, &content)?;
+        if programs.is_empty() {
+            continue; // Nothing to run
+        }
+
+        let mut notebook_cells = vec![code_cell(&dep_lines(&index, &programs, &dependencies)?)];
+        for cell in cells {
+            notebook_cells.push(match cell {
+                Cell::Markdown(lines) => markdown_cell(&lines),
+                Cell::Code(lines) => code_cell(&lines),
+            });
+        }
+
+        let relative = file.strip_prefix(&src).unwrap_or(&file);
+        let notebook_path = out.join(relative).with_extension("ipynb");
+        eprintln!("Writing: {}", notebook_path.display());
+
+        if let Some(parent) = notebook_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        let notebook = serde_json::to_string_pretty(&notebook(notebook_cells))
+            .expect("a notebook is always valid JSON");
+        fs::write(&notebook_path, notebook).map_err(|e| Error::io(&notebook_path, e))?;
+    }
+
+    Ok(())
+}
+
+// Split a chapter in markdown and code cells, and list the examples it uses
+fn chapter_cells(
+    index: &ExampleIndex,
+    file: &Path,
+    content: &str,
+) -> Result<(Vec<Cell>, BTreeSet<String>), Error> {
+    let mut cells = vec![];
+    let mut programs = BTreeSet::new();
+
+    let mut markdown: Vec<String> = vec![];
+    let mut fence: Option<Vec<String>> = None; // Lines of the current code block
+    let mut fence_code: Vec<Vec<String>> = vec![]; // Code of the directives in the current block
+
+    for (i, line) in content.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            match fence.take() {
+                None => fence = Some(vec![line.to_owned()]),
+                Some(mut block) => {
+                    if fence_code.is_empty() {
+                        // A code block that does not come from an example stays in the prose
+                        block.push(line.to_owned());
+                        markdown.extend(block);
+                    } else {
+                        flush_markdown(&mut cells, &mut markdown);
+                        cells.extend(fence_code.drain(..).map(Cell::Code));
+                    }
+                }
+            }
+            continue;
+        }
+
+        match Directive::parse(line) {
+            None => match fence.as_mut() {
+                Some(block) => block.push(line.to_owned()),
+                None => markdown.push(line.to_owned()),
+            },
+            Some(directive) => {
+                let located = |kind| Error::at(file, i + 1, line, kind);
+                match directive.map_err(located)? {
+                    Directive::Rust {
+                        program, chunks, ..
+                    } => {
+                        // The display options would not run, so the whole chunks are used
+                        let example = index.get(&program).map_err(located)?;
+                        let code = merged_code(example, &chunks).map_err(located)?;
+                        programs.insert(program);
+                        match fence {
+                            Some(_) => fence_code.push(code),
+                            None => {
+                                flush_markdown(&mut cells, &mut markdown);
+                                cells.push(Cell::Code(code));
+                            }
+                        }
+                    }
+                    // The notebook prints its own output
+                    Directive::Output { .. } => {}
+                }
+            }
+        }
+    }
+
+    // An unclosed code block is left as is
+    if let Some(block) = fence {
+        markdown.extend(block);
+    }
+    flush_markdown(&mut cells, &mut markdown);
+
+    Ok((cells, programs))
+}
+
+fn flush_markdown(cells: &mut Vec<Cell>, markdown: &mut Vec<String>) {
+    let text = markdown.join("\n");
+    let text = text.trim_matches('\n');
+    if !text.trim().is_empty() {
+        cells.push(Cell::Markdown(text.lines().map(str::to_owned).collect()));
+    }
+    markdown.clear();
+}
+
+// `:dep` lines for every crate the examples of the chapter use
+fn dep_lines(
+    index: &ExampleIndex,
+    programs: &BTreeSet<String>,
+    dependencies: &[Dependency],
+) -> Result<Vec<String>, Error> {
+    let mut code = String::new();
+    for program in programs {
+        let example = index
+            .get(program)
+            .map_err(|kind| Error::file(&example_path(&index.root, program), kind))?;
+        for chunk in &example.chunks {
+            code.push_str(&chunk.lines.join("\n"));
+        }
+    }
+
+    let lines = dependencies
+        .iter()
+        .filter(|d| code.contains(&format!("{}::", d.ident())))
+        .map(Dependency::dep_line)
+        .collect();
+
+    Ok(lines)
+}
+
+fn markdown_cell(lines: &[String]) -> Value {
+    json!({
+        "cell_type": "markdown",
+        "metadata": {},
+        "source": source(lines),
+    })
+}
+
+fn code_cell(lines: &[String]) -> Value {
+    json!({
+        "cell_type": "code",
+        "execution_count": null,
+        "metadata": {},
+        "outputs": [],
+        "source": source(lines),
+    })
+}
+
+// Jupyter stores the source as a list of lines, each but the last ending with `\n`
+fn source(lines: &[String]) -> Vec<String> {
+    let last = lines.len().saturating_sub(1);
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, l)| {
+            if i == last {
+                l.clone()
+            } else {
+                format!("{l}\n")
+            }
+        })
+        .collect()
+}
+
+fn notebook(cells: Vec<Value>) -> Value {
+    json!({
+        "cells": cells,
+        "metadata": {
+            "kernelspec": {
+                "display_name": "Rust",
+                "language": "rust",
+                "name": "rust"
+            },
+            "language_info": {
+                "codemirror_mode": "rust",
+                "file_extension": ".rs",
+                "mimetype": "text/rust",
+                "name": "Rust",
+                "pygment_lexer": "rust",
+                "version": ""
+            }
+        },
+        "nbformat": 4,
+        "nbformat_minor": 4
+    })
+}
@@ -0,0 +1,109 @@
+use crate::error::ErrorKind;
+use crate::example::{Example, TEMPORARY_PREFIX, example_path};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+// Captured outputs are kept here (out of git), so that an example only runs again when it changes
+const CACHE_DIR: &str = "book_processor/output_cache";
+
+// Chapters are processed in parallel, but an example is only run once at a time
+static RUNNING: Mutex<()> = Mutex::new(());
+
+// Fetch what an example prints between `// === name` and the next marker
+pub fn output_chunk(root: &Path, example: &Example, chunk_name: &str) -> Result<String, ErrorKind> {
+    eprintln!(" ↳ Capturing: {}", example.path.display());
+
+    let stdout = {
+        let _running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        cached_stdout(root, example)?
+    };
+
+    let split_text: Vec<&str> = stdout.split("\n=== ").collect();
+
+    for chunk in split_text.into_iter().skip(1) {
+        let name = chunk.split([' ', '\n']).next().unwrap();
+        if name == chunk_name {
+            let printed: Vec<&str> = chunk.split('\n').skip(1).collect();
+            return Ok(printed.join("\n").trim().to_owned());
+        }
+    }
+
+    Err(ErrorKind::MissingOutput {
+        program: example.name.clone(),
+        chunk: chunk_name.to_owned(),
+    })
+}
+
+// Return the stdout of the example, only running it if its source changed
+fn cached_stdout(root: &Path, example: &Example) -> Result<String, ErrorKind> {
+    let hash = &example.hash;
+    let cache_dir = root.join(CACHE_DIR);
+    let cache_path = cache_dir.join(format!("{}.txt", example.name));
+
+    // The first line of the cache is the hash of the source that produced it
+    if let Ok(cached) = fs::read_to_string(&cache_path)
+        && let Some((cached_hash, stdout)) = cached.split_once('\n')
+        && cached_hash == *hash
+    {
+        return Ok(stdout.to_owned());
+    }
+
+    let stdout = run_example(root, &example.name, &example.source)?;
+
+    fs::create_dir_all(&cache_dir).map_err(|e| ErrorKind::Io(cache_dir, e))?;
+    fs::write(&cache_path, format!("{hash}\n{stdout}"))
+        .map_err(|e| ErrorKind::Io(cache_path, e))?;
+
+    Ok(stdout)
+}
+
+// Run a copy of the example where every indented marker prints its own name
+fn run_example(root: &Path, program_path: &str, code: &str) -> Result<String, ErrorKind> {
+    eprintln!(" ↳ Running: {program_path}");
+
+    let mut instrumented: Vec<String> = vec![];
+    for line in code.lines() {
+        let marker = line.trim_start();
+        let indent = &line[..line.len() - marker.len()];
+        match marker.strip_prefix("// === ") {
+            Some(name) if !indent.is_empty() => {
+                instrumented.push(format!("{indent}println!(\"\\n=== {}\");", name.trim()))
+            }
+            _ => instrumented.push(line.to_owned()),
+        }
+    }
+
+    // Cargo only runs examples found in the examples folder
+    let example_name = format!("{TEMPORARY_PREFIX}{program_path}");
+    let example_path = example_path(root, &example_name);
+    fs::write(&example_path, instrumented.join("\n"))
+        .map_err(|e| ErrorKind::Io(example_path.clone(), e))?;
+    let _copy = Temporary(example_path);
+
+    let result = Command::new("cargo")
+        .args(["run", "-r", "--example", &example_name])
+        .current_dir(root)
+        .stderr(Stdio::inherit())
+        .output();
+
+    let result = result.map_err(|e| ErrorKind::Io(PathBuf::from("cargo"), e))?;
+    if !result.status.success() {
+        return Err(ErrorKind::ExampleFailed(program_path.to_owned()));
+    }
+
+    // Newline so that a marker on the first printed line is still found
+    Ok(format!("\n{}", String::from_utf8_lossy(&result.stdout)))
+}
+
+// A file removed when dropped, even when the run fails or panics
+struct Temporary(PathBuf);
+
+impl Drop for Temporary {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            eprintln!("Could not remove {}: {e}", self.0.display());
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::error::{Error, ErrorKind};
+use crate::example::ExampleIndex;
+use crate::manifest::{ChapterEntry, Manifest};
+use crate::process_chapter;
+use crate::versions::{Versions, expand_versions};
+use rayon::prelude::*;
+use serde_json::Value;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Read `[context, book]` from mdBook on stdin and write the processed book to stdout
+pub fn run() -> Result<(), Error> {
+    let stdin = Path::new("<stdin>");
+    let input: Value =
+        serde_json::from_reader(io::stdin().lock()).map_err(|e| protocol(stdin, e))?;
+
+    let Value::Array(mut input) = input else {
+        return Err(protocol(stdin, "expected `[context, book]`"));
+    };
+    if input.len() != 2 {
+        return Err(protocol(stdin, "expected `[context, book]`"));
+    }
+    let mut book = input.pop().unwrap_or_default();
+    let context = input.pop().unwrap_or_default();
+
+    let root = context["root"].as_str().unwrap_or(".");
+    let root = PathBuf::from(root);
+    let src = context["config"]["book"]["src"].as_str().unwrap_or("src");
+
+    let index = ExampleIndex::load(&root)?;
+    let versions = Versions::load(&root)?;
+    let previous = Manifest::load(&root);
+
+    let mut chapters = vec![];
+    collect_chapters(&root.join(src), &mut book, &mut chapters);
+
+    // Only the chapters that changed, or whose examples changed, are processed again
+    let entries = chapters
+        .par_iter_mut()
+        .map(|(path, content)| {
+            **content = expand_versions(path, content, &versions)?;
+            let entry = match previous.unchanged(path, content, &index) {
+                Some(entry) => entry.clone(),
+                None => {
+                    let (output, programs) = process_chapter(&index, path, content)?;
+                    ChapterEntry::new(content, &programs, &index, output)
+                }
+            };
+            content.clone_from(&entry.output);
+            Ok((path.clone(), entry))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut manifest = Manifest::default();
+    for (path, entry) in entries {
+        manifest.record(&path, entry);
+    }
+    manifest.save(&root)?;
+
+    serde_json::to_writer(io::stdout().lock(), &book)
+        .map_err(|e| protocol(Path::new("<stdout>"), e))
+}
+
+// Walk the book, whatever the nesting, and collect the content of every chapter
+fn collect_chapters<'a>(
+    src: &Path,
+    value: &'a mut Value,
+    chapters: &mut Vec<(PathBuf, &'a mut String)>,
+) {
+    match value {
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                if key != "Chapter" {
+                    collect_chapters(src, item, chapters);
+                    continue;
+                }
+                let Value::Object(chapter) = item else {
+                    continue;
+                };
+
+                let path = chapter
+                    .get("source_path")
+                    .or_else(|| chapter.get("path"))
+                    .and_then(Value::as_str)
+                    .map(|p| src.join(p))
+                    .unwrap_or_else(|| src.to_path_buf());
+
+                for (field, value) in chapter.iter_mut() {
+                    match (field.as_str(), value) {
+                        ("content", Value::String(content)) => {
+                            chapters.push((path.clone(), content))
+                        }
+                        (_, value) => collect_chapters(src, value, chapters),
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_chapters(src, item, chapters);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn protocol(path: &Path, reason: impl ToString) -> Error {
+    Error::file(path, ErrorKind::Protocol(reason.to_string()))
+}
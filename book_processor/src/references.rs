@@ -0,0 +1,520 @@
+use crate::error::{Error, ErrorKind};
+use crate::example::{Example, ExampleIndex};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// The name the book uses to stand for any example
+const PLACEHOLDER_EXAMPLE: &str = "0_0_0_name_of_example";
+
+// The `just` recipe that creates the data, one example after the other
+const DATA_RECIPE: &str = "get-data-examples:";
+
+// The module of the library of the book that names the `./data` paths
+const PATHS_MODULE: &str = "lib/paths.rs";
+
+// Calls that produce the path they are given
+const WRITES: [&str; 11] = [
+    "File::create",
+    "create_dir",
+    "fs::write",
+    "Writer::new",
+    "write_partitioned_dataset",
+    ".write(", // `Partitioning::write` of the library
+    ".compact(",
+    "sink_",
+    ".save(",
+    "write_html",
+    "write_image",
+];
+
+// Calls that delete the path they are given, which is neither read nor produced
+const REMOVES: [&str; 2] = ["remove_dir", "remove_file"];
+
+// How an example uses a `./data` path
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Access {
+    Read,
+    Write,
+    Remove,
+}
+
+// A `./data` path in the source of an example, with `{...}` replaced by `*`
+struct DataPath {
+    line: usize,
+    text: String,
+    pattern: Vec<String>,
+    access: Access,
+}
+
+// Report relative links that do not resolve, unknown `--example` names and data paths
+// that no earlier example produces
+pub fn check_references(root: &Path, files: &[PathBuf], index: &ExampleIndex) -> Vec<Error> {
+    let mut errors = vec![];
+    let mut anchors: HashMap<PathBuf, Vec<String>> = HashMap::new(); // Heading ids, by chapter
+
+    for file in files {
+        let content = match fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(e) => {
+                errors.push(Error::io(file, e));
+                continue;
+            }
+        };
+        errors.extend(check_links(file, &content, &mut anchors));
+        errors.extend(check_example_names(file, &content, index));
+    }
+
+    let justfile = root.join("Justfile");
+    let recipes = match fs::read_to_string(&justfile) {
+        Ok(recipes) => recipes,
+        Err(e) => {
+            errors.push(Error::io(&justfile, e));
+            return errors;
+        }
+    };
+    errors.extend(check_example_names(&justfile, &recipes, index));
+
+    let chain = data_chain(&recipes);
+    errors.extend(check_data_paths(&chain, index));
+
+    errors
+}
+
+// Every relative `[text](target#anchor)` must point to an existing file and heading
+fn check_links(
+    file: &Path,
+    content: &str,
+    anchors: &mut HashMap<PathBuf, Vec<String>>,
+) -> Vec<Error> {
+    let mut errors = vec![];
+
+    let mut in_fence = false;
+    for (i, line) in content.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        if in_fence {
+            continue;
+        }
+
+        for target in link_targets(&outside_code(line)) {
+            if target.contains("://") || target.starts_with("mailto:") {
+                continue;
+            }
+
+            let (path, anchor) = target.split_once('#').unwrap_or((target, ""));
+            let linked = match path {
+                "" => file.to_path_buf(),
+                _ => normalize(&file.parent().unwrap_or(Path::new("")).join(path)),
+            };
+
+            let broken = |reason: &str| {
+                let kind = ErrorKind::BrokenLink(format!("{target} ({reason})"));
+                Error::at(file, i + 1, line, kind)
+            };
+
+            if !linked.exists() {
+                errors.push(broken("no such file"));
+                continue;
+            }
+            if anchor.is_empty() || linked.extension().is_none_or(|e| e != "md") {
+                continue;
+            }
+
+            let ids = match anchors.get(&linked) {
+                Some(ids) => ids,
+                None => match fs::read_to_string(&linked) {
+                    Ok(linked_content) => anchors
+                        .entry(linked.clone())
+                        .or_insert(heading_ids(&linked_content)),
+                    Err(e) => {
+                        errors.push(Error::io(&linked, e));
+                        continue;
+                    }
+                },
+            };
+            if !ids.iter().any(|id| id == anchor) {
+                errors.push(broken("no such heading"));
+            }
+        }
+    }
+
+    errors
+}
+
+// A line without its `inline code`, which is not rendered as links
+fn outside_code(line: &str) -> String {
+    line.split('`').step_by(2).collect()
+}
+
+// The targets of the inline links of a line, without their optional title
+fn link_targets(line: &str) -> Vec<&str> {
+    let mut targets = vec![];
+    let mut rest = line;
+    while let Some(start) = rest.find("](") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find(')') else {
+            break;
+        };
+        let target = rest[..end].split_whitespace().next().unwrap_or_default();
+        targets.push(target.trim_start_matches('<').trim_end_matches('>'));
+        rest = &rest[end..];
+    }
+    targets
+}
+
+// `..` and `.` resolved without touching the file system
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// The ids mdBook gives the headings of a chapter, e.g. `## SQL (optional)` is `#sql-optional`
+fn heading_ids(content: &str) -> Vec<String> {
+    let mut ids: Vec<String> = vec![];
+
+    let mut in_fence = false;
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        let Some(heading) = line.strip_prefix('#') else {
+            continue;
+        };
+        if in_fence {
+            continue;
+        }
+
+        let id = heading_id(&ids, heading.trim_start_matches('#'));
+        ids.push(id);
+    }
+
+    ids
+}
+
+// The id of a heading, given the ids of the headings before it in the chapter
+pub fn heading_id(ids: &[String], heading: &str) -> String {
+    let id: String = heading
+        .trim()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() || c == '_' || c == '-' => Some(c.to_ascii_lowercase()),
+            c if c.is_whitespace() => Some('-'),
+            _ => None,
+        })
+        .collect();
+
+    // Repeated headings get a number, like mdBook does
+    let repeats = ids
+        .iter()
+        .filter(|i| **i == id || i.strip_prefix(&format!("{id}-")).is_some_and(is_number))
+        .count();
+    match repeats {
+        0 => id,
+        n => format!("{id}-{n}"),
+    }
+}
+
+fn is_number(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_digit())
+}
+
+// Every `--example name` must be an example of `examples/`
+fn check_example_names(file: &Path, content: &str, index: &ExampleIndex) -> Vec<Error> {
+    let mut errors = vec![];
+    for (i, line) in content.lines().enumerate() {
+        for name in example_names(line) {
+            if name != PLACEHOLDER_EXAMPLE && !index.examples.contains_key(name) {
+                let kind = ErrorKind::UnknownExample(name.to_owned());
+                errors.push(Error::at(file, i + 1, line, kind));
+            }
+        }
+    }
+    errors
+}
+
+// The names following `--example` in a line
+pub fn example_names(line: &str) -> Vec<&str> {
+    line.match_indices("--example")
+        .filter_map(|(start, flag)| {
+            let rest = &line[start + flag.len()..];
+            let rest = rest.strip_prefix([' ', '='])?.trim_start();
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (end > 0).then_some(&rest[..end])
+        })
+        .collect()
+}
+
+// The examples of the `get-data-examples` recipe, in the order they run
+fn data_chain(recipes: &str) -> Vec<&str> {
+    recipes
+        .lines()
+        .skip_while(|l| l.trim_end() != DATA_RECIPE)
+        .skip(1)
+        .take_while(|l| l.starts_with([' ', '\t']) || l.trim().is_empty())
+        .flat_map(example_names)
+        .collect()
+}
+
+// Every `./data` path an example reads must be produced by an earlier example of the chain,
+// or earlier in the example itself
+fn check_data_paths(chain: &[&str], index: &ExampleIndex) -> Vec<Error> {
+    let mut errors = vec![];
+
+    let registry = match path_registry(&index.root) {
+        Ok(registry) => registry,
+        Err(e) => {
+            errors.push(e);
+            HashMap::new()
+        }
+    };
+    let data_paths: HashMap<&str, Vec<DataPath>> = index
+        .examples
+        .values()
+        .map(|example| (example.name.as_str(), data_paths(example, &registry)))
+        .collect();
+
+    for example in index.examples.values() {
+        // Examples of the chain only rely on those before them, the others on all of them
+        let earlier = match chain.iter().position(|p| *p == example.name) {
+            Some(position) => &chain[..position],
+            None => chain,
+        };
+
+        let paths = &data_paths[example.name.as_str()];
+        for (i, path) in paths.iter().enumerate() {
+            if path.access != Access::Read {
+                continue;
+            }
+
+            let produced_before = earlier
+                .iter()
+                .filter_map(|program| data_paths.get(program))
+                .flatten()
+                .chain(&paths[..i])
+                .filter(|p| p.access == Access::Write)
+                .any(|p| produces(&p.pattern, &path.pattern));
+            if produced_before {
+                continue;
+            }
+
+            let kind = ErrorKind::DanglingDataPath {
+                program: example.name.clone(),
+                path: path.text.clone(),
+            };
+            let text = example
+                .source
+                .lines()
+                .nth(path.line - 1)
+                .unwrap_or_default();
+            errors.push(Error::at(&example.path, path.line, text, kind));
+        }
+    }
+
+    errors
+}
+
+// The `"./data..."` literals of an example and the `paths::NAME` it uses, in source order
+fn data_paths(example: &Example, registry: &HashMap<String, String>) -> Vec<DataPath> {
+    let source = &example.source;
+
+    let mut found: Vec<(usize, &str)> = vec![]; // Where each path is used, and the path
+    for (start, _) in source.match_indices("\"./data") {
+        if let Some(len) = source[start + 1..].find('"') {
+            found.push((start, &source[start + 1..start + 1 + len]));
+        }
+    }
+    for (start, prefix) in source.match_indices("paths::") {
+        let name = &source[start + prefix.len()..];
+        let end = name
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(name.len());
+        if let Some(text) = registry.get(&name[..end]) {
+            found.push((start, text));
+        }
+    }
+    found.sort();
+
+    let mut paths = vec![];
+    for (start, text) in found {
+        let line_start = source[..start].rfind('\n').map_or(0, |n| n + 1);
+        if source[line_start..start].trim_start().starts_with("//") {
+            continue;
+        }
+
+        paths.push(DataPath {
+            line: source[..start].matches('\n').count() + 1,
+            text: text.to_owned(),
+            pattern: pattern(text),
+            access: access(source, start),
+        });
+    }
+
+    paths
+}
+
+// The `./data` path of each `pub const` and `pub fn` of `lib/paths.rs`, e.g. `PARTITIONED`
+// for `./data/large/partitioned` and `csv_chunk` for `./data/csv/census_{chunk}.csv`
+fn path_registry(root: &Path) -> Result<HashMap<String, String>, Error> {
+    let file = root.join(PATHS_MODULE);
+    let source = match fs::read_to_string(&file) {
+        Ok(source) => source,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(Error::io(&file, e)),
+    };
+
+    let mut registry = HashMap::new();
+    let items: Vec<&str> = source.split("\npub ").skip(1).collect();
+    for item in items {
+        let Some(rest) = item
+            .strip_prefix("const ")
+            .or_else(|| item.strip_prefix("fn "))
+        else {
+            continue;
+        };
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        let Some(start) = rest.find("\"./data") else {
+            continue; // Not a `./data` path, like `ZIP` or `DIRECTORIES`
+        };
+        let Some(len) = rest[start + 1..].find('"') else {
+            continue;
+        };
+        registry.insert(
+            rest[..end].to_owned(),
+            rest[start + 1..start + 1 + len].to_owned(),
+        );
+    }
+
+    Ok(registry)
+}
+
+// `./data/csv/census_{chunk}.csv` becomes `["data", "csv", "census_*.csv"]`
+fn pattern(text: &str) -> Vec<String> {
+    let mut wildcarded = String::new();
+    let mut in_braces = false;
+    for c in text.chars() {
+        match c {
+            '{' => {
+                in_braces = true;
+                wildcarded.push('*');
+            }
+            '}' => in_braces = false,
+            _ if in_braces => {}
+            c => wildcarded.push(c),
+        }
+    }
+
+    wildcarded
+        .split('/')
+        .filter(|s| !s.is_empty() && *s != ".")
+        .map(str::to_owned)
+        .collect()
+}
+
+// Whether the statement around a literal writes, deletes or reads it, following a `let`
+// binding to where it is used
+fn access(source: &str, literal: usize) -> Access {
+    let start = source[..literal].rfind(';').map_or(0, |n| n + 1);
+    let end = source[literal..]
+        .find(';')
+        .map_or(source.len(), |n| literal + n);
+    let statement = &source[start..end];
+
+    if REMOVES.iter().any(|r| statement.contains(r)) {
+        return Access::Remove;
+    }
+    if WRITES.iter().any(|w| statement.contains(w)) {
+        return Access::Write;
+    }
+
+    // `let path = format!("./data/...");` followed by `File::create(path)`
+    let Some(binding) = let_binding(&source[start..literal]) else {
+        return Access::Read;
+    };
+    let written = source[end..].split(';').any(|statement| {
+        contains_word(statement, binding) && WRITES.iter().any(|w| statement.contains(w))
+    });
+    if written { Access::Write } else { Access::Read }
+}
+
+// The variable a statement binds, e.g. `path` in `let mut path = ...`
+fn let_binding(statement: &str) -> Option<&str> {
+    let (_, rest) = statement.split_once("let ")?;
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix("mut ").unwrap_or(rest).trim_start();
+    let end = rest.find(|c: char| !c.is_alphanumeric() && c != '_')?;
+    (end > 0).then_some(&rest[..end])
+}
+
+fn contains_word(text: &str, word: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+        !before.is_some_and(is_ident) && !after.is_some_and(is_ident)
+    })
+}
+
+// A written path produces a read one if they match, or if the read one is a directory
+// holding it
+fn produces(written: &[String], read: &[String]) -> bool {
+    read.len() <= written.len()
+        && read
+            .iter()
+            .zip(written)
+            .all(|(r, w)| wildcard_match(r, w) || wildcard_match(w, r))
+}
+
+// Whether `text` matches `pattern`, where `*` stands for any characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty(); // No `*` at all
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(wildcard_match("census.csv", "census.csv"));
+        assert!(!wildcard_match("census.csv", "census.csv.bak"));
+        assert!(wildcard_match("census_*.csv", "census_0.csv"));
+        assert!(wildcard_match("census_*.csv", "census_.csv"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*_*_*", "a_b_c"));
+        assert!(!wildcard_match("*_*_*", "a_b"));
+        // The end of the text can not be used twice
+        assert!(!wildcard_match("ab*ba", "aba"));
+        assert!(!wildcard_match("census_*.csv", "census_0.parquet"));
+    }
+}
@@ -0,0 +1,278 @@
+use crate::directive::ChunkOptions;
+use crate::error::ErrorKind;
+use crate::example::{Chunk, Example};
+use std::ops::RangeInclusive;
+
+// What replaces a `=== Rust` directive, with the annotations its fence needs
+#[derive(Debug, Default)]
+pub struct Snippet {
+    pub lines: Vec<String>,
+    pub hidden: bool,
+}
+
+// Merge the chunks of an example and apply the line options to them
+pub fn code_snippet(
+    example: &Example,
+    chunk_names: &[String],
+    options: &ChunkOptions,
+) -> Result<Snippet, ErrorKind> {
+    let code = merged_code(example, chunk_names)?;
+
+    // Every line number given must exist
+    let ranges = options
+        .lines
+        .iter()
+        .chain(&options.collapse)
+        .chain(&options.hide);
+    if let Some(end) = ranges.map(|r| *r.end()).max()
+        && end > code.len()
+    {
+        return Err(ErrorKind::MalformedDirective(format!(
+            "line {end} is past the end of the code ({} lines)",
+            code.len()
+        )));
+    }
+
+    let within = |ranges: &[RangeInclusive<usize>], n: usize| ranges.iter().any(|r| r.contains(&n));
+
+    let mut snippet = Snippet::default();
+    let mut collapsing = false;
+    for (i, line) in code.iter().enumerate() {
+        let n = i + 1;
+        if options.lines.as_ref().is_some_and(|r| !r.contains(&n)) {
+            continue;
+        }
+
+        let content = line.trim_start();
+        let indent = &line[..line.len() - content.len()];
+
+        if within(&options.collapse, n) {
+            // Only one ellipsis for consecutive collapsed lines
+            if !collapsing {
+                snippet.lines.push(format!("{indent}// ..."));
+            }
+            collapsing = true;
+            continue;
+        }
+        collapsing = false;
+
+        if within(&options.hide, n) {
+            // mdBook hides Rust lines starting with `#`
+            snippet
+                .lines
+                .push(format!("{indent}# {content}").trim_end().to_owned());
+            snippet.hidden = true;
+        } else {
+            snippet.lines.push(line.to_owned());
+        }
+    }
+
+    Ok(snippet)
+}
+
+// Columns a tab advances to, when measuring indentation
+const TAB_WIDTH: usize = 4;
+
+// The lines of several chunks of an example, one after the other
+pub fn merged_code(example: &Example, chunk_names: &[String]) -> Result<Vec<String>, ErrorKind> {
+    eprintln!(" ↳ Fetching: {}", example.path.display());
+
+    let mut code: Vec<String> = vec![];
+    for chunk_name in chunk_names {
+        let chunk = example.chunk(chunk_name)?;
+        code.extend(chunk_code(chunk).lines().map(str::to_owned));
+    }
+
+    Ok(code)
+}
+
+// The code of a chunk, without the indentation all its lines share
+pub fn chunk_code(chunk: &Chunk) -> String {
+    let string_lines = string_continuations(&chunk.lines);
+    let lines: Vec<(String, &str)> = chunk.lines.iter().map(|l| split_indent(l)).collect();
+
+    // `// :dep` lines are for the evcxr REPL and the inside of multi-line strings is text,
+    // so neither count towards the indentation
+    let common = lines
+        .iter()
+        .zip(&string_lines)
+        .filter(|((_, content), in_string)| {
+            !content.is_empty() && !content.starts_with("// :dep") && !**in_string
+        })
+        .map(|((indent, _), _)| indent.len())
+        .min()
+        .unwrap_or(0);
+
+    let mut final_line: Vec<String> = vec![];
+    for ((indent, content), (line, in_string)) in
+        lines.into_iter().zip(chunk.lines.iter().zip(string_lines))
+    {
+        if in_string {
+            // Follow the code if the text is indented enough, never cut into it
+            let text = line.strip_prefix(&" ".repeat(common)).unwrap_or(line);
+            final_line.push(text.to_owned());
+        } else if let Some(dep) = content.strip_prefix("// ")
+            && dep.starts_with(":dep")
+        {
+            final_line.push(dep.to_owned());
+        } else if content.is_empty() {
+            final_line.push(String::new());
+        } else {
+            final_line.push(format!("{}{content}", &indent[common..]));
+        }
+    }
+
+    final_line.join("\n").trim_matches('\n').to_owned()
+}
+
+// For each line, whether it starts inside a string literal opened on a previous line
+fn string_continuations(lines: &[String]) -> Vec<bool> {
+    // `None` outside of strings, otherwise the number of `#` closing the (raw) string
+    let mut string: Option<Option<usize>> = None;
+    let mut continuations = vec![];
+
+    for line in lines {
+        continuations.push(string.is_some());
+
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let ahead = |n: usize| chars.get(i + n).copied();
+            match (string, chars[i]) {
+                (Some(None), '\\') => i += 1,
+                (Some(None), '"') => string = None,
+                (Some(Some(hashes)), '"') if (1..=hashes).all(|n| ahead(n) == Some('#')) => {
+                    string = None;
+                    i += hashes;
+                }
+                (None, '"') => string = Some(None),
+                (None, 'r') if matches!(ahead(1), Some('"' | '#')) => {
+                    let hashes = chars[i + 1..].iter().take_while(|c| **c == '#').count();
+                    if ahead(hashes + 1) == Some('"') {
+                        string = Some(Some(hashes));
+                        i += hashes + 1;
+                    }
+                }
+                (None, '/') if ahead(1) == Some('/') => break,
+                // Character literal, such as '"' or '\'' (but not a lifetime)
+                (None, '\'') => {
+                    let width = if ahead(1) == Some('\\') { 2 } else { 1 };
+                    if ahead(width + 1) == Some('\'') {
+                        i += width + 1;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    continuations
+}
+
+// The indentation of a line, with tabs expanded to spaces, and the rest of the line
+fn split_indent(line: &str) -> (String, &str) {
+    let content = line.trim_start();
+
+    let mut indent = String::new();
+    for c in line[..line.len() - content.len()].chars() {
+        match c {
+            '\t' => indent.push_str(&" ".repeat(TAB_WIDTH - indent.len() % TAB_WIDTH)),
+            _ => indent.push(' '),
+        }
+    }
+
+    (indent, content.trim_end())
+}
+
+// Add `hidelines` to the fence around a snippet with hidden lines
+pub fn annotate_fence(fence: &mut String, snippet: &Snippet) {
+    if snippet.hidden && !fence.contains("hidelines=") {
+        fence.push_str(",hidelines=#");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn example(code: &str) -> Example {
+        Example::parse(
+            "1_1_1_test",
+            PathBuf::from("1_1_1_test.rs"),
+            code.to_owned(),
+        )
+    }
+
+    fn code(source: &str) -> String {
+        chunk_code(example(source).chunk("block_1").unwrap())
+    }
+
+    #[test]
+    fn dedents_nested_chunks() {
+        let source = "fn main() {\n    for i in 0..2 {\n        // === block_1\n        let x = i;\n\n            println!(\"{x}\");\n        // === end\n    }\n}\n";
+        assert_eq!(code(source), "let x = i;\n\n    println!(\"{x}\");");
+    }
+
+    #[test]
+    fn expands_tabs_before_dedenting() {
+        let source = "fn main() {\n\t// === block_1\n\tlet x = 1;\n\tif x > 0 {\n\t    x;\n\t}\n\t// === end\n}\n";
+        assert_eq!(code(source), "let x = 1;\nif x > 0 {\n    x;\n}");
+    }
+
+    #[test]
+    fn keeps_dep_lines_out_of_the_indentation() {
+        let source = "// === block_1\n// :dep polars\n    let x = 1;\n";
+        assert_eq!(code(source), ":dep polars\nlet x = 1;");
+    }
+
+    #[test]
+    fn leaves_the_text_of_multi_line_strings() {
+        let source = "fn main() {\n    // === block_1\n    let s = \"first\nsecond \\\" still text\n        indented\";\n    let t = 1;\n    // === end\n}\n";
+        assert_eq!(
+            code(source),
+            "let s = \"first\nsecond \\\" still text\n    indented\";\nlet t = 1;"
+        );
+    }
+
+    #[test]
+    fn finds_string_continuations() {
+        let lines = |code: &str| code.lines().map(str::to_owned).collect::<Vec<_>>();
+        assert_eq!(
+            string_continuations(&lines(
+                "let s = r#\"a \"quoted\"\nb\"#;\nlet c = '\"';\nlet d = 1; // \"\n"
+            )),
+            [false, true, false, false]
+        );
+        assert_eq!(
+            string_continuations(&lines(
+                "fn f<'a>(x: &'a str) {\nlet s = \"\\\\\";\nlet t = \"\nx\";\n"
+            )),
+            [false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn applies_the_line_options() {
+        let source = "fn main() {\n    // === block_1\n    let a = 1;\n    let b = 2;\n    let c = 3;\n    let d = 4;\n    let e = 5;\n    // === end\n}\n";
+        let options = ChunkOptions {
+            lines: Some(1..=5),
+            collapse: vec![2..=3],
+            hide: vec![5..=5],
+        };
+        let snippet = code_snippet(&example(source), &["block_1".to_owned()], &options).unwrap();
+        assert_eq!(
+            snippet.lines,
+            ["let a = 1;", "// ...", "let d = 4;", "# let e = 5;"]
+        );
+        assert!(snippet.hidden);
+
+        let options = ChunkOptions {
+            hide: vec![7..=9],
+            ..ChunkOptions::default()
+        };
+        let error = code_snippet(&example(source), &["block_1".to_owned()], &options);
+        assert!(matches!(error, Err(ErrorKind::MalformedDirective(_))));
+    }
+}
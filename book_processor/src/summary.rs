@@ -0,0 +1,347 @@
+use crate::cargo::{Dependency, read_dependencies};
+use crate::directive::Directive;
+use crate::error::{Error, ErrorKind};
+use crate::example::{Example, ExampleIndex};
+use crate::references::example_names;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The appendix listing every example, after the numbered chapters
+const APPENDIX: &str = "examples.md";
+
+// The Polars features and what an example writes when it uses them: an identifier (matched as a
+// whole word) or any other text (matched as is). Features missing from `Cargo.toml` are skipped.
+const POLARS_FEATURES: &[(&str, &[&str])] = &[
+    ("lazy", &["LazyFrame", "LazyCsvReader", "lazy"]),
+    (
+        "new_streaming",
+        &["sink_parquet", "sink_csv", "sink_batches", "Streaming"],
+    ),
+    (
+        "parquet",
+        &[
+            "scan_parquet",
+            "sink_parquet",
+            "ParquetReader",
+            "ParquetWriter",
+        ],
+    ),
+    ("partition_by", &["partition_by", "partition_by_stable"]),
+    ("round_series", &["round"]),
+    ("replace", &["replace", "replace_strict"]),
+    ("is_in", &["is_in"]),
+    ("pivot", &["pivot", "pivot_stable"]),
+    (
+        "cum_agg",
+        &["cum_sum", "cum_count", "cum_min", "cum_max", "cum_prod"],
+    ),
+    ("abs", &["abs"]),
+    ("aws", &["CloudOptions", "s3://"]),
+    ("regex", &["col(\"^"]),
+    ("fmt", &["POLARS_FMT_TABLE_FORMATTING"]),
+    ("dtype-decimal", &["Decimal"]),
+];
+
+// A `N_name` directory of the book, with its `K_name.md` chapters (`0_index.md` first)
+struct Section {
+    number: usize,
+    dir: String,
+    chapters: Vec<String>,
+}
+
+// A chapter an example is used in, as a path relative to `src`
+#[derive(Debug, Clone, PartialEq)]
+struct ChapterRef {
+    section: usize,
+    path: String,
+    title: String,
+}
+
+// Write `src/SUMMARY.md` and the example appendix from the chapter directories
+pub fn write_summary(root: &Path) -> Result<(), Error> {
+    let index = ExampleIndex::load(root)?;
+    for (path, content) in generate(root, &index)? {
+        eprintln!("Writing: {}", path.display());
+        fs::write(&path, content).map_err(|e| Error::io(&path, e))?;
+    }
+    Ok(())
+}
+
+// Report a summary or appendix that differs from the chapter directories, or an example
+// numbered in another section than the chapters that use it
+pub fn check_summary(root: &Path, index: &ExampleIndex) -> Vec<Error> {
+    let mut errors = vec![];
+
+    match generate(root, index) {
+        Ok(generated) => {
+            for (path, expected) in generated {
+                match fs::read_to_string(&path) {
+                    Ok(content) if content == expected => {}
+                    Ok(_) => errors.push(Error::file(&path, ErrorKind::OutOfDate)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        errors.push(Error::file(&path, ErrorKind::OutOfDate))
+                    }
+                    Err(e) => errors.push(Error::io(&path, e)),
+                }
+            }
+        }
+        Err(e) => errors.push(e),
+    }
+
+    let src = root.join("src");
+    let used_in = match sections(&src).and_then(|sections| example_chapters(&src, &sections)) {
+        Ok(used_in) => used_in,
+        Err(e) => {
+            errors.push(e);
+            return errors;
+        }
+    };
+    for (program, chapters) in used_in {
+        let Some(example) = index.examples.get(&program) else {
+            continue; // Reported with the links
+        };
+        for chapter in chapters {
+            if section_number(&program) != Some(chapter.section) {
+                let kind = ErrorKind::MisnumberedExample {
+                    program: program.clone(),
+                    chapter: chapter.path,
+                };
+                errors.push(Error::file(&example.path, kind));
+            }
+        }
+    }
+
+    errors
+}
+
+// The paths and content of `SUMMARY.md` and of the appendix
+fn generate(root: &Path, index: &ExampleIndex) -> Result<Vec<(PathBuf, String)>, Error> {
+    let src = root.join("src");
+    let sections = sections(&src)?;
+
+    let manifest = root.join("Cargo.toml");
+    let dependencies = read_dependencies(root).map_err(|kind| Error::file(&manifest, kind))?;
+    let used_in = example_chapters(&src, &sections)?;
+
+    let summary = summary(&src, &sections)?;
+    let appendix = appendix(index, &dependencies, &used_in);
+
+    Ok(vec![
+        (src.join("SUMMARY.md"), summary),
+        (src.join(APPENDIX), appendix),
+    ])
+}
+
+// The chapters in book order. A chapter keeps the title it already has in `SUMMARY.md` (e.g.
+// "Data analysis in Rust" for the introduction); a new one gets its heading.
+fn summary(src: &Path, sections: &[Section]) -> Result<String, Error> {
+    let existing = existing_titles(src);
+    let title = |path: &str| match existing.get(path) {
+        Some(title) => Ok(title.clone()),
+        None => title(src, path),
+    };
+    let mut lines = vec!["# Summary".to_owned(), String::new()];
+
+    if src.join("README.md").exists() {
+        lines.push(format!("[{}](README.md)", title("README.md")?));
+        lines.push(String::new());
+    }
+
+    for section in sections {
+        for chapter in &section.chapters {
+            let path = format!("{}/{chapter}", section.dir);
+            let indent = if section_number(chapter) == Some(0) {
+                ""
+            } else {
+                "    "
+            };
+            lines.push(format!("{indent}* [{}]({path})", title(&path)?));
+        }
+        lines.push(String::new());
+    }
+
+    lines.push(format!("[Examples]({APPENDIX})"));
+
+    Ok(lines.join("\n") + "\n")
+}
+
+// A table of every example, with where it is explained and what it needs
+fn appendix(
+    index: &ExampleIndex,
+    dependencies: &[Dependency],
+    used_in: &BTreeMap<String, Vec<ChapterRef>>,
+) -> String {
+    let mut lines = vec![
+        "# Examples".to_owned(),
+        String::new(),
+        "<!-- Generated by `just summary`, do not edit -->".to_owned(),
+        String::new(),
+        "Every example of the book, with the chapter that explains it, the crates and Polars features it uses, and how to run it from the root of the repository.".to_owned(),
+        String::new(),
+        "| Example | Chapter | Crates | Polars features | Command |".to_owned(),
+        "|---|---|---|---|---|".to_owned(),
+    ];
+
+    for example in index.examples.values() {
+        let chapters: Vec<String> = used_in
+            .get(&example.name)
+            .into_iter()
+            .flatten()
+            .map(|c| format!("[{}]({})", c.title, c.path))
+            .collect();
+        let (crates, features) = imported(example, dependencies);
+
+        lines.push(format!(
+            "| `{}` | {} | {} | {} | `cargo run -r --example {}` |",
+            example.name,
+            chapters.join(", "),
+            crates.join(", "),
+            features.join(", "),
+            example.name
+        ));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+// The crates an example uses and the Polars features it needs, e.g. `pivot` for `pivot_stable`
+fn imported(example: &Example, dependencies: &[Dependency]) -> (Vec<String>, Vec<String>) {
+    let source: Vec<&str> = example
+        .source
+        .lines()
+        .filter(|l| !l.trim_start().starts_with("//"))
+        .collect();
+    let source = source.join("\n");
+
+    // Some crates are only used with full paths, like `zip::ZipArchive`
+    let crates = dependencies
+        .iter()
+        .filter(|d| source.contains(&format!("{}::", d.ident())))
+        .map(|d| d.name.clone())
+        .collect();
+
+    let words: BTreeSet<&str> = source
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .collect();
+    let uses = |symbol: &&str| match symbol.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        true => words.contains(symbol),
+        false => source.contains(symbol),
+    };
+    let enabled: Vec<&String> = dependencies
+        .iter()
+        .filter(|d| d.name == "polars")
+        .flat_map(|d| &d.features)
+        .collect();
+    let features = POLARS_FEATURES
+        .iter()
+        .filter(|(feature, _)| enabled.iter().any(|f| f == feature))
+        .filter(|(_, symbols)| symbols.iter().any(uses))
+        .map(|(feature, _)| feature.to_string())
+        .collect();
+
+    (crates, features)
+}
+
+// The numbered directories of the book, each with its numbered chapters
+fn sections(src: &Path) -> Result<Vec<Section>, Error> {
+    let mut sections = vec![];
+    for (number, dir) in numbered_entries(src)? {
+        let path = src.join(&dir);
+        if !path.is_dir() {
+            continue;
+        }
+
+        let chapters = numbered_entries(&path)?
+            .into_iter()
+            .map(|(_, name)| name)
+            .filter(|name| name.ends_with(".md"))
+            .collect();
+        sections.push(Section {
+            number,
+            dir,
+            chapters,
+        });
+    }
+    Ok(sections)
+}
+
+// The `N_name` entries of a directory, sorted by number
+fn numbered_entries(dir: &Path) -> Result<Vec<(usize, String)>, Error> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir).map_err(|e| Error::io(dir, e))? {
+        let entry = entry.map_err(|e| Error::io(dir, e))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(number) = section_number(&name) {
+            entries.push((number, name));
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+// `3` for `3_transformation`, `2_select.md` or `3_1_1_filter`
+fn section_number(name: &str) -> Option<usize> {
+    name.split_once('_')?.0.parse().ok()
+}
+
+// The titles of the links of the current `SUMMARY.md`, by path (none if there is no summary yet)
+fn existing_titles(src: &Path) -> BTreeMap<String, String> {
+    let content = fs::read_to_string(src.join("SUMMARY.md")).unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| {
+            let (title, rest) = line.split_once('[')?.1.split_once("](")?;
+            let (path, _) = rest.split_once(')')?;
+            Some((path.to_owned(), title.to_owned()))
+        })
+        .collect()
+}
+
+// The text of the first `# ` heading of a chapter, or its file name
+fn title(src: &Path, path: &str) -> Result<String, Error> {
+    let file = src.join(path);
+    let content = fs::read_to_string(&file).map_err(|e| Error::io(&file, e))?;
+    let heading = content
+        .lines()
+        .find_map(|l| l.strip_prefix("# "))
+        .map(|h| h.trim().to_owned());
+    Ok(heading.unwrap_or_else(|| path.to_owned()))
+}
+
+// The chapters that show or run each example, in book order
+fn example_chapters(
+    src: &Path,
+    sections: &[Section],
+) -> Result<BTreeMap<String, Vec<ChapterRef>>, Error> {
+    let mut used_in: BTreeMap<String, Vec<ChapterRef>> = BTreeMap::new();
+
+    for section in sections {
+        for chapter in &section.chapters {
+            let path = format!("{}/{chapter}", section.dir);
+            let file = src.join(&path);
+            let content = fs::read_to_string(&file).map_err(|e| Error::io(&file, e))?;
+            let chapter_ref = ChapterRef {
+                section: section.number,
+                title: title(src, &path)?,
+                path,
+            };
+
+            for line in content.lines() {
+                let mut programs: Vec<String> =
+                    example_names(line).into_iter().map(str::to_owned).collect();
+                if let Some(Ok(directive)) = Directive::parse(line) {
+                    programs.push(directive.program().to_owned());
+                }
+                for program in programs {
+                    let chapters = used_in.entry(program).or_default();
+                    if !chapters.contains(&chapter_ref) {
+                        chapters.push(chapter_ref.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(used_in)
+}
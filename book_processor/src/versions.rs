@@ -0,0 +1,412 @@
+use crate::cargo::{Dependency, read_dependencies, read_manifest};
+use crate::error::{Error, ErrorKind};
+use crate::example::ExampleIndex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The versions of the crates the examples use, from `Cargo.toml` and `Cargo.lock`
+pub struct Versions {
+    dependencies: Vec<Dependency>,
+    locked: Option<BTreeMap<String, Vec<String>>>, // `None` without a `Cargo.lock`
+}
+
+// A crate, as quoted in the book
+struct Mention {
+    line: usize,
+    name: String,
+    version: String,
+    features: Vec<String>,
+    complete: bool, // Lists every feature, like the `[dependencies]` of a `toml` block
+}
+
+impl Versions {
+    pub fn load(root: &Path) -> Result<Versions, Error> {
+        let manifest = root.join("Cargo.toml");
+        let dependencies = read_dependencies(root).map_err(|kind| Error::file(&manifest, kind))?;
+
+        // `Cargo.lock` is not committed, so it only refines the check when it exists
+        let lock = root.join("Cargo.lock");
+        let locked = match lock.exists() {
+            false => None,
+            true => {
+                let lock_file = read_manifest(&lock).map_err(|kind| Error::file(&lock, kind))?;
+                let mut locked: BTreeMap<String, Vec<String>> = BTreeMap::new();
+                let packages = lock_file.get("package").and_then(toml::Value::as_array);
+                for package in packages.into_iter().flatten() {
+                    let name = package.get("name").and_then(toml::Value::as_str);
+                    let version = package.get("version").and_then(toml::Value::as_str);
+                    if let (Some(name), Some(version)) = (name, version) {
+                        let versions = locked.entry(name.to_owned()).or_default();
+                        versions.push(version.to_owned());
+                    }
+                }
+                Some(locked)
+            }
+        };
+
+        Ok(Versions {
+            dependencies,
+            locked,
+        })
+    }
+
+    fn dependency(&self, name: &str) -> Option<&Dependency> {
+        self.dependencies.iter().find(|d| d.name == name)
+    }
+
+    // The version `Cargo.lock` picked for a dependency of `Cargo.toml`
+    fn locked(&self, dependency: &Dependency) -> Option<&str> {
+        let versions = self.locked.as_ref()?.get(&dependency.name)?;
+        versions
+            .iter()
+            .filter(|v| satisfies(&dependency.version, v))
+            .max_by_key(|v| numbers(v))
+            .map(String::as_str)
+    }
+}
+
+// Replace `{{version:name}}` by the version `Cargo.toml` asks for and `{{locked:name}}` by
+// the one `Cargo.lock` picked
+pub fn expand_versions(file: &Path, content: &str, versions: &Versions) -> Result<String, Error> {
+    if !content.contains("{{") {
+        return Ok(content.to_owned());
+    }
+
+    let mut lines = vec![];
+    for (i, line) in content.lines().enumerate() {
+        let mut expanded = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}").map(|e| start + e) else {
+                break;
+            };
+            let variable = &rest[start + 2..end];
+            let Some((kind @ ("version" | "locked"), name)) = variable.split_once(':') else {
+                // Not ours, like mdBook's `{{#include file}}`
+                expanded.push_str(&rest[..end + 2]);
+                rest = &rest[end + 2..];
+                continue;
+            };
+
+            let unknown = |reason: &str| {
+                let kind = ErrorKind::UnknownVersion(format!("{name} {reason}"));
+                Error::at(file, i + 1, line, kind)
+            };
+            let dependency = versions
+                .dependency(name.trim())
+                .ok_or_else(|| unknown("is not a dependency in Cargo.toml"))?;
+            let version = match kind {
+                "version" => &dependency.version,
+                _ => versions
+                    .locked(dependency)
+                    .ok_or_else(|| unknown("is not in Cargo.lock"))?,
+            };
+
+            expanded.push_str(&rest[..start]);
+            expanded.push_str(version);
+            rest = &rest[end + 2..];
+        }
+        expanded.push_str(rest);
+        lines.push(expanded);
+    }
+
+    let mut expanded = lines.join("\n");
+    if content.ends_with('\n') {
+        expanded.push('\n');
+    }
+    Ok(expanded)
+}
+
+// Report crate versions and features quoted in the book (in `toml` blocks and `:dep` lines)
+// that do not match `Cargo.toml` and `Cargo.lock`
+pub fn check_versions(root: &Path, files: &[PathBuf], index: &ExampleIndex) -> Vec<Error> {
+    let versions = match Versions::load(root) {
+        Ok(versions) => versions,
+        Err(e) => return vec![e],
+    };
+
+    let mut errors = vec![];
+    for file in files {
+        let content = match fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(e) => {
+                errors.push(Error::io(file, e));
+                continue;
+            }
+        };
+        let content = match expand_versions(file, &content, &versions) {
+            Ok(content) => content,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        for mention in markdown_mentions(&content) {
+            let line = content.lines().nth(mention.line - 1).unwrap_or_default();
+            for reason in drift(&mention, &versions) {
+                let kind = ErrorKind::VersionDrift(reason);
+                errors.push(Error::at(file, mention.line, line, kind));
+            }
+        }
+    }
+
+    for example in index.examples.values() {
+        for (i, line) in example.source.lines().enumerate() {
+            let Some(mention) = line.trim_start().strip_prefix("// ").and_then(dep_mention) else {
+                continue;
+            };
+            let mention = Mention {
+                line: i + 1,
+                ..mention
+            };
+            for reason in drift(&mention, &versions) {
+                let kind = ErrorKind::VersionDrift(reason);
+                errors.push(Error::at(&example.path, mention.line, line, kind));
+            }
+        }
+    }
+
+    errors
+}
+
+// The crates of the `toml` blocks and of the `:dep` lines of a chapter
+fn markdown_mentions(content: &str) -> Vec<Mention> {
+    let mut mentions = vec![];
+
+    let mut toml_block: Option<(usize, Vec<&str>)> = None; // First line and lines of a `toml` block
+    let mut in_fence = false;
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_fence = !in_fence;
+            match toml_block.take() {
+                Some((first, block)) => mentions.extend(toml_mentions(first, &block)),
+                None if in_fence && trimmed.trim_start_matches('`').starts_with("toml") => {
+                    toml_block = Some((i + 2, vec![]));
+                }
+                None => {}
+            }
+            continue;
+        }
+
+        if let Some((_, block)) = toml_block.as_mut() {
+            block.push(line);
+            continue;
+        }
+
+        // `:dep` lines in code blocks, or in `inline code` in the prose
+        let candidates: Vec<&str> = match in_fence {
+            true => vec![trimmed],
+            false => line.split('`').skip(1).step_by(2).collect(),
+        };
+        for candidate in candidates {
+            if let Some(mention) = dep_mention(candidate) {
+                mentions.push(Mention {
+                    line: i + 1,
+                    ..mention
+                });
+            }
+        }
+    }
+
+    mentions
+}
+
+// The dependencies of a `toml` block, with the line each one starts on
+fn toml_mentions(first: usize, block: &[&str]) -> Vec<Mention> {
+    let Ok(table) = block.join("\n").parse::<toml::Table>() else {
+        return vec![]; // Not meant to be a manifest
+    };
+    let dependencies = match table.get("dependencies") {
+        Some(toml::Value::Table(dependencies)) => dependencies,
+        _ => &table,
+    };
+
+    let mut mentions = vec![];
+    for (name, spec) in dependencies {
+        let Some((version, features)) = spec_parts(spec) else {
+            continue;
+        };
+        let offset = block
+            .iter()
+            .position(|l| {
+                l.trim_start()
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|rest| rest.trim_start().starts_with('='))
+            })
+            .unwrap_or(0);
+        mentions.push(Mention {
+            line: first + offset,
+            name: name.clone(),
+            version,
+            features,
+            complete: true,
+        });
+    }
+    mentions
+}
+
+// `:dep polars = { version = "0.52", features = ["lazy"] }`, without its line
+fn dep_mention(text: &str) -> Option<Mention> {
+    let spec = text.trim().strip_prefix(":dep ")?;
+    let table: toml::Table = spec.parse().ok()?;
+    let (name, spec) = table.into_iter().next()?;
+    let (version, features) = spec_parts(&spec)?;
+    Some(Mention {
+        line: 0,
+        name,
+        version,
+        features,
+        complete: false,
+    })
+}
+
+fn spec_parts(spec: &toml::Value) -> Option<(String, Vec<String>)> {
+    match spec {
+        toml::Value::String(version) => Some((version.clone(), vec![])),
+        toml::Value::Table(spec) => {
+            let version = spec.get("version")?.as_str()?.to_owned();
+            let features = spec
+                .get("features")
+                .and_then(toml::Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(toml::Value::as_str)
+                .map(str::to_owned)
+                .collect();
+            Some((version, features))
+        }
+        _ => None,
+    }
+}
+
+// Why a quoted crate does not match the one the examples are built with
+fn drift(mention: &Mention, versions: &Versions) -> Vec<String> {
+    let Some(dependency) = versions.dependency(&mention.name) else {
+        return vec![]; // A crate the examples do not use
+    };
+    let name = &mention.name;
+    let mut reasons = vec![];
+
+    let built_with = versions.locked(dependency).unwrap_or(&dependency.version);
+    if !satisfies(&mention.version, built_with) {
+        reasons.push(format!(
+            "{name} {} does not match {} in Cargo.toml",
+            mention.version, dependency.version
+        ));
+    }
+
+    let missing: Vec<&String> = mention
+        .features
+        .iter()
+        .filter(|f| !dependency.features.contains(f))
+        .collect();
+    let extra: Vec<&String> = dependency
+        .features
+        .iter()
+        .filter(|f| mention.complete && !mention.features.contains(f))
+        .collect();
+    if !missing.is_empty() || !extra.is_empty() {
+        reasons.push(format!(
+            "{name} features {:?} do not match {:?} in Cargo.toml",
+            mention.features, dependency.features
+        ));
+    }
+
+    // Features like `polars_0_51` must name a version that is built
+    if let Some(locked) = &versions.locked {
+        for feature in &mention.features {
+            let Some((package, version)) = versioned_feature(feature, locked) else {
+                continue;
+            };
+            if !locked[&package].iter().any(|v| satisfies(&version, v)) {
+                reasons.push(format!(
+                    "{name} feature {feature} is for {package} {version}, which is not in Cargo.lock"
+                ));
+            }
+        }
+    }
+
+    reasons
+}
+
+// `polars_0_51` is `("polars", "0.51")` if `polars` is a locked package
+fn versioned_feature(
+    feature: &str,
+    locked: &BTreeMap<String, Vec<String>>,
+) -> Option<(String, String)> {
+    let parts: Vec<&str> = feature.split('_').collect();
+    let first_number = parts.iter().position(|p| p.parse::<u64>().is_ok())?;
+    if first_number == 0
+        || !parts[first_number..]
+            .iter()
+            .all(|p| p.parse::<u64>().is_ok())
+    {
+        return None;
+    }
+
+    let package = parts[..first_number].join("_");
+    let package = [package.clone(), package.replace('_', "-")]
+        .into_iter()
+        .find(|p| locked.contains_key(p))?;
+    Some((package, parts[first_number..].join(".")))
+}
+
+// Whether a version is accepted by a Cargo requirement, such as `0.52` or `^1.2` (caret rules)
+fn satisfies(requirement: &str, version: &str) -> bool {
+    let requirement = requirement.trim();
+    let requirement = requirement.strip_prefix('^').unwrap_or(requirement);
+    let Some(required) = parse_numbers(requirement) else {
+        return true; // `*`, `>=`, `~` and the like are not checked
+    };
+    let Some(actual) = parse_numbers(version) else {
+        return false;
+    };
+
+    let at_least = |n: usize| required.get(n).copied().unwrap_or(0);
+    let actual_at = |n: usize| actual.get(n).copied().unwrap_or(0);
+    let padded: Vec<u64> = (0..3).map(at_least).collect();
+    let current: Vec<u64> = (0..3).map(actual_at).collect();
+    if current < padded {
+        return false;
+    }
+
+    // The left-most non-zero number (or the last one given) must not change
+    let fixed = required
+        .iter()
+        .position(|n| *n != 0)
+        .unwrap_or(required.len() - 1);
+    (0..=fixed).all(|n| actual_at(n) == required[n])
+}
+
+fn parse_numbers(version: &str) -> Option<Vec<u64>> {
+    let version = version.split(['-', '+']).next()?; // Without pre-release or build
+    version.split('.').map(|n| n.parse().ok()).collect()
+}
+
+fn numbers(version: &str) -> Vec<u64> {
+    parse_numbers(version).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_caret_requirements() {
+        assert!(satisfies("0.52", "0.52.0"));
+        assert!(satisfies("0.52", "0.52.3"));
+        assert!(!satisfies("0.52", "0.53.0"));
+        assert!(!satisfies("0.52.1", "0.52.0"));
+        assert!(satisfies("^1.2", "1.9.0"));
+        assert!(!satisfies("1.2", "2.0.0"));
+        assert!(satisfies("0.0.3", "0.0.3"));
+        assert!(!satisfies("0.0.3", "0.0.4"));
+        assert!(satisfies("1", "1.0.0-beta.1"));
+        // Not checked
+        assert!(satisfies(">=0.50", "0.40.0"));
+        assert!(satisfies("*", "3.0.0"));
+        assert!(!satisfies("0.52", "unknown"));
+    }
+}
@@ -1,56 +1,31 @@
 // === imports
-use std::{
-    fs::File,
-    io::{Read, Write},
-};
+use rust_data_analysis::paths;
+use std::{fs::File, io};
 
 // === main
 fn main() {
     // === program
 
     // Create directories used throughout the book
-    let _ = std::fs::remove_dir_all("./data");
-    std::fs::create_dir("./data").unwrap();
-    std::fs::create_dir("./data/raw").unwrap();
-    std::fs::create_dir("./data/codeset").unwrap();
-    std::fs::create_dir("./data/csv").unwrap();
-    std::fs::create_dir("./data/parquet").unwrap();
-    std::fs::create_dir("./data/large").unwrap();
-    std::fs::create_dir("./data/temp_data").unwrap();
-    std::fs::create_dir("./data/minio").unwrap();
-    std::fs::create_dir("./data/output").unwrap();
+    let _ = std::fs::remove_dir_all(paths::DATA);
+    for dir in paths::DIRECTORIES {
+        std::fs::create_dir(dir).unwrap();
+    }
 
     // Open ZIP file
-    let zip_file = File::open("./zip/data.zip").unwrap();
-
-    // Initiate CSV buffer
-    let mut csv_buf: Vec<u8> = Vec::new();
-
-    // Find census.csv in Zip
+    let zip_file = File::open(paths::ZIP).unwrap();
     let mut archive = zip::ZipArchive::new(zip_file).unwrap();
-    let _ = archive
-        .by_name("census.csv")
-        .unwrap()
-        .read_to_end(&mut csv_buf)
-        .unwrap();
-
-    // Write `census.csv` file
-    let mut file = std::fs::File::create("./data/raw/census.csv").unwrap();
-    file.write_all(&csv_buf).unwrap();
-
-    // Clear buffer
-    csv_buf.clear();
 
-    // Find codeset.csv in Zip
-    let _ = archive
-        .by_name("codeset.csv")
-        .unwrap()
-        .read_to_end(&mut csv_buf)
-        .unwrap();
+    // Stream `census.csv` from the ZIP to its file, without reading it all in memory
+    let mut entry = archive.by_name("census.csv").unwrap();
+    let mut file = File::create(paths::RAW_CENSUS).unwrap();
+    io::copy(&mut entry, &mut file).unwrap();
+    drop(entry);
 
-    // Write `codeset.csv` file
-    let mut file = std::fs::File::create("./data/codeset/codeset.csv").unwrap();
-    file.write_all(&csv_buf).unwrap();
+    // Stream `codeset.csv` the same way
+    let mut entry = archive.by_name("codeset.csv").unwrap();
+    let mut file = File::create(paths::CODESET).unwrap();
+    io::copy(&mut entry, &mut file).unwrap();
 
     // === end
 }
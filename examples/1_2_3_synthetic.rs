@@ -1,14 +1,13 @@
 // === imports
 use polars::prelude::*;
-use rand::prelude::*;
-use rand_chacha::ChaCha8Rng;
+use rust_data_analysis::{paths, synthetic::Synthetic};
 
 // === main
 fn main() {
     // === program
 
     // Read CSV (into memory)
-    let mut df = LazyCsvReader::new(PlPath::from_string("./data/raw/census.csv".to_string()))
+    let df = LazyCsvReader::new(PlPath::from_str(paths::RAW_CENSUS))
         .with_infer_schema_length(Some(10_000)) // Default 100, missing = String
         .with_has_header(true)
         .finish()
@@ -16,44 +15,22 @@ fn main() {
         .collect()
         .unwrap();
 
-    // Start up seeded RNG
-    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    // Read how to draw the synthetic variables (distributions, factors, seed)
+    let synthetic = Synthetic::load("./synthetic.toml").unwrap();
 
-    // Create random income vector of correct size
-    let random_income: Vec<i64> = (0..df.height())
-        .map(|_| rng.random_range(10_000..100_000))
-        .collect();
+    // Add a random `income` and `weight` to every individual (`income` is `Null` for the
+    // economically inactive)
+    let mut df = synthetic.generate(&df).unwrap();
 
-    // Create random weight vector of correct size
-    let randome_weight: Vec<i64> = (0..df.height())
-        .map(|_| rng.random_range(75..125))
-        .collect();
-
-    // Make them "Series" as required by Polars
-    let income = Series::new("income".into(), random_income);
-    let weight = Series::new("weight".into(), randome_weight);
-
-    // Add them as columns to the data
-    let df = df.with_column(income).unwrap().with_column(weight).unwrap();
-
-    // If economically non-active, put income as `Null`
-    let mut df = df
-        .clone()
-        .lazy()
-        .with_column(
-            when(col("econ").is_in(
-                lit(Series::from_iter(vec![-8, 5, 6, 7, 8, 9])).implode(),
-                false,
-            ))
-            .then(Null {}.lit())
-            .otherwise(col("income"))
-            .alias("income"),
-        )
-        .collect()
-        .unwrap();
+    // Calibrate `weight`, so that the weighted counts match the population totals, and show
+    // the totals next to the weighted counts
+    let report = synthetic.calibrate(&mut df).unwrap();
+    if let Some(report) = report {
+        println!("{report}");
+    }
 
     // Write output to CSV
-    let mut file = std::fs::File::create("./data/raw/census.csv").unwrap();
+    let mut file = std::fs::File::create(paths::RAW_CENSUS).unwrap();
     CsvWriter::new(&mut file).finish(&mut df).unwrap();
 
     // === end
@@ -1,29 +1,29 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::{panel::Panel, paths};
 
 // === main
 fn main() {
     // === program
 
-    // Read CSV
-    let lf = LazyCsvReader::new(PlPath::from_string("./data/raw/census.csv".to_string()))
+    // Read CSV (into memory)
+    let df = LazyCsvReader::new(PlPath::from_str(paths::RAW_CENSUS))
         .with_infer_schema_length(Some(10_000)) // Default 100, missing = String
         .with_has_header(true)
         .finish()
+        .unwrap()
+        .collect()
         .unwrap();
 
-    // Create 100x 1% sample
-    for chunk in 0..100 {
-        // Write csv
-        let mut file = std::fs::File::create(format!("./data/csv/census_{chunk}.csv")).unwrap();
-
-        let mut df = lf
-            .clone()
-            .with_column(lit(chunk).alias("chunk"))
-            .collect()
-            .unwrap();
+    // Each wave of the panel keeps most individuals (one year older, with a new income), loses
+    // 1% of them (4% of those 65 and over) and gains 1.5% of new ones
+    let panel = Panel::default();
 
-        CsvWriter::new(&mut file).finish(&mut df).unwrap();
+    // Create 100 waves, from the 1% sample itself (`chunk` 0)
+    for (chunk, df) in panel.waves(df).take(100).enumerate() {
+        // Write csv
+        let mut file = std::fs::File::create(paths::csv_chunk(chunk)).unwrap();
+        CsvWriter::new(&mut file).finish(&mut df.unwrap()).unwrap();
     }
     // === end
 }
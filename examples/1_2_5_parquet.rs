@@ -1,35 +1,42 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::paths;
 
 // === main
 fn main() {
     // === program
     // Get all files in path
-    let paths = std::fs::read_dir("./data/csv").unwrap();
+    let files = std::fs::read_dir(paths::CSV).unwrap();
 
     // For each file, save as Parquet
-    for path in paths {
+    for path in files {
         let path_csv = path.unwrap().path();
         let file_name = std::path::Path::new(&path_csv)
             .file_stem()
             .unwrap()
             .to_str()
             .unwrap();
-        let path_parquet = format!("./data/parquet/{file_name}.parquet");
+        let path_parquet = paths::parquet_file(file_name);
         let path_csv_string = path_csv.into_os_string().into_string().unwrap();
 
-        // Read CSV
-        let mut df = LazyCsvReader::new(PlPath::from_string(path_csv_string.clone()))
+        // Read CSV (lazily, nothing is read yet)
+        let lf = LazyCsvReader::new(PlPath::from_string(path_csv_string.clone()))
             .with_infer_schema_length(Some(10_000)) // Default 100, missing = String
             .with_has_header(true)
             .finish()
-            .unwrap()
-            .collect() // Can't collect in finish below
             .unwrap();
 
-        // Write Parquet
-        let mut file = std::fs::File::create(path_parquet).unwrap();
-        ParquetWriter::new(&mut file).finish(&mut df).unwrap();
+        // Write Parquet with the streaming engine: the CSV is read and written in batches, so
+        // that the file is never all in memory
+        lf.sink_parquet(
+            SinkTarget::Path(PlPath::from_string(path_parquet)),
+            ParquetWriteOptions::default(),
+            None,
+            SinkOptions::default(),
+        )
+        .unwrap()
+        .collect_with_engine(Engine::Streaming)
+        .unwrap();
     }
     // === end
 }
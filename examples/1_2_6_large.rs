@@ -1,16 +1,16 @@
 // === imports
 use polars::prelude::*;
-use std::fs::File;
+use rust_data_analysis::{partition::Partitioning, paths};
 
 // === main
 fn main() {
     // === program
     // Get all files in path
-    let paths = std::fs::read_dir("./data/parquet").unwrap();
+    let files = std::fs::read_dir(paths::PARQUET).unwrap();
 
     let mut lf_vec = vec![];
 
-    for path in paths {
+    for path in files {
         let parquet = path.unwrap().path().into_os_string().into_string().unwrap();
 
         let args = ScanArgsParquet::default();
@@ -26,81 +26,41 @@ fn main() {
     // Drop weight for the large file
     let lf = lf.select([all().exclude_cols(["weight"]).as_expr()]);
 
-    // Get regions
-    let regions = lf
-        .clone()
-        .select([col("region").unique()])
-        .collect()
+    // Write a large parquet file with the streaming engine (in batches, never all in memory)
+    lf.clone()
+        .sink_parquet(
+            SinkTarget::Path(PlPath::from_str(paths::LARGE_PARQUET)),
+            ParquetWriteOptions::default(),
+            None,
+            SinkOptions::default(),
+        )
         .unwrap()
-        .column("region")
-        .unwrap()
-        .str()
-        .unwrap()
-        .drop_nulls()
-        .into_no_null_iter()
-        .map(|s| s.to_string())
-        .collect::<Vec<String>>();
-
-    // Get age_group
-    let ages = lf
-        .clone()
-        .select([col("age_group").unique()])
-        .collect()
-        .unwrap()
-        .column("age_group")
-        .unwrap()
-        .i64()
-        .unwrap()
-        .to_vec_null_aware()
-        .left()
+        .collect_with_engine(Engine::Streaming)
         .unwrap();
 
-    // Ready write large parquet file by batch
-    let file = File::create("./data/large/census.parquet").unwrap();
-    let schema: Arc<Schema> = lf.clone().collect_schema().unwrap();
-    let mut pq_writer: polars::io::parquet::write::BatchedWriter<File> = ParquetWriter::new(file)
-        .set_parallel(true)
-        .batched(&schema)
+    // Write a large csv file the same way
+    lf.clone()
+        .sink_csv(
+            SinkTarget::Path(PlPath::from_str(paths::LARGE_CSV)),
+            CsvWriterOptions::default(),
+            None,
+            SinkOptions::default(),
+        )
+        .unwrap()
+        .collect_with_engine(Engine::Streaming)
         .unwrap();
 
-    // Ready write large csv file by batch
-    let file = File::create("./data/large/census.csv").unwrap();
-    let schema: Arc<Schema> = lf.clone().collect_schema().unwrap();
-    let mut csv_writer: polars::io::csv::write::BatchedWriter<File> =
-        CsvWriter::new(file).batched(&schema).unwrap();
-
-    // By region
-    for region in regions {
-        // By age group
-        for age in ages.clone() {
-            // Collect chunk in memory
-            let mut chunk_df = lf
-                .clone()
-                .filter(col("region").eq(lit(region.clone())))
-                .filter(col("age_group").eq(lit(age)))
-                .collect()
-                .unwrap();
-
-            // Write Partitioned Parquet (by region and age_group) - unstable according to the docs
-            write_partitioned_dataset(
-                &mut chunk_df,
-                PlPath::from_str("./data/large/partitioned/").as_ref(),
-                vec!["region".into(), "age_group".into()],
-                &ParquetWriteOptions::default(),
-                None,
-                4294967296,
-            )
-            .unwrap();
-
-            // Write chunk to a large parquet file and large csv file
-            pq_writer.write_batch(&chunk_df).unwrap();
-            csv_writer.write_batch(&chunk_df).unwrap();
-        }
-    }
-
-    // Finalize the writing
-    pq_writer.finish().unwrap();
-    csv_writer.finish().unwrap();
+    // Write Partitioned Parquet (by region and age_group), with the `partition` module of the
+    // shared code: one directory `region=...` with one directory `age_group=...` for each value,
+    // each with files of at most 1,000,000 rows
+    let partitioning = Partitioning {
+        keys: vec!["region".to_string(), "age_group".to_string()],
+        max_rows_per_file: Some(1_000_000),
+        row_group_size: 100_000,
+        ..Default::default()
+    };
+    let written = partitioning.write(lf, paths::PARTITIONED).unwrap();
+    println!("{written}");
 
     // === end
 }
@@ -1,9 +1,7 @@
 // === imports
-use polars::prelude::*;
-use std::{
-    env,
-    io::{Read, Write},
-};
+use rust_data_analysis::loader::{LoadOptions, Table};
+use rust_data_analysis::paths;
+use std::env;
 
 // === main
 fn main() {
@@ -16,64 +14,43 @@ fn main() {
     // Connect to postgresql
     let mut client = postgres::Client::connect(postgres_url.as_str(), postgres::NoTls).unwrap();
 
-    // Drop table if exists
-    let _ = client.batch_execute("drop TABLE census;");
-
-    // Get all variable names and types using Polars;
-    let args = ScanArgsParquet::default();
-    let mut lf =
-        LazyFrame::scan_parquet(PlPath::from_str("./data/parquet/census_0.parquet"), args).unwrap();
-
-    let cols: Vec<String> = lf
-        .collect_schema()
-        .unwrap()
-        .iter_names()
-        .map(|c| c.to_owned().to_string())
-        .collect();
-
-    let types: Vec<String> = lf
-        .collect_schema()
+    // The `census` table: `chunk` and `id` identify a row (primary key) and `region` gets an
+    // index. The columns and their types come from the Polars schema.
+    let table = Table {
+        name: "census".to_string(),
+        primary_key: vec!["chunk".to_string(), "id".to_string()],
+        indexes: vec![vec!["region".to_string()]],
+    };
+
+    // Load in `census_staging` and swap it with `census` once it is complete, so that the
+    // previous `census` can still be queried during the load. Each file is read 100,000 rows at
+    // a time and sent 1 MB at a time.
+    let options = LoadOptions {
+        staging: true,
+        batch_rows: 100_000,
+        buffer_bytes: 1 << 20,
+    };
+
+    // Get all files in path, in order
+    let mut files: Vec<_> = std::fs::read_dir(paths::PARQUET)
         .unwrap()
-        .iter_fields()
-        .map(|f| f.dtype.to_string())
+        .map(|path| path.unwrap().path())
         .collect();
-
-    // Create table string
-    let mut ct_string = String::new();
-    ct_string.push_str("CREATE TABLE census (");
-    for i in 0..cols.len() {
-        ct_string.push('"');
-        ct_string.push_str(&cols[i]);
-        ct_string.push('"');
-        if types[i] == "i64" {
-            ct_string.push_str(" int,");
-        } else if types[i] == "str" {
-            ct_string.push_str(" VARCHAR(25),");
-        } else {
-            unreachable!("You have an unaccounted for type: {}.", types[i])
-        }
-    }
-    ct_string.pop();
-    ct_string.push_str(");");
-
-    client.batch_execute(&ct_string).unwrap();
-
-    // Get all files in path
-    let paths = std::fs::read_dir("./data/csv").unwrap();
-
-    // For each file, send it to postgresql
-    for path in paths {
-        let csv = path.unwrap().path();
-
-        let mut f = std::fs::File::open(csv.clone()).unwrap();
-        let metadata = std::fs::metadata(csv).unwrap();
-        let mut buffer = vec![0; metadata.len() as usize];
-        f.read_exact(&mut buffer).unwrap();
-
-        let mut writer = client.copy_in("COPY census FROM STDIN CSV HEADER").unwrap();
-        writer.write_all(&buffer).unwrap();
-        writer.finish().unwrap();
-    }
+    files.sort();
+
+    // Create the table from the schema of the first file, with a PostgreSQL type for each Polars
+    // type, and copy every file in PostgreSQL's binary format (`COPY ... (FORMAT binary)`), all in
+    // one transaction. The indexes are created once all the rows are in, and the transaction is
+    // only committed if the table has as many rows as the files: a half-loaded table is never
+    // visible.
+    let rows = table
+        .load_files(&mut client, &files, &options, |progress| {
+            if progress.file_done() {
+                println!("{progress}");
+            }
+        })
+        .unwrap();
+    println!("Loaded {rows} rows");
 
     // === end
 }
@@ -1,3 +1,8 @@
+use rust_data_analysis::paths;
+use rust_data_analysis::sync::{SyncOptions, sync};
+use rust_data_analysis::upload::Upload;
+use std::path::Path;
+
 #[tokio::main]
 async fn main() {
     // https://docs.aws.amazon.com/sdk-for-rust/latest/dg/rust_s3_code_examples.html
@@ -39,160 +44,63 @@ async fn main() {
         .collect::<Vec<&str>>()
         .contains(&bucket);
 
-    // If exist, empty and delete
-    if bucket_exists {
-        // Get objects
-        let objects = client
-            .list_objects_v2()
+    // Create "census" bucket, if needed (it is kept between runs, so that only what changed is
+    // uploaded again)
+    if !bucket_exists {
+        let constraint = aws_sdk_s3::types::BucketLocationConstraint::from(region);
+        let cfg = aws_sdk_s3::types::CreateBucketConfiguration::builder()
+            .location_constraint(constraint)
+            .build();
+
+        let _ = client
+            .create_bucket()
+            .create_bucket_configuration(cfg)
             .bucket(bucket)
             .send()
             .await
             .unwrap();
-
-        let objects_to_delete: Vec<String> = objects
-            .contents()
-            .iter()
-            .filter_map(|obj| obj.key())
-            .map(String::from)
-            .collect();
-
-        // Delete each object
-        for object in objects_to_delete {
-            let _ = client
-                .delete_object()
-                .bucket(bucket)
-                .key(object)
-                .send()
-                .await
-                .unwrap();
-        }
-
-        // Delete bucket
-        let _ = client.delete_bucket().bucket(bucket).send().await.unwrap();
-    }
-
-    // Create "census" bucket
-    let constraint = aws_sdk_s3::types::BucketLocationConstraint::from(region);
-    let cfg = aws_sdk_s3::types::CreateBucketConfiguration::builder()
-        .location_constraint(constraint)
-        .build();
-
-    let _ = client
-        .create_bucket()
-        .create_bucket_configuration(cfg)
-        .bucket(bucket)
-        .send()
-        .await
-        .unwrap();
-
-    // Copy large Parquet
-    upload_multipart(
-        &client,
-        "./data/large/census.parquet",
-        "census.parquet",
-        bucket,
-    )
-    .await;
-
-    // Copy large CSV
-    upload_multipart(&client, "./data/large/census.csv", "census.csv", bucket).await;
-
-    // Get all the path of files in a folder (recursive)
-    fn get_file_path(path: std::path::PathBuf) -> Vec<String> {
-        let mut path_vec = vec![];
-        let paths = std::fs::read_dir(path).unwrap();
-        for path in paths {
-            let p = path.unwrap();
-            let p_meta = p.metadata().unwrap();
-            if p_meta.is_dir() {
-                path_vec.append(&mut get_file_path(p.path()));
-            } else {
-                path_vec.push(p.path().to_str().unwrap().to_string());
-            }
-        }
-
-        path_vec
-    }
-
-    // Upload files to bucket
-    for path in get_file_path(std::path::PathBuf::from("./data/large/partitioned")) {
-        let key = path.strip_prefix("./data/large/").unwrap().to_string();
-
-        upload_multipart(&client, &path, &key, bucket).await;
-    }
-}
-
-async fn upload_multipart(client: &aws_sdk_s3::Client, file: &str, key: &str, bucket: &str) {
-    let multipart_upload: aws_sdk_s3::operation::create_multipart_upload::CreateMultipartUploadOutput = client
-        .create_multipart_upload()
-        .bucket(bucket)
-        .key(key)
-        .send()
-        .await
-        .unwrap();
-
-    let upload_id = multipart_upload.upload_id().unwrap();
-
-    let path = std::path::Path::new(file);
-    let file_size = tokio::fs::metadata(path).await.unwrap().len();
-
-    let chunk_size = 1024 * 1024 * 10; // 10 MB
-    let mut chunk_count = (file_size / chunk_size) + 1;
-    let mut size_of_last_chunk = file_size % chunk_size;
-    if size_of_last_chunk == 0 {
-        size_of_last_chunk = chunk_size;
-        chunk_count -= 1;
     }
 
-    let mut upload_parts: Vec<aws_sdk_s3::types::CompletedPart> = Vec::new();
-
-    for chunk_index in 0..chunk_count {
-        let this_chunk = if chunk_count - 1 == chunk_index {
-            size_of_last_chunk
-        } else {
-            chunk_size
+    // Upload in parts of 10 MB, 4 at a time. A part that fails on a network or server error is
+    // sent again (up to 5 times, waiting 200 ms, then 400 ms, ...), any other error aborts the
+    // upload. Each part is checked against its MD5, and an unfinished upload of the same key is
+    // resumed.
+    let upload = Upload {
+        part_size: 10 * 1024 * 1024,
+        concurrency: 4,
+        retries: 5,
+        backoff_ms: 200,
+        resume: true,
+    };
+
+    // Sync: a file or directory is compared with the objects of the bucket under its key (listing
+    // every page of objects), by size and ETag. Only the new or changed files are uploaded, and
+    // the objects that are no longer local are deleted (`delete`, 1,000 keys at a time).
+    let options = SyncOptions {
+        delete: true,
+        dry_run: false,
+        upload,
+    };
+
+    // Large Parquet, large CSV and the partitioned Parquet folder
+    for (path, key) in [
+        (paths::LARGE_PARQUET, "census.parquet"),
+        (paths::LARGE_CSV, "census.csv"),
+        (paths::PARTITIONED, "partitioned"),
+    ] {
+        // A dry run only lists what would change
+        let dry_run = SyncOptions {
+            dry_run: true,
+            ..options.clone()
         };
-        let stream = aws_sdk_s3::primitives::ByteStream::read_from()
-            .path(path)
-            .offset(chunk_index * chunk_size)
-            .length(aws_sdk_s3::primitives::Length::Exact(this_chunk))
-            .build()
+        let plan = sync(&client, Path::new(path), bucket, key, &dry_run)
             .await
             .unwrap();
+        println!("{key}: would sync {plan}");
 
-        // Chunk index needs to start at 0, but part numbers start at 1.
-        let part_number = (chunk_index as i32) + 1;
-        let upload_part_res = client
-            .upload_part()
-            .key(key)
-            .bucket(bucket)
-            .upload_id(upload_id)
-            .body(stream)
-            .part_number(part_number)
-            .send()
+        let synced = sync(&client, Path::new(path), bucket, key, &options)
             .await
             .unwrap();
-
-        upload_parts.push(
-            aws_sdk_s3::types::CompletedPart::builder()
-                .e_tag(upload_part_res.e_tag.unwrap_or_default())
-                .part_number(part_number)
-                .build(),
-        );
+        println!("{key}: {synced}");
     }
-
-    let completed_multipart_upload: aws_sdk_s3::types::CompletedMultipartUpload =
-        aws_sdk_s3::types::CompletedMultipartUpload::builder()
-            .set_parts(Some(upload_parts))
-            .build();
-
-    let _ = client
-        .complete_multipart_upload()
-        .bucket(bucket)
-        .key(key)
-        .multipart_upload(completed_multipart_upload)
-        .upload_id(upload_id)
-        .send()
-        .await
-        .unwrap();
 }
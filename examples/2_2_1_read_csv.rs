@@ -1,11 +1,12 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::paths;
 
 // === main
 fn main() {
     // === block_1
     // Connect to LazyFrame (no data is brought into memory)
-    let lf = LazyCsvReader::new(PlPath::from_str("./data/large/census.csv"))
+    let lf = LazyCsvReader::new(PlPath::from_str(paths::LARGE_CSV))
         .with_has_header(true)
         .finish()
         .unwrap();
@@ -1,11 +1,12 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::paths;
 
 // === main
 fn main() {
     // === block_1
     // Read `census_0.csv` as LazyFrame
-    let lf = LazyCsvReader::new(PlPath::from_str("./data/csv/census_0.csv"))
+    let lf = LazyCsvReader::new(PlPath::from_string(paths::csv_chunk(0)))
         .with_has_header(true)
         .finish()
         .unwrap();
@@ -16,7 +17,7 @@ fn main() {
     // === block_2
 
     // Write `census_0.csv`
-    let mut file = std::fs::File::create("./data/temp_data/census_0.csv").unwrap();
+    let mut file = std::fs::File::create(paths::temp("census_0.csv")).unwrap();
     CsvWriter::new(&mut file).finish(&mut df).unwrap();
 
     // === end
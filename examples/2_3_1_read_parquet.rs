@@ -1,13 +1,13 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::paths;
 
 // === main
 fn main() {
     // === block_1
     // Connect to LazyFrame (no data is brought into memory)
     let args = ScanArgsParquet::default();
-    let lf =
-        LazyFrame::scan_parquet(PlPath::from_str("./data/large/census.parquet"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::LARGE_PARQUET), args).unwrap();
 
     // === block_end
 
@@ -17,7 +17,7 @@ fn main() {
 
     // Connect to LazyFrame (no data is brought into memory)
     let args = ScanArgsParquet::default();
-    let lf = LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // === block_3
 
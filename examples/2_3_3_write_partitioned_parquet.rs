@@ -1,31 +1,44 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::{partition::Partitioning, paths};
 
 // === main
 fn main() {
     // === block_1
 
     // Read `census_0.csv` as LazyFrame
-    let lf = LazyCsvReader::new(PlPath::from_str("./data/csv/census_0.csv"))
+    let lf = LazyCsvReader::new(PlPath::from_string(paths::csv_chunk(0)))
         .with_has_header(true)
         .finish()
         .unwrap();
 
     // Bring it into memory (by converting it to DataFrame)
-    let mut df = lf.collect().unwrap();
+    let df = lf.collect().unwrap();
 
     // === block_2
 
-    // This functionality is unstable according to the docs
-    write_partitioned_dataset(
-        &mut df,
-        PlPath::from_str("./data/temp_data/partitioned/").as_ref(),
-        vec!["region".into(), "age_group".into()],
-        &ParquetWriteOptions::default(),
-        None,
-        4294967296,
-    )
+    // Partition by `region` and `age_group`, in files of at most 1,000 rows (too small, to
+    // show the compaction below)
+    let partitioning = Partitioning {
+        keys: vec!["region".to_string(), "age_group".to_string()],
+        max_rows_per_file: Some(1_000),
+        ..Default::default()
+    };
+    let written = partitioning
+        .write(df.lazy(), paths::temp("partitioned"))
+        .unwrap();
+    println!("{written}");
+
+    // === block_3
+
+    // Merge the small files of each partition into files of at most 100 MB
+    let compaction = Partitioning {
+        max_bytes_per_file: Some(100_000_000),
+        ..Default::default()
+    }
+    .compact(paths::temp("partitioned"))
     .unwrap();
+    println!("{compaction}");
 
     // === end
 }
@@ -1,5 +1,6 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::paths;
 use tokio::runtime::Runtime;
 
 // === main
@@ -17,7 +18,7 @@ fn main() {
     // === block_2
 
     // Read file form local
-    let lf = LazyCsvReader::new(PlPath::from_str("./data/csv/census_0.csv"))
+    let lf = LazyCsvReader::new(PlPath::from_string(paths::csv_chunk(0)))
         .with_has_header(true)
         .finish()
         .unwrap();
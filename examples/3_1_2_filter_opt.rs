@@ -1,7 +1,7 @@
-use std::env;
-
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::paths;
+use std::env;
 
 // === main
 fn main() {
@@ -15,8 +15,7 @@ fn main() {
 
     // Connect to LazyFrame (one large parquet file)
     let args = ScanArgsParquet::default();
-    let lf_one =
-        LazyFrame::scan_parquet(PlPath::from_str("./data/large/census.parquet"), args).unwrap();
+    let lf_one = LazyFrame::scan_parquet(PlPath::from_str(paths::LARGE_PARQUET), args).unwrap();
 
     // Filter it
     let lf_one = lf_one
@@ -28,8 +27,7 @@ fn main() {
 
     // Connect to LazyFrame (partitioned parquet file)
     let args = ScanArgsParquet::default();
-    let lf_part =
-        LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf_part = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // Filter it
     let lf_part = lf_part
@@ -1,5 +1,6 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::paths;
 
 // === main
 fn main() {
@@ -7,8 +8,7 @@ fn main() {
 
     // Connect to LazyFrame
     let args = ScanArgsParquet::default();
-    let mut lf =
-        LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let mut lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // === block_2
 
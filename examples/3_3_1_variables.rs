@@ -1,5 +1,6 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::{codeset, paths};
 
 // === main
 fn main() {
@@ -7,7 +8,7 @@ fn main() {
 
     // Connect to LazyFrame
     let args = ScanArgsParquet::default();
-    let lf = LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // === block_2
 
@@ -93,5 +94,21 @@ fn main() {
 
     println!("{}", lf.clone().limit(5).collect().unwrap());
 
+    // === block_7
+
+    // Read the codeset of the census (`./data/codeset/codeset.csv`)
+    let codeset = codeset::census().unwrap();
+
+    // Codes of `age_group` that have no label in the codeset
+    let missing = codeset.missing_codes(lf.clone(), &["age_group"]).unwrap();
+    println!("Codes without a label: {missing:?}");
+
+    // Add the label of the age group with a join, keeping its code
+    let lf = codeset
+        .join_labels(lf, "age_group", "age_group_label")
+        .unwrap();
+
+    println!("{}", lf.clone().limit(5).collect().unwrap());
+
     // === end
 }
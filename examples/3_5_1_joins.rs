@@ -1,5 +1,6 @@
 // === imports
 use polars::prelude::*;
+use rust_data_analysis::paths;
 
 // === main
 fn main() {
@@ -9,11 +10,8 @@ fn main() {
     let mut census_chunk = vec![];
     for c in 1..6 {
         let args = ScanArgsParquet::default();
-        let lf = LazyFrame::scan_parquet(
-            PlPath::from_string(format!("./data/parquet/census_{c}.parquet")),
-            args,
-        )
-        .unwrap();
+        let lf =
+            LazyFrame::scan_parquet(PlPath::from_string(paths::parquet_chunk(c)), args).unwrap();
 
         census_chunk.push(lf);
     }
@@ -21,7 +19,7 @@ fn main() {
     // === block_2
 
     // Concatenate vertically two (or more) datasets
-    let five_percent_sample = concat(
+    let waves = concat(
         [
             census_chunk[0].clone(), // Cloned, since we need it later
             census_chunk[1].clone(),
@@ -35,14 +33,13 @@ fn main() {
 
     // === block_3
 
-    // See `chunk` going from 1 to 5
-    println!(
-        "{}",
-        five_percent_sample
-            .filter(col("chunk").eq(1))
-            .collect()
-            .unwrap()
-    );
+    // Count the rows of each `chunk` (1 to 5), rather than collecting all 3 million of them
+    let rows = waves
+        .group_by([col("chunk")])
+        .agg([len().alias("rows")])
+        .sort(["chunk"], Default::default());
+
+    println!("{}", rows.collect().unwrap());
 
     // === block_4
 
@@ -1,5 +1,6 @@
 // === imports
 use polars::prelude::{pivot::pivot_stable, *};
+use rust_data_analysis::{label_column, paths};
 
 // === main
 fn main() {
@@ -7,52 +8,25 @@ fn main() {
 
     // Connect to LazyFrame
     let args = ScanArgsParquet::default();
-    let lf = LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // Modify var
     let lf = lf
         .filter(col("keep_type").eq(lit(1))) // Usual resident
-        .filter(col("income").is_not_null())
-        .with_column(col("region").replace_strict(
-            lit(Series::from_iter(vec![
-                "E12000001",
-                "E12000002",
-                "E12000003",
-                "E12000004",
-                "E12000005",
-                "E12000006",
-                "E12000007",
-                "E12000008",
-                "E12000009",
-                "W92000004",
-            ])),
-            lit(Series::from_iter(vec![
-                "North East",
-                "North West",
-                "Yorkshire and The Humber",
-                "East Midlands",
-                "West Midlands",
-                "East of England",
-                "London",
-                "South East",
-                "South West",
-                "Wales",
-            ])),
-            None,
-            Some(DataType::String),
-        ));
+        .filter(col("income").is_not_null());
+    let lf = label_column(lf, "region").unwrap();
 
     // === block_2
 
     // Simple statistics (single point)
-    let mean_income: DataFrame = lf
+    let median_income: DataFrame = lf
         .clone()
         .select([col("income")])
         .median()
         .collect()
         .unwrap();
 
-    println!("Mean income:\n\n{mean_income}\n");
+    println!("Median income:\n\n{median_income}\n");
 
     // === block_3
 
@@ -108,20 +82,14 @@ fn main() {
 
     // Connect to LazyFrame
     let args = ScanArgsParquet::default();
-    let lf =
-        LazyFrame::scan_parquet(PlPath::from_str("./data/parquet/census_0.parquet"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_string(paths::parquet_chunk(0)), args).unwrap();
 
     // Filter and format
     let lf = lf
         .filter(col("keep_type").eq(lit(1))) // Usual resident
         .filter(col("income").is_not_null())
-        .with_column(col("sex").replace_strict(
-            lit(Series::from_iter(vec!["1", "2"])),
-            lit(Series::from_iter(vec!["Female", "Male"])),
-            None,
-            Some(DataType::String),
-        ))
         .with_column(col("weight").cast(DataType::Float64));
+    let lf = label_column(lf, "sex").unwrap();
 
     // Calculate weighted quantile
     fn weighted_quantile(col: Expr, wt: Expr, percentile: Expr) -> Expr {
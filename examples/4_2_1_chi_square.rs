@@ -1,6 +1,7 @@
 // === imports
 use hypors::chi_square::independence;
 use polars::prelude::*;
+use rust_data_analysis::{label_column, paths};
 
 // === main
 fn main() {
@@ -8,12 +9,12 @@ fn main() {
 
     // Connect to the parquet data
     let args = ScanArgsParquet::default();
-    let lf = LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // === block_2
 
     // Count individuals with fair or better health by sex and marital status
-    let df = lf
+    let counts = lf
         .filter(col("keep_type").eq(lit(1))) // Usual resident
         .filter(col("health").neq(lit(-8)))
         .filter(col("mar_stat").neq(lit(-8)))
@@ -24,13 +25,11 @@ fn main() {
             .cast(DataType::Int8)
             .sum()
             .alias("health_flag")])
-        .sort(["sex", "mar_stat"], Default::default())
-        .with_column(col("sex").replace_strict(
-            lit(Series::from_iter(vec!["1", "2"])),
-            lit(Series::from_iter(vec!["Female", "Male"])),
-            None,
-            Some(DataType::String),
-        ))
+        .sort(["sex", "mar_stat"], Default::default());
+
+    // Label sex (from the codeset) and marital status
+    let df = label_column(counts, "sex")
+        .unwrap()
         .with_column(col("mar_stat").replace_strict(
             lit(Series::from_iter(vec![1, 2, 3, 4, 5])),
             lit(Series::from_iter(vec![
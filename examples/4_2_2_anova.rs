@@ -1,6 +1,7 @@
 // === imports
 use hypors::anova::anova;
 use polars::prelude::*;
+use rust_data_analysis::paths;
 
 // === main
 fn main() {
@@ -8,7 +9,7 @@ fn main() {
 
     // Connect to LazyFrame
     let args = ScanArgsParquet::default();
-    let lf = LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // Income by economically active type (in London)
     let df = lf
@@ -2,6 +2,7 @@
 use hypors::common::types::TailType;
 use hypors::mann_whitney::u_test;
 use polars::prelude::*;
+use rust_data_analysis::{label_column, paths};
 
 // === main
 fn main() {
@@ -9,23 +10,17 @@ fn main() {
 
     // Connect to LazyFrame
     let args = ScanArgsParquet::default();
-    let lf = LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // Income by sex (in London)
-    let df = lf
+    let london = lf
         .clone()
         .filter(col("keep_type").eq(lit(1))) // Usual resident
         .filter(col("region").eq(lit("E12000007"))) // London
         .filter(col("income").is_null().not())
-        .select([
-            col("sex").replace_strict(
-                lit(Series::from_iter(vec!["1", "2"])),
-                lit(Series::from_iter(vec!["Female", "Male"])),
-                None,
-                Some(DataType::String),
-            ),
-            col("income"),
-        ])
+        .select([col("sex"), col("income")]);
+    let df = label_column(london, "sex")
+        .unwrap()
         .with_row_index("index", None)
         .collect()
         .unwrap();
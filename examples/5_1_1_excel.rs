@@ -2,6 +2,7 @@
 use polars::prelude::pivot::pivot_stable;
 use polars::prelude::*;
 use polars_excel_writer::PolarsExcelWriter;
+use rust_data_analysis::{label_column, paths};
 use rust_xlsxwriter::{Chart, ChartLegendPosition, ChartType, Workbook};
 
 // === main
@@ -10,7 +11,7 @@ fn main() {
 
     // Connect to LazyFrame
     let args = ScanArgsParquet::default();
-    let lf = LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // Filter
     let lf: LazyFrame = lf
@@ -18,39 +19,13 @@ fn main() {
         .filter(col("income").is_not_null());
 
     // Mean income by region and economic activity type
-    let df_long = lf
+    let means = lf
         .clone()
         .group_by([col("region"), col("econ")])
         .agg([col("income").mean().round(2, RoundMode::HalfAwayFromZero)])
-        .sort(["region", "econ"], Default::default())
-        .with_column(col("region").replace_strict(
-            lit(Series::from_iter(vec![
-                "E12000001",
-                "E12000002",
-                "E12000003",
-                "E12000004",
-                "E12000005",
-                "E12000006",
-                "E12000007",
-                "E12000008",
-                "E12000009",
-                "W92000004",
-            ])),
-            lit(Series::from_iter(vec![
-                "North East",
-                "North West",
-                "Yorkshire and The Humber",
-                "East Midlands",
-                "West Midlands",
-                "East of England",
-                "London",
-                "South East",
-                "South West",
-                "Wales",
-            ])),
-            None,
-            Some(DataType::String),
-        ))
+        .sort(["region", "econ"], Default::default());
+    let df_long = label_column(means, "region")
+        .unwrap()
         .with_column(col("econ").replace_strict(
             lit(Series::from_iter(vec![1, 2, 3, 4])),
             lit(Series::from_iter(vec![
@@ -148,7 +123,7 @@ fn main() {
     // === block_5
 
     // Save the file to disk.
-    workbook.save("./data/output/income.xlsx").unwrap();
+    workbook.save(paths::output("income.xlsx")).unwrap();
 
     // === end
 }
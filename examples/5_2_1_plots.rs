@@ -2,6 +2,7 @@
 // === imports
 use plotlars::{Axis, BarPlot, Legend, LinePlot, Orientation, Plot, Rgb, Text};
 use polars::prelude::{pivot::pivot_stable, *};
+use rust_data_analysis::{label_column, paths};
 
 // === main
 fn main() {
@@ -9,7 +10,7 @@ fn main() {
 
     // Connect to LazyFrame
     let args = ScanArgsParquet::default();
-    let lf = LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // Modify var
     let lf = lf
@@ -17,47 +18,13 @@ fn main() {
         .filter(col("income").is_not_null());
 
     // Mean income by region and sex
-    let df_bar = lf
+    let means = lf
         .clone()
         .group_by([col("sex"), col("region")])
         .agg([col("income").mean().round(2, RoundMode::HalfAwayFromZero)])
-        .sort(["sex", "region"], SortMultipleOptions::new())
-        .with_column(col("region").replace_strict(
-            lit(Series::from_iter(vec![
-                "E12000001",
-                "E12000002",
-                "E12000003",
-                "E12000004",
-                "E12000005",
-                "E12000006",
-                "E12000007",
-                "E12000008",
-                "E12000009",
-                "W92000004",
-            ])),
-            lit(Series::from_iter(vec![
-                "North East",
-                "North West",
-                "Yorkshire and The Humber",
-                "East Midlands",
-                "West Midlands",
-                "East of England",
-                "London",
-                "South East",
-                "South West",
-                "Wales",
-            ])),
-            None,
-            Some(DataType::String),
-        ))
-        .with_column(col("sex").replace_strict(
-            lit(Series::from_iter(vec!["1", "2"])),
-            lit(Series::from_iter(vec!["Female", "Male"])),
-            None,
-            Some(DataType::String),
-        ))
-        .collect()
-        .unwrap();
+        .sort(["sex", "region"], SortMultipleOptions::new());
+    let means = label_column(means, "region").unwrap();
+    let df_bar = label_column(means, "sex").unwrap().collect().unwrap();
 
     println!("{df_bar}");
 
@@ -95,26 +62,19 @@ fn main() {
 
     // === block_3
 
-    let mut file = std::fs::File::create("./data/output/bar.html").unwrap();
+    let mut file = std::fs::File::create(paths::output("bar.html")).unwrap();
     std::io::Write::write_all(&mut file, html.as_bytes()).unwrap();
 
     // === block_4
 
     // Mean income by sex and hours worked (groupped)
-    let df_line = lf
+    let means = lf
         .clone()
         .filter(col("hours_worked").neq(lit(-8)))
         .group_by([col("sex"), col("hours_worked")])
         .agg([col("income").mean().round(2, RoundMode::HalfAwayFromZero)])
-        .sort(["sex", "hours_worked"], SortMultipleOptions::new())
-        .with_column(col("sex").replace_strict(
-            lit(Series::from_iter(vec!["1", "2"])),
-            lit(Series::from_iter(vec!["Female", "Male"])),
-            None,
-            Some(DataType::String),
-        ))
-        .collect()
-        .unwrap();
+        .sort(["sex", "hours_worked"], SortMultipleOptions::new());
+    let df_line = label_column(means, "sex").unwrap().collect().unwrap();
 
     // Pivot to make two lines out of sex values
     let df_line = pivot_stable(
@@ -164,7 +124,7 @@ fn main() {
 
     // === block_6
 
-    let mut file = std::fs::File::create("./data/output/line.html").unwrap();
+    let mut file = std::fs::File::create(paths::output("line.html")).unwrap();
     std::io::Write::write_all(&mut file, html.as_bytes()).unwrap();
 
     // === end
@@ -7,12 +7,13 @@ use comrak::{Options, markdown_to_html};
 // use df_interchange::Interchange; (currently unecessary)
 use plotlars::{BarPlot, Legend, Orientation, Plot, Rgb, Text};
 use polars::prelude::{pivot::pivot_stable, *};
+use rust_data_analysis::{label_column, paths};
 
 // === main
 fn main() {
     // === block_1
 
-    let mut file = File::create("./data/output/report.html").unwrap();
+    let mut file = File::create(paths::output("report.html")).unwrap();
 
     // === block_2
 
@@ -77,7 +78,7 @@ Maecenas posuere tellus porttitor turpis pulvinar luctus. Nunc lacinia suscipit
 
     // Connect to LazyFrame
     let args = ScanArgsParquet::default();
-    let lf = LazyFrame::scan_parquet(PlPath::from_str("./data/large/partitioned"), args).unwrap();
+    let lf = LazyFrame::scan_parquet(PlPath::from_str(paths::PARTITIONED), args).unwrap();
 
     // Filter
     let lf: LazyFrame = lf
@@ -85,47 +86,13 @@ Maecenas posuere tellus porttitor turpis pulvinar luctus. Nunc lacinia suscipit
         .filter(col("income").is_not_null());
 
     // Mean income by sex and region
-    let df_bar = lf
+    let means = lf
         .clone()
         .group_by([col("sex"), col("region")])
         .agg([col("income").mean().round(2, RoundMode::HalfAwayFromZero)])
-        .sort(["sex", "region"], SortMultipleOptions::new())
-        .with_column(col("region").replace_strict(
-            lit(Series::from_iter(vec![
-                "E12000001",
-                "E12000002",
-                "E12000003",
-                "E12000004",
-                "E12000005",
-                "E12000006",
-                "E12000007",
-                "E12000008",
-                "E12000009",
-                "W92000004",
-            ])),
-            lit(Series::from_iter(vec![
-                "North East",
-                "North West",
-                "Yorkshire and The Humber",
-                "East Midlands",
-                "West Midlands",
-                "East of England",
-                "London",
-                "South East",
-                "South West",
-                "Wales",
-            ])),
-            None,
-            Some(DataType::String),
-        ))
-        .with_column(col("sex").replace_strict(
-            lit(Series::from_iter(vec!["1", "2"])),
-            lit(Series::from_iter(vec!["Female", "Male"])),
-            None,
-            Some(DataType::String),
-        ))
-        .collect()
-        .unwrap();
+        .sort(["sex", "region"], SortMultipleOptions::new());
+    let means = label_column(means, "region").unwrap();
+    let df_bar = label_column(means, "sex").unwrap().collect().unwrap();
 
     // Pivot on region
     let df_bar_trans = pivot_stable(
@@ -177,7 +144,7 @@ Maecenas posuere tellus porttitor turpis pulvinar luctus. Nunc lacinia suscipit
                 .x(0.37),
         )
         .build()
-        .write_image("./data/output/out.png", 1000, 600, 1.0)
+        .write_image(paths::output("out.png"), 1000, 600, 1.0)
         .unwrap();
 
     markdown.push_str("![alt text](out.png) \n");
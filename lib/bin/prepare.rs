@@ -0,0 +1,43 @@
+// Create the data of the book from `prepare.toml`. Run with `cargo run -r --bin prepare`:
+//
+// * `prepare sql s3` runs these optional steps, and the steps they depend on
+// * `--all` also runs the optional steps, `--force` runs the steps even when up to date
+// * `--dry-run` only lists what would run, `--config file.toml` reads another pipeline
+
+use rust_data_analysis::prepare::{self, Options, Pipeline};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut config = PathBuf::from("prepare.toml");
+    let mut options = Options::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--all" => options.all = true,
+            "--force" => options.force = true,
+            "--dry-run" => options.dry_run = true,
+            "--config" => match args.next() {
+                Some(path) => config = PathBuf::from(path),
+                None => {
+                    eprintln!("--config needs a file");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => options.steps.push(arg),
+        }
+    }
+
+    let result = Pipeline::load(&config).and_then(|pipeline| prepare::run(&pipeline, &options));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            if e.step.is_some() {
+                eprintln!("Run it again to resume from this step");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,569 @@
+use polars::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+// Adjust survey weights so that the weighted counts of a sample match known population totals
+// (e.g. by `region` and `sex`), by raking (iterative proportional fitting) or by GREG (the
+// generalized regression estimator, with a linear distance)
+#[derive(Debug, Deserialize)]
+pub struct Calibration {
+    #[serde(default)]
+    pub method: Method,
+    #[serde(default = "default_weight")]
+    pub weight: String, // The starting weights, replaced by the calibrated ones
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64, // Largest difference between a weighted count and its total (relative)
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: usize,
+    #[serde(rename = "margin")]
+    pub margins: Vec<Margin>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    #[default]
+    Raking,
+    Greg,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Method::Raking => write!(f, "Raking"),
+            Method::Greg => write!(f, "GREG"),
+        }
+    }
+}
+
+// The population totals of every combination of some columns, either from a CSV (with the
+// columns and a `total` column) or from the number of rows of the sample, times `scale`
+#[derive(Debug, Deserialize)]
+pub struct Margin {
+    pub columns: Vec<String>,
+    pub controls: Option<String>,
+    pub scale: Option<f64>,
+}
+
+fn default_weight() -> String {
+    "weight".to_owned()
+}
+
+fn default_tolerance() -> f64 {
+    1e-6
+}
+
+fn default_max_iterations() -> usize {
+    100
+}
+
+fn error(reason: impl Into<String>) -> PolarsError {
+    PolarsError::ComputeError(format!("calibration: {}", reason.into()).into())
+}
+
+// How the calibration went, and the weighted count of every group next to its total
+#[derive(Debug)]
+pub struct Report {
+    pub method: Method,
+    pub iterations: usize,
+    pub largest_difference: f64, // Relative to the total
+    pub negative_weights: usize, // GREG can give negative weights
+    pub margins: Vec<DataFrame>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} converged in {} iteration(s), largest difference {:.2e} ({} negative weights)",
+            self.method, self.iterations, self.largest_difference, self.negative_weights
+        )?;
+        for margin in &self.margins {
+            writeln!(f, "\n{margin}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Calibration {
+    // Replace the weight column by the calibrated weights
+    pub fn apply(&self, df: &mut DataFrame) -> PolarsResult<Report> {
+        let (weights, report) = self.calibrate(df)?;
+        df.with_column(Column::new(self.weight.as_str().into(), weights))?;
+        Ok(report)
+    }
+
+    // The calibrated weights, without changing the data
+    pub fn calibrate(&self, df: &DataFrame) -> PolarsResult<(Vec<f64>, Report)> {
+        let weights: Vec<f64> = df
+            .column(&self.weight)?
+            .cast(&DataType::Float64)?
+            .f64()?
+            .iter()
+            .map(|w| w.filter(|w| *w > 0.0))
+            .collect::<Option<_>>()
+            .ok_or_else(|| error(format!("every {} must be above 0", self.weight)))?;
+        if self.margins.is_empty() {
+            return Err(error("no margin to calibrate to"));
+        }
+
+        let groups = self
+            .margins
+            .iter()
+            .map(|m| m.groups(df))
+            .collect::<PolarsResult<Vec<_>>>()?;
+
+        // Raking can only converge if every margin describes the same population
+        let population: f64 = groups[0].targets.iter().sum();
+        for (margin, g) in self.margins.iter().zip(&groups) {
+            let total: f64 = g.targets.iter().sum();
+            if (total - population).abs() > self.tolerance * population {
+                let reason = format!(
+                    "the totals by {} add up to {total}, not {population}",
+                    margin.columns.join(", ")
+                );
+                return Err(error(reason));
+            }
+        }
+
+        let (weights, iterations) = match self.method {
+            Method::Raking => self.rake(&groups, weights)?,
+            Method::Greg => (greg(&groups, &weights), 1),
+        };
+
+        let largest_difference = largest_difference(&groups, &weights);
+        if largest_difference > self.tolerance {
+            let reason = format!(
+                "{} is {largest_difference:.2e} away from the totals after {iterations} iteration(s)",
+                self.method
+            );
+            return Err(error(reason));
+        }
+
+        let report = Report {
+            method: self.method,
+            iterations,
+            largest_difference,
+            negative_weights: weights.iter().filter(|w| **w < 0.0).count(),
+            margins: groups
+                .iter()
+                .map(|g| g.report(&weights))
+                .collect::<PolarsResult<_>>()?,
+        };
+        Ok((weights, report))
+    }
+
+    // Scale the weights of each group to its total, one margin after the other, until every
+    // margin matches at the same time
+    fn rake(&self, groups: &[Groups], mut weights: Vec<f64>) -> PolarsResult<(Vec<f64>, usize)> {
+        for iteration in 1..=self.max_iterations {
+            for g in groups {
+                let sums = g.sums(&weights);
+                for (weight, group) in weights.iter_mut().zip(&g.rows) {
+                    *weight *= g.targets[*group] / sums[*group];
+                }
+            }
+            if largest_difference(groups, &weights) <= self.tolerance {
+                return Ok((weights, iteration));
+            }
+        }
+        let reason = format!(
+            "raking did not converge in {} iterations (largest difference {:.2e})",
+            self.max_iterations,
+            largest_difference(groups, &weights)
+        );
+        Err(error(reason))
+    }
+}
+
+// A margin matched to the data: the group of every row and the total of every group
+struct Groups {
+    columns: Vec<String>,
+    keys: Vec<Vec<String>>, // The values of the columns, for each group
+    targets: Vec<f64>,
+    rows: Vec<usize>,
+}
+
+impl Margin {
+    fn groups(&self, df: &DataFrame) -> PolarsResult<Groups> {
+        let mut values = vec![];
+        for column in &self.columns {
+            values.push(df.column(column)?.cast(&DataType::String)?.str()?.clone());
+        }
+        let row_key = |row: usize| -> Vec<String> {
+            values
+                .iter()
+                .map(|v| v.get(row).unwrap_or("null").to_owned())
+                .collect()
+        };
+        let describe = |key: &[String]| -> String {
+            self.columns
+                .iter()
+                .zip(key)
+                .map(|(c, v)| format!("{c} = {v}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut index: HashMap<Vec<String>, usize> = HashMap::new();
+        let mut keys = vec![];
+        let mut targets = vec![];
+        let mut rows = Vec::with_capacity(df.height());
+
+        match (&self.controls, self.scale) {
+            (Some(path), None) => {
+                let controls = LazyCsvReader::new(PlPath::from_str(path))
+                    .with_infer_schema_length(Some(0)) // Codes as text
+                    .with_has_header(true)
+                    .finish()?
+                    .collect()?;
+                let mut control_values = vec![];
+                for column in &self.columns {
+                    control_values.push(controls.column(column)?.str()?.clone());
+                }
+                let totals = controls.column("total")?.cast(&DataType::Float64)?;
+                let totals = totals.f64()?;
+                for row in 0..controls.height() {
+                    let key: Vec<String> = control_values
+                        .iter()
+                        .map(|v| v.get(row).unwrap_or("null").to_owned())
+                        .collect();
+                    let total = totals
+                        .get(row)
+                        .ok_or_else(|| error(format!("{path}: no total for {}", describe(&key))))?;
+                    index.insert(key.clone(), keys.len());
+                    keys.push(key);
+                    targets.push(total);
+                }
+                for row in 0..df.height() {
+                    let key = row_key(row);
+                    let group = index
+                        .get(&key)
+                        .ok_or_else(|| error(format!("{path}: no total for {}", describe(&key))))?;
+                    rows.push(*group);
+                }
+            }
+            (None, Some(scale)) => {
+                for row in 0..df.height() {
+                    let key = row_key(row);
+                    let group = *index.entry(key.clone()).or_insert_with(|| {
+                        keys.push(key);
+                        targets.push(0.0);
+                        targets.len() - 1
+                    });
+                    targets[group] += scale;
+                    rows.push(group);
+                }
+            }
+            _ => {
+                let reason = format!(
+                    "the margin by {} needs either `controls` or `scale`",
+                    self.columns.join(", ")
+                );
+                return Err(error(reason));
+            }
+        }
+
+        if let Some(zero) = targets.iter().position(|t| *t <= 0.0) {
+            let reason = format!("the total of {} must be above 0", describe(&keys[zero]));
+            return Err(error(reason));
+        }
+
+        // A total without anyone in the sample can not be reached
+        let mut counts = vec![0_usize; keys.len()];
+        for group in &rows {
+            counts[*group] += 1;
+        }
+        if let Some(empty) = counts.iter().position(|c| *c == 0) {
+            let reason = format!("no one in the data has {}", describe(&keys[empty]));
+            return Err(error(reason));
+        }
+
+        Ok(Groups {
+            columns: self.columns.clone(),
+            keys,
+            targets,
+            rows,
+        })
+    }
+}
+
+impl Groups {
+    fn sums(&self, weights: &[f64]) -> Vec<f64> {
+        let mut sums = vec![0.0; self.targets.len()];
+        for (weight, group) in weights.iter().zip(&self.rows) {
+            sums[*group] += weight;
+        }
+        sums
+    }
+
+    fn report(&self, weights: &[f64]) -> PolarsResult<DataFrame> {
+        let mut columns = vec![];
+        for (i, name) in self.columns.iter().enumerate() {
+            let values: Vec<&str> = self.keys.iter().map(|k| k[i].as_str()).collect();
+            columns.push(Column::new(name.as_str().into(), values));
+        }
+        let achieved = self.sums(weights);
+        let difference: Vec<f64> = achieved
+            .iter()
+            .zip(&self.targets)
+            .map(|(a, t)| a - t)
+            .collect();
+        columns.push(Column::new("total".into(), &self.targets));
+        columns.push(Column::new("achieved".into(), achieved));
+        columns.push(Column::new("difference".into(), difference));
+
+        let names: Vec<PlSmallStr> = self.columns.iter().map(|c| c.as_str().into()).collect();
+        DataFrame::new(columns)?.sort(names, SortMultipleOptions::default())
+    }
+}
+
+fn largest_difference(groups: &[Groups], weights: &[f64]) -> f64 {
+    groups
+        .iter()
+        .flat_map(|g| {
+            g.sums(weights)
+                .into_iter()
+                .zip(&g.targets)
+                .map(|(sum, target)| (sum - target).abs() / target)
+                .collect::<Vec<_>>()
+        })
+        .fold(0.0, f64::max)
+}
+
+// The weights closest to the starting ones (in a chi-square sense) that match every total:
+// `w = d * (1 + x'λ)`, with `x` the groups of the row and `λ` the solution of
+// `(Σ d x x') λ = totals - Σ d x`
+fn greg(groups: &[Groups], weights: &[f64]) -> Vec<f64> {
+    let mut offsets = vec![];
+    let mut size = 0;
+    for g in groups {
+        offsets.push(size);
+        size += g.targets.len();
+    }
+
+    let mut matrix = vec![vec![0.0; size]; size];
+    let mut difference: Vec<f64> = groups.iter().flat_map(|g| g.targets.clone()).collect();
+    let row_groups = |row: usize| -> Vec<usize> {
+        groups
+            .iter()
+            .zip(&offsets)
+            .map(|(g, offset)| offset + g.rows[row])
+            .collect()
+    };
+
+    for (row, weight) in weights.iter().enumerate() {
+        let x = row_groups(row);
+        for a in &x {
+            difference[*a] -= weight;
+            for b in &x {
+                matrix[*a][*b] += weight;
+            }
+        }
+    }
+
+    let lambda = solve(matrix, difference);
+    weights
+        .iter()
+        .enumerate()
+        .map(|(row, weight)| {
+            let adjustment: f64 = row_groups(row).iter().map(|g| lambda[*g]).sum();
+            weight * (1.0 + adjustment)
+        })
+        .collect()
+}
+
+// Solve `a x = b` by Gauss-Jordan elimination. The groups of different margins overlap (they
+// all add up to the population), so `a` is singular: the variables without a pivot are set to
+// 0, which gives one of the solutions.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    let largest = (0..n).map(|i| a[i][i].abs()).fold(0.0, f64::max);
+    let epsilon = largest * 1e-12;
+
+    let mut pivots = vec![];
+    let mut row = 0;
+    for col in 0..n {
+        if row == n {
+            break;
+        }
+        let pivot = (row..n)
+            .max_by(|x, y| a[*x][col].abs().total_cmp(&a[*y][col].abs()))
+            .unwrap_or(row);
+        if a[pivot][col].abs() <= epsilon {
+            continue;
+        }
+        a.swap(row, pivot);
+        b.swap(row, pivot);
+
+        let pivot_row = a[row].clone();
+        for r in (0..n).filter(|r| *r != row) {
+            let factor = a[r][col] / pivot_row[col];
+            if factor != 0.0 {
+                for (value, p) in a[r][col..].iter_mut().zip(&pivot_row[col..]) {
+                    *value -= factor * p;
+                }
+                b[r] -= factor * b[row];
+            }
+        }
+        pivots.push((row, col));
+        row += 1;
+    }
+
+    let mut x = vec![0.0; n];
+    for (row, col) in pivots {
+        x[col] = b[row] / a[row][col];
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Six people, unevenly spread over `sex` and `region`, each with a weight of 1
+    fn sample() -> DataFrame {
+        df!(
+            "sex" => [1, 1, 2, 2, 2, 1],
+            "region" => ["a", "b", "a", "b", "a", "b"],
+            "weight" => [1.0; 6],
+        )
+        .unwrap()
+    }
+
+    // A control total CSV, in the temporary directory
+    fn controls(name: &str, csv: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("calibration_{name}_{}.csv", std::process::id()));
+        std::fs::write(&path, csv).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn calibration(method: Method, margins: Vec<(&str, String)>) -> Calibration {
+        Calibration {
+            method,
+            weight: default_weight(),
+            tolerance: default_tolerance(),
+            max_iterations: default_max_iterations(),
+            margins: margins
+                .into_iter()
+                .map(|(column, controls)| Margin {
+                    columns: vec![column.to_owned()],
+                    controls: Some(controls),
+                    scale: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn weighted_counts(df: &DataFrame, weights: &[f64], column: &str) -> Vec<(String, f64)> {
+        let values = df.column(column).unwrap().cast(&DataType::String).unwrap();
+        let mut counts: Vec<(String, f64)> = vec![];
+        for (value, weight) in values.str().unwrap().iter().zip(weights) {
+            let value = value.unwrap().to_owned();
+            match counts.iter_mut().find(|(v, _)| *v == value) {
+                Some((_, count)) => *count += weight,
+                None => counts.push((value, *weight)),
+            }
+        }
+        counts.sort_by(|a, b| a.0.cmp(&b.0));
+        counts
+    }
+
+    fn assert_totals(df: &DataFrame, weights: &[f64], column: &str, totals: &[(&str, f64)]) {
+        for ((value, count), (expected, total)) in
+            weighted_counts(df, weights, column).iter().zip(totals)
+        {
+            assert_eq!(value, expected);
+            assert!(
+                (count - total).abs() < 1e-6 * total,
+                "{column} = {value}: {count}, not {total}"
+            );
+        }
+    }
+
+    #[test]
+    fn raking_converges_to_every_margin() {
+        let sex = controls("rake_sex", "sex,total\n1,60\n2,40\n");
+        let region = controls("rake_region", "region,total\na,30\nb,70\n");
+        let calibration = calibration(Method::Raking, vec![("sex", sex), ("region", region)]);
+        let df = sample();
+        let (weights, report) = calibration.calibrate(&df).unwrap();
+
+        assert!(report.iterations > 1); // The margins pull against each other
+        assert!(report.largest_difference <= calibration.tolerance);
+        assert_eq!(report.negative_weights, 0);
+        assert_totals(&df, &weights, "sex", &[("1", 60.0), ("2", 40.0)]);
+        assert_totals(&df, &weights, "region", &[("a", 30.0), ("b", 70.0)]);
+    }
+
+    #[test]
+    fn raking_stops_after_max_iterations() {
+        let sex = controls("stop_sex", "sex,total\n1,60\n2,40\n");
+        let region = controls("stop_region", "region,total\na,30\nb,70\n");
+        let mut calibration = calibration(Method::Raking, vec![("sex", sex), ("region", region)]);
+        calibration.max_iterations = 1;
+        let error = calibration.calibrate(&sample()).unwrap_err().to_string();
+        assert!(
+            error.contains("raking did not converge in 1 iterations"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn margins_must_have_the_same_total() {
+        let sex = controls("mismatch_sex", "sex,total\n1,60\n2,40\n");
+        let region = controls("mismatch_region", "region,total\na,30\nb,60\n");
+        let calibration = calibration(Method::Raking, vec![("sex", sex), ("region", region)]);
+        let error = calibration.calibrate(&sample()).unwrap_err().to_string();
+        assert!(
+            error.contains("the totals by region add up to 90, not 100"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn every_group_needs_a_total() {
+        let sex = controls("missing_sex", "sex,total\n1,100\n");
+        let calibration = calibration(Method::Raking, vec![("sex", sex)]);
+        let error = calibration.calibrate(&sample()).unwrap_err().to_string();
+        assert!(error.contains("no total for sex = 2"), "{error}");
+    }
+
+    // The groups of the two margins both add up to everyone, so the GREG system is singular
+    #[test]
+    fn greg_matches_overlapping_margins() {
+        let sex = controls("greg_sex", "sex,total\n1,60\n2,40\n");
+        let region = controls("greg_region", "region,total\na,30\nb,70\n");
+        let calibration = calibration(Method::Greg, vec![("sex", sex), ("region", region)]);
+        let df = sample();
+        let (weights, report) = calibration.calibrate(&df).unwrap();
+
+        assert_eq!(report.iterations, 1);
+        assert_totals(&df, &weights, "sex", &[("1", 60.0), ("2", 40.0)]);
+        assert_totals(&df, &weights, "region", &[("a", 30.0), ("b", 70.0)]);
+    }
+
+    #[test]
+    fn solve_gives_a_solution_of_a_singular_system() {
+        // The third row is the sum of the first two
+        let a = vec![
+            vec![2.0, 1.0, 0.0],
+            vec![1.0, 3.0, 1.0],
+            vec![3.0, 4.0, 1.0],
+        ];
+        let b = vec![3.0, 5.0, 8.0];
+        let x = solve(a.clone(), b.clone());
+        for (row, expected) in a.iter().zip(&b) {
+            let value: f64 = row.iter().zip(&x).map(|(a, x)| a * x).sum();
+            assert!((value - expected).abs() < 1e-9, "{value}, not {expected}");
+        }
+        assert_eq!(x[2], 0.0); // No pivot left for the last variable
+
+        assert_eq!(
+            solve(vec![vec![4.0, 0.0], vec![0.0, 2.0]], vec![8.0, 1.0]),
+            [2.0, 0.5]
+        );
+    }
+}
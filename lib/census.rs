@@ -0,0 +1,139 @@
+use polars::prelude::*;
+
+// A column of the census, with the short name `1_2_2_rename` gives it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Variable {
+    Id,
+    Social,
+    Birth,
+    Econ,
+    Ethnic,
+    Health,
+    FamType,
+    HoursWorked,
+    Education,
+    Industry,
+    London,
+    MarStat,
+    Occupation,
+    Region,
+    Religion,
+    ResidenceType,
+    AgeGroup,
+    Sex,
+    KeepType,
+    // Added by `1_2_3_synthetic` and `1_2_4_expand`
+    Income,
+    Weight,
+    Chunk,
+}
+
+impl Variable {
+    // Every column, in the order of the files
+    pub const ALL: [Variable; 22] = [
+        Variable::Id,
+        Variable::Social,
+        Variable::Birth,
+        Variable::Econ,
+        Variable::Ethnic,
+        Variable::Health,
+        Variable::FamType,
+        Variable::HoursWorked,
+        Variable::Education,
+        Variable::Industry,
+        Variable::London,
+        Variable::MarStat,
+        Variable::Occupation,
+        Variable::Region,
+        Variable::Religion,
+        Variable::ResidenceType,
+        Variable::AgeGroup,
+        Variable::Sex,
+        Variable::KeepType,
+        Variable::Income,
+        Variable::Weight,
+        Variable::Chunk,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Variable::Id => "id",
+            Variable::Social => "social",
+            Variable::Birth => "birth",
+            Variable::Econ => "econ",
+            Variable::Ethnic => "ethnic",
+            Variable::Health => "health",
+            Variable::FamType => "fam_type",
+            Variable::HoursWorked => "hours_worked",
+            Variable::Education => "education",
+            Variable::Industry => "industry",
+            Variable::London => "london",
+            Variable::MarStat => "mar_stat",
+            Variable::Occupation => "occupation",
+            Variable::Region => "region",
+            Variable::Religion => "religion",
+            Variable::ResidenceType => "residence_type",
+            Variable::AgeGroup => "age_group",
+            Variable::Sex => "sex",
+            Variable::KeepType => "keep_type",
+            Variable::Income => "income",
+            Variable::Weight => "weight",
+            Variable::Chunk => "chunk",
+        }
+    }
+
+    // The name of the column in the census as published by the ONS, before `1_2_2_rename`
+    pub fn original_name(self) -> Option<&'static str> {
+        match self {
+            Variable::Id => Some("resident_id_m"),
+            Variable::Social => Some("approx_social_grade"),
+            Variable::Birth => Some("country_of_birth_3a"),
+            Variable::Econ => Some("economic_activity_status_10m"),
+            Variable::Ethnic => Some("ethnic_group_tb_6a"),
+            Variable::Health => Some("health_in_general"),
+            Variable::FamType => Some("hh_families_type_6a"),
+            Variable::HoursWorked => Some("hours_per_week_worked"),
+            Variable::Education => Some("in_full_time_education"),
+            Variable::Industry => Some("industry_10a"),
+            Variable::London => Some("iol22cd"),
+            Variable::MarStat => Some("legal_partnership_status_6a"),
+            Variable::Occupation => Some("occupation_10a"),
+            Variable::Region => Some("region"),
+            Variable::Religion => Some("religion_tb"),
+            Variable::ResidenceType => Some("residence_type"),
+            Variable::AgeGroup => Some("resident_age_7d"),
+            Variable::Sex => Some("sex"),
+            Variable::KeepType => Some("usual_short_student"),
+            Variable::Income | Variable::Weight | Variable::Chunk => None, // Synthetic
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variable> {
+        Variable::ALL.into_iter().find(|v| v.name() == name)
+    }
+
+    // Geographic codes (e.g. `E12000007`) are strings, everything else is a number
+    pub fn data_type(self) -> DataType {
+        match self {
+            Variable::Region | Variable::London => DataType::String,
+            Variable::Weight => DataType::Float64,
+            _ => DataType::Int64,
+        }
+    }
+
+    // Whether the values are codes with a label in the codeset (`-8` is "Does not apply")
+    pub fn is_coded(self) -> bool {
+        !matches!(
+            self,
+            Variable::Id | Variable::Income | Variable::Weight | Variable::Chunk
+        )
+    }
+}
+
+// The schema of the census once renamed and expanded (e.g. `./data/csv/census_0.csv`)
+pub fn schema() -> Schema {
+    Variable::ALL
+        .into_iter()
+        .map(|v| Field::new(v.name().into(), v.data_type()))
+        .collect()
+}
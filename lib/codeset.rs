@@ -0,0 +1,317 @@
+use crate::paths;
+use polars::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+// The codeset of the census, read once by `census()`
+static CENSUS: OnceLock<Codeset> = OnceLock::new();
+
+// The code and label of every value of every variable, e.g. `E12000007` is `London` in `region`
+#[derive(Debug, Clone)]
+pub struct Codeset {
+    values: BTreeMap<String, Vec<(String, String)>>,
+}
+
+impl Codeset {
+    // Read a codeset CSV with a `variable` column followed by the code and label columns
+    // (after `1_2_2_rename`, the variables have the short names of the census)
+    pub fn load(path: &str) -> PolarsResult<Codeset> {
+        let df = LazyCsvReader::new(PlPath::from_str(path))
+            .with_infer_schema_length(Some(0)) // Every column as a string, like `-8` or `E12000007`
+            .with_has_header(true)
+            .finish()?
+            .collect()?;
+
+        let names = df.get_column_names_owned();
+        let others: Vec<&PlSmallStr> = names.iter().filter(|n| n.as_str() != "variable").collect();
+        let (Some(code), Some(label)) = (others.first(), others.get(1)) else {
+            let reason = format!("{path} needs a variable, a code and a label column");
+            return Err(PolarsError::ComputeError(reason.into()));
+        };
+
+        let variables = df.column("variable")?.str()?;
+        let codes = df.column(code.as_str())?.str()?;
+        let labels = df.column(label.as_str())?.str()?;
+
+        let mut values: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+        for ((variable, code), label) in variables.into_iter().zip(codes).zip(labels) {
+            let (Some(variable), Some(code)) = (variable, code) else {
+                continue; // Blank lines between variables
+            };
+            let label = label.unwrap_or(code);
+            values
+                .entry(variable.trim().to_owned())
+                .or_default()
+                .push((code.trim().to_owned(), label.trim().to_owned()));
+        }
+
+        Ok(Codeset { values })
+    }
+
+    // The variables that have codes
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
+    // The codes of a variable, with their label, in the order of the codeset
+    pub fn values(&self, variable: &str) -> Option<&[(String, String)]> {
+        self.values.get(variable).map(Vec::as_slice)
+    }
+
+    // The label of one code of a variable
+    pub fn label(&self, variable: &str, code: &str) -> Option<&str> {
+        self.values(variable)?
+            .iter()
+            .find(|(c, _)| c == code)
+            .map(|(_, label)| label.as_str())
+    }
+
+    // The codes and labels of a variable, as two columns of strings: `code` and `label`
+    pub fn frame(&self, variable: &str) -> PolarsResult<DataFrame> {
+        let (codes, labels) = self.codes_and_labels(variable)?;
+        df!(
+            "code" => codes,
+            "label" => labels,
+        )
+    }
+
+    // Replace the codes of a column by their labels, e.g. `E12000007` by `London`
+    pub fn label_column(&self, lf: LazyFrame, variable: &str) -> PolarsResult<LazyFrame> {
+        self.label_columns(lf, &[variable])
+    }
+
+    // Replace the codes of several columns by their labels. A code that is not in the codeset
+    // fails the query when it is collected: see `missing_codes`.
+    pub fn label_columns(&self, lf: LazyFrame, variables: &[&str]) -> PolarsResult<LazyFrame> {
+        let mut labelled = vec![];
+        for variable in variables {
+            let (codes, labels) = self.codes_and_labels(variable)?;
+
+            // Numeric codes (e.g. `sex`) are compared as text, like they are in the codeset
+            labelled.push(col(*variable).cast(DataType::String).replace_strict(
+                lit(Series::from_iter(codes)),
+                lit(Series::from_iter(labels)),
+                None,
+                Some(DataType::String),
+            ));
+        }
+        Ok(lf.with_columns(labelled))
+    }
+
+    // Replace the codes of every column of the codeset by their labels
+    pub fn label_all(&self, lf: LazyFrame) -> PolarsResult<LazyFrame> {
+        let variables = self.coded_columns(&lf)?;
+        let variables: Vec<&str> = variables.iter().map(String::as_str).collect();
+        self.label_columns(lf, &variables)
+    }
+
+    // Add the labels of a column as a new column (e.g. `region_label`) with a lazy left join,
+    // keeping the codes. Codes that are not in the codeset get a `null` label.
+    pub fn join_labels(
+        &self,
+        lf: LazyFrame,
+        variable: &str,
+        alias: &str,
+    ) -> PolarsResult<LazyFrame> {
+        const KEY: &str = "__code";
+
+        let labels = self
+            .frame(variable)?
+            .lazy()
+            .select([col("code").alias(KEY), col("label").alias(alias)]);
+
+        Ok(lf
+            .with_column(col(variable).cast(DataType::String).alias(KEY))
+            .join(
+                labels,
+                [col(KEY)],
+                [col(KEY)],
+                JoinArgs {
+                    maintain_order: MaintainOrderJoin::Left, // Same row order as without labels
+                    ..JoinArgs::new(JoinType::Left)
+                },
+            )
+            .select([all().exclude_cols([KEY]).as_expr()]))
+    }
+
+    // The codes of the data that are not in the codeset, by variable (only the variables with
+    // missing codes), for the given columns
+    pub fn missing_codes(
+        &self,
+        lf: LazyFrame,
+        variables: &[&str],
+    ) -> PolarsResult<BTreeMap<String, Vec<String>>> {
+        let mut missing = BTreeMap::new();
+        for variable in variables {
+            let (codes, _) = self.codes_and_labels(variable)?;
+            let known = lit(Series::from_iter(codes)).implode();
+
+            let df = lf
+                .clone()
+                .select([col(*variable).cast(DataType::String).unique()])
+                .filter(col(*variable).is_not_null())
+                .filter(col(*variable).is_in(known, false).not())
+                .sort([*variable], Default::default())
+                .collect()?;
+
+            let unknown: Vec<String> = df
+                .column(variable)?
+                .str()?
+                .into_no_null_iter()
+                .map(str::to_owned)
+                .collect();
+            if !unknown.is_empty() {
+                missing.insert(variable.to_string(), unknown);
+            }
+        }
+        Ok(missing)
+    }
+
+    // The codes of the data that are not in the codeset, for every column of the codeset
+    pub fn missing_codes_all(&self, lf: LazyFrame) -> PolarsResult<BTreeMap<String, Vec<String>>> {
+        let variables = self.coded_columns(&lf)?;
+        let variables: Vec<&str> = variables.iter().map(String::as_str).collect();
+        self.missing_codes(lf, &variables)
+    }
+
+    // The columns of a frame that have codes in the codeset
+    fn coded_columns(&self, lf: &LazyFrame) -> PolarsResult<Vec<String>> {
+        let schema = lf.clone().collect_schema()?;
+        Ok(schema
+            .iter_names()
+            .filter(|name| self.values.contains_key(name.as_str()))
+            .map(|name| name.to_string())
+            .collect())
+    }
+
+    fn codes_and_labels(&self, variable: &str) -> PolarsResult<(Vec<&str>, Vec<&str>)> {
+        let Some(values) = self.values(variable) else {
+            let reason = format!("{variable} is not in the codeset");
+            return Err(PolarsError::ColumnNotFound(reason.into()));
+        };
+        Ok(values
+            .iter()
+            .map(|(code, label)| (code.as_str(), label.as_str()))
+            .unzip())
+    }
+}
+
+// The codeset of the census, from `./data/codeset/codeset.csv`
+pub fn census() -> PolarsResult<&'static Codeset> {
+    if let Some(codeset) = CENSUS.get() {
+        return Ok(codeset);
+    }
+    let codeset = Codeset::load(paths::CODESET)?;
+    Ok(CENSUS.get_or_init(|| codeset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A codeset with numeric (`sex`, `econ`) and text (`region`) codes, a blank line and an
+    // unlabelled code
+    fn codeset(name: &str) -> Codeset {
+        let path = std::env::temp_dir().join(format!("codeset_{name}_{}.csv", std::process::id()));
+        let csv = "variable,code,label\n\
+                   sex,1,Female\n\
+                   sex,2,Male\n\
+                   \n\
+                   region,E12000007, London \n\
+                   region,W92000004,Wales\n\
+                   econ,-8,\n";
+        fs::write(&path, csv).unwrap();
+        let codeset = Codeset::load(path.to_str().unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+        codeset
+    }
+
+    fn people() -> LazyFrame {
+        df!(
+            "id" => [1, 2, 3],
+            "sex" => [1, 2, 2],
+            "region" => ["W92000004", "E12000007", "E12000007"],
+            "income" => [100, 200, 300],
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    fn strings(df: &DataFrame, name: &str) -> Vec<Option<String>> {
+        let column = df.column(name).unwrap().str().unwrap();
+        column.into_iter().map(|s| s.map(str::to_owned)).collect()
+    }
+
+    #[test]
+    fn loads_codes_and_labels() {
+        let codeset = codeset("load");
+        assert_eq!(
+            codeset.variables().collect::<Vec<_>>(),
+            ["econ", "region", "sex"]
+        );
+        assert_eq!(codeset.label("region", "E12000007"), Some("London"));
+        assert_eq!(codeset.label("econ", "-8"), Some("-8")); // No label: the code
+        assert_eq!(codeset.label("sex", "3"), None);
+        assert_eq!(codeset.values("sex").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn labels_numeric_and_text_codes_and_keeps_the_other_columns() {
+        let codeset = codeset("label");
+        let df = codeset.label_all(people()).unwrap().collect().unwrap();
+
+        assert_eq!(
+            strings(&df, "sex"),
+            [Some("Female"), Some("Male"), Some("Male")].map(|s| s.map(String::from))
+        );
+        assert_eq!(
+            strings(&df, "region"),
+            [Some("Wales"), Some("London"), Some("London")].map(|s| s.map(String::from))
+        );
+        // Without codes: as they were
+        let original = people().collect().unwrap();
+        assert_eq!(df.column("id").unwrap(), original.column("id").unwrap());
+        assert_eq!(
+            df.column("income").unwrap(),
+            original.column("income").unwrap()
+        );
+
+        let Err(error) = codeset.label_column(people(), "income") else {
+            panic!("income has no codes");
+        };
+        assert!(
+            error.to_string().contains("income is not in the codeset"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn handles_codes_missing_from_the_codeset() {
+        let codeset = codeset("missing");
+        let lf = people().with_column(
+            when(col("id").eq(lit(3)))
+                .then(lit(9))
+                .otherwise(col("sex"))
+                .alias("sex"),
+        );
+
+        // Replacing fails, joining gives a null label and the code is reported
+        let labelled = codeset.label_column(lf.clone(), "sex").unwrap();
+        assert!(labelled.collect().is_err());
+
+        let joined = codeset.join_labels(lf.clone(), "sex", "sex_label").unwrap();
+        let joined = joined.collect().unwrap();
+        assert_eq!(
+            strings(&joined, "sex_label"),
+            [Some("Female".to_owned()), Some("Male".to_owned()), None]
+        );
+        assert_eq!(joined.column("sex").unwrap().i32().unwrap().get(2), Some(9));
+
+        let missing = codeset.missing_codes_all(lf).unwrap();
+        assert_eq!(
+            missing,
+            BTreeMap::from([("sex".to_owned(), vec!["9".to_owned()])])
+        );
+    }
+}
@@ -0,0 +1,38 @@
+// What the examples of the book share: the columns of the census, the labels of their
+// codes and where the data is
+
+pub mod calibration;
+pub mod census;
+pub mod codeset;
+pub mod loader;
+pub mod panel;
+pub mod partition;
+pub mod paths;
+pub mod prepare;
+pub mod sync;
+pub mod synthetic;
+pub mod upload;
+
+use polars::prelude::*;
+use std::collections::BTreeMap;
+
+// Replace the codes of a census column by the labels of the codeset, e.g.
+// `label_column(lf, "region")` turns `E12000007` into `London`
+pub fn label_column(lf: LazyFrame, variable: &str) -> PolarsResult<LazyFrame> {
+    codeset::census()?.label_column(lf, variable)
+}
+
+// Replace the codes of several census columns by their labels
+pub fn label_columns(lf: LazyFrame, variables: &[&str]) -> PolarsResult<LazyFrame> {
+    codeset::census()?.label_columns(lf, variables)
+}
+
+// Replace the codes of every census column that has labels in the codeset
+pub fn label_all(lf: LazyFrame) -> PolarsResult<LazyFrame> {
+    codeset::census()?.label_all(lf)
+}
+
+// The codes of the data that have no label in the codeset, by column
+pub fn missing_codes(lf: LazyFrame) -> PolarsResult<BTreeMap<String, Vec<String>>> {
+    codeset::census()?.missing_codes_all(lf)
+}
@@ -0,0 +1,962 @@
+use polars::prelude::*;
+use postgres::GenericClient;
+use serde::Deserialize;
+use std::fmt;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+// Load a DataFrame in a PostgreSQL table: the table is created from the Polars schema (every
+// column with the PostgreSQL type of its Polars type, see `sql_type`), and the rows are sent
+// with `COPY ... (FORMAT binary)`, without going through CSV
+#[derive(Debug, Clone, Deserialize)]
+pub struct Table {
+    pub name: String,
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+    #[serde(default)]
+    pub indexes: Vec<Vec<String>>, // An index on each list of columns, created after the rows
+}
+
+impl Table {
+    // A table with no primary key and no index
+    pub fn named(name: &str) -> Table {
+        Table {
+            name: name.to_owned(),
+            primary_key: vec![],
+            indexes: vec![],
+        }
+    }
+
+    // `CREATE TABLE` with a column for each field of `schema`
+    pub fn create_sql(&self, schema: &Schema) -> PolarsResult<String> {
+        let mut columns = vec![];
+        for (name, dtype) in schema.iter() {
+            columns.push(format!("{} {}", quote(name), sql_type(dtype)?));
+        }
+        if !self.primary_key.is_empty() {
+            for key in &self.primary_key {
+                if schema.get(key).is_none() {
+                    return Err(PolarsError::ColumnNotFound(
+                        format!("primary key `{key}`").into(),
+                    ));
+                }
+            }
+            columns.push(format!("PRIMARY KEY ({})", quote_all(&self.primary_key)));
+        }
+        Ok(format!(
+            "CREATE TABLE {} ({});",
+            quote(&self.name),
+            columns.join(", ")
+        ))
+    }
+
+    // `CREATE INDEX` for each index, named after the table and its columns
+    pub fn index_sql(&self) -> Vec<String> {
+        self.indexes
+            .iter()
+            .map(|columns| {
+                format!(
+                    "CREATE INDEX {} ON {} ({});",
+                    quote(&self.index_name(columns)),
+                    quote(&self.name),
+                    quote_all(columns)
+                )
+            })
+            .collect()
+    }
+
+    // `ALTER ... RENAME` moving `staging` (a copy of this table under another name, with its
+    // primary key and indexes) in the place of this table
+    fn swap_sql(&self, staging: &Table) -> String {
+        let mut sql = vec![
+            format!("DROP TABLE IF EXISTS {};", quote(&self.name)),
+            format!(
+                "ALTER TABLE {} RENAME TO {};",
+                quote(&staging.name),
+                quote(&self.name)
+            ),
+        ];
+        let mut renames = vec![];
+        if !self.primary_key.is_empty() {
+            renames.push((
+                format!("{}_pkey", staging.name),
+                format!("{}_pkey", self.name),
+            ));
+        }
+        for columns in &self.indexes {
+            renames.push((staging.index_name(columns), self.index_name(columns)));
+        }
+        for (from, to) in renames {
+            sql.push(format!(
+                "ALTER INDEX {} RENAME TO {};",
+                quote(&from),
+                quote(&to)
+            ));
+        }
+        sql.join("\n")
+    }
+
+    fn index_name(&self, columns: &[String]) -> String {
+        format!("{}_{}_idx", self.name, columns.join("_"))
+    }
+
+    // Create the table (dropping the one of the same name), copy `df` in it and create the
+    // indexes, which is faster than updating the indexes for every row
+    pub fn load(&self, client: &mut impl GenericClient, df: &DataFrame) -> PolarsResult<u64> {
+        self.create(client, df.schema())?;
+        let rows = self.copy(client, df)?;
+        self.create_indexes(client)?;
+        Ok(rows)
+    }
+
+    // Load Parquet or CSV `files` in the table, created from the schema of the first one. Each
+    // file is read `batch_rows` rows at a time and the rows are sent `buffer_bytes` at a time,
+    // so that a file is never in memory. Everything happens in one transaction: the table is
+    // only visible once all the rows are in and their number is the one of the files, or stays
+    // as it was. `progress` gets called after each batch.
+    pub fn load_files(
+        &self,
+        client: &mut impl GenericClient,
+        files: &[PathBuf],
+        options: &LoadOptions,
+        progress: impl FnMut(&Progress),
+    ) -> PolarsResult<u64> {
+        options.check()?;
+        let Some(first) = files.first() else {
+            return Err(PolarsError::NoData("no file to load".into()));
+        };
+
+        // The rows of each file (from the metadata of a Parquet file), to report progress and
+        // check the table
+        let mut file_totals = vec![];
+        for file in files {
+            let df = scan(file)?.select([len().alias("rows")]).collect()?;
+            file_totals.push(df.column("rows")?.get(0)?.extract::<u64>().unwrap_or(0));
+        }
+        let total = file_totals.iter().sum();
+        let schema = scan(first)?.collect_schema()?;
+
+        // With `staging`, the current table can still be queried during the load (dropping it
+        // would lock it until the end)
+        let target = if options.staging {
+            Table {
+                name: format!("{}_staging", self.name),
+                ..self.clone()
+            }
+        } else {
+            self.clone()
+        };
+
+        // Rolled back when dropped, on any error
+        let mut transaction = client.transaction().map_err(sql_error)?;
+        target.create(&mut transaction, &schema)?;
+
+        copy_files(
+            files,
+            &file_totals,
+            options.batch_rows,
+            |batches, on_batch| {
+                target.copy_batches(
+                    &mut transaction,
+                    &schema,
+                    batches,
+                    options.buffer_bytes,
+                    on_batch,
+                )
+            },
+            progress,
+        )?;
+
+        target.create_indexes(&mut transaction)?;
+
+        // What a query will see after the commit
+        let count: i64 = transaction
+            .query_one(
+                &format!("SELECT count(*) FROM {}", quote(&target.name)),
+                &[],
+            )
+            .map_err(sql_error)?
+            .get(0);
+        if count as u64 != total {
+            return Err(PolarsError::ComputeError(
+                format!(
+                    "{} has {count} rows, the files {total}: rolled back",
+                    target.name
+                )
+                .into(),
+            ));
+        }
+
+        if options.staging {
+            transaction
+                .batch_execute(&self.swap_sql(&target))
+                .map_err(sql_error)?;
+        }
+        transaction.commit().map_err(sql_error)?;
+        Ok(total)
+    }
+
+    pub fn create(&self, client: &mut impl GenericClient, schema: &Schema) -> PolarsResult<()> {
+        let sql = format!(
+            "DROP TABLE IF EXISTS {};\n{}",
+            quote(&self.name),
+            self.create_sql(schema)?
+        );
+        client.batch_execute(&sql).map_err(sql_error)
+    }
+
+    pub fn create_indexes(&self, client: &mut impl GenericClient) -> PolarsResult<()> {
+        for sql in self.index_sql() {
+            client.batch_execute(&sql).map_err(sql_error)?;
+        }
+        Ok(())
+    }
+
+    // Append the rows of `df` to the table, in PostgreSQL's binary format; the columns are
+    // matched by name. Returns the number of rows.
+    pub fn copy(&self, client: &mut impl GenericClient, df: &DataFrame) -> PolarsResult<u64> {
+        self.copy_batches(client, df.schema(), [Ok(df.clone())], 1 << 20, |_| {})
+    }
+
+    // One `COPY` for all the `batches`, which must have the columns of `schema`; `on_batch`
+    // gets the rows of each batch once it is sent
+    fn copy_batches(
+        &self,
+        client: &mut impl GenericClient,
+        schema: &Schema,
+        batches: impl IntoIterator<Item = PolarsResult<DataFrame>>,
+        buffer_bytes: usize,
+        on_batch: impl FnMut(u64),
+    ) -> PolarsResult<u64> {
+        let names: Vec<String> = schema.iter_names().map(|n| n.to_string()).collect();
+        let sql = format!(
+            "COPY {} ({}) FROM STDIN (FORMAT binary)",
+            quote(&self.name),
+            quote_all(&names)
+        );
+        // An error drops the writer before `finish`, which cancels the `COPY`
+        let mut writer = client.copy_in(&sql).map_err(sql_error)?;
+        write_rows(&mut writer, schema, batches, buffer_bytes, on_batch)?;
+        writer.finish().map_err(sql_error) // The rows PostgreSQL got
+    }
+}
+
+// Copy each file with `copy`, which gets the batches of the file and what to call with the rows
+// of each batch, and returns the rows it copied. A file that did not give all its rows (or a
+// batch that failed) is an error, which stops the load before anything is committed.
+fn copy_files(
+    files: &[PathBuf],
+    file_totals: &[u64],
+    batch_rows: usize,
+    mut copy: impl FnMut(Batches, &mut dyn FnMut(u64)) -> PolarsResult<u64>,
+    mut progress: impl FnMut(&Progress),
+) -> PolarsResult<u64> {
+    let total = file_totals.iter().sum();
+    let mut rows = 0;
+    for (i, file) in files.iter().enumerate() {
+        let mut report = Progress {
+            file,
+            file_number: i + 1,
+            files: files.len(),
+            file_rows: 0,
+            file_total: file_totals[i],
+            rows,
+            total,
+        };
+        let batches = Batches::read(scan(file)?, batch_rows);
+        let copied = copy(batches, &mut |batch| {
+            report.file_rows += batch;
+            report.rows += batch;
+            progress(&report);
+        })?;
+        if copied != file_totals[i] {
+            return Err(PolarsError::ComputeError(
+                format!(
+                    "{}: {copied} rows copied, {} in the file",
+                    file.display(),
+                    file_totals[i]
+                )
+                .into(),
+            ));
+        }
+        if copied == 0 {
+            progress(&report); // No batch
+        }
+        rows += copied;
+    }
+    Ok(rows)
+}
+
+// Write the `batches` to `writer` in PostgreSQL's binary `COPY` format, `buffer_bytes` at a time.
+// The trailer that ends the data is only written once every batch is in.
+fn write_rows(
+    writer: &mut impl Write,
+    schema: &Schema,
+    batches: impl IntoIterator<Item = PolarsResult<DataFrame>>,
+    buffer_bytes: usize,
+    mut on_batch: impl FnMut(u64),
+) -> PolarsResult<u64> {
+    let mut rows = 0;
+
+    // Header: signature, flags and the length of the header extension
+    let mut buffer = b"PGCOPY\n\xff\r\n\0".to_vec();
+    buffer.extend_from_slice(&0i32.to_be_bytes());
+    buffer.extend_from_slice(&0i32.to_be_bytes());
+
+    for df in batches {
+        let df = df?;
+        let mut columns = vec![];
+        for (name, dtype) in schema.iter() {
+            let column = df.column(name)?;
+            if column.dtype() != dtype {
+                return Err(PolarsError::SchemaMismatch(
+                    format!("`{name}` is {}, the table {dtype}", column.dtype()).into(),
+                ));
+            }
+            columns.push(transport(column)?);
+        }
+        for row in 0..df.height() {
+            buffer.extend_from_slice(&(columns.len() as i16).to_be_bytes());
+            for column in &columns {
+                field(&column.get(row)?, &mut buffer)?;
+            }
+            if buffer.len() >= buffer_bytes {
+                writer.write_all(&buffer).map_err(io_error)?;
+                buffer.clear();
+            }
+        }
+        rows += df.height() as u64;
+        on_batch(df.height() as u64);
+    }
+    buffer.extend_from_slice(&(-1i16).to_be_bytes());
+    writer.write_all(&buffer).map_err(io_error)?;
+    Ok(rows)
+}
+
+// How `Table::load_files` loads
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoadOptions {
+    pub staging: bool,       // Load in `<name>_staging`, then swap it with the table
+    pub batch_rows: usize,   // Rows read from a file at a time
+    pub buffer_bytes: usize, // Bytes sent to PostgreSQL at a time
+}
+
+impl Default for LoadOptions {
+    fn default() -> LoadOptions {
+        LoadOptions {
+            staging: false,
+            batch_rows: 100_000,
+            buffer_bytes: 1 << 20,
+        }
+    }
+}
+
+impl LoadOptions {
+    fn check(&self) -> PolarsResult<()> {
+        if self.batch_rows == 0 || self.buffer_bytes == 0 {
+            return Err(PolarsError::InvalidOperation(
+                "batch_rows and buffer_bytes must be more than 0".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Where a load is, after a batch
+#[derive(Debug, Clone)]
+pub struct Progress<'a> {
+    pub file: &'a Path,
+    pub file_number: usize, // From 1
+    pub files: usize,
+    pub file_rows: u64, // Copied from this file
+    pub file_total: u64,
+    pub rows: u64, // Copied from all the files
+    pub total: u64,
+}
+
+impl Progress<'_> {
+    pub fn file_done(&self) -> bool {
+        self.file_rows == self.file_total
+    }
+}
+
+impl fmt::Display for Progress<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = if self.total == 0 {
+            100.0
+        } else {
+            self.rows as f64 * 100.0 / self.total as f64
+        };
+        write!(
+            f,
+            "file {} of {} ({}), {} of {} rows ({percent:.0}%)",
+            self.file_number,
+            self.files,
+            self.file.display(),
+            self.rows,
+            self.total
+        )
+    }
+}
+
+// The batches of a streaming query, computed in a thread while the previous ones are sent. At
+// most two wait in the channel, so memory stays at a few batches whatever the size of the file.
+struct Batches {
+    receiver: Option<Receiver<DataFrame>>,
+    thread: Option<JoinHandle<PolarsResult<()>>>,
+}
+
+impl Batches {
+    fn read(lf: LazyFrame, batch_rows: usize) -> Batches {
+        let (sender, receiver) = mpsc::sync_channel(2);
+        let thread = thread::spawn(move || {
+            lf.sink_batches(
+                PlanCallback::new(move |df: DataFrame| {
+                    // Fails once the receiver is dropped, on an error while copying
+                    sender
+                        .send(df)
+                        .map_err(|_| PolarsError::ComputeError("load stopped".into()))?;
+                    Ok(false) // Do not stop
+                }),
+                true,
+                NonZeroUsize::new(batch_rows),
+            )?
+            .collect_with_engine(Engine::Streaming)?;
+            Ok(())
+        });
+        Batches {
+            receiver: Some(receiver),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Iterator for Batches {
+    type Item = PolarsResult<DataFrame>;
+
+    // The batches, then the error of the query if it failed
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(receiver) = &self.receiver {
+            if let Ok(df) = receiver.recv() {
+                return Some(Ok(df));
+            }
+            self.receiver = None;
+        }
+        match self.thread.take()?.join() {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(Err(e)),
+            Err(_) => Some(Err(PolarsError::ComputeError(
+                "reading thread panicked".into(),
+            ))),
+        }
+    }
+}
+
+// A Parquet or CSV file, by its extension
+fn scan(file: &Path) -> PolarsResult<LazyFrame> {
+    let path = PlPath::from_string(file.display().to_string());
+    match file.extension().and_then(|e| e.to_str()) {
+        Some("parquet") => LazyFrame::scan_parquet(path, ScanArgsParquet::default()),
+        Some("csv") => LazyCsvReader::new(path)
+            .with_infer_schema_length(Some(10_000)) // Default 100, missing = String
+            .with_has_header(true)
+            .finish(),
+        _ => Err(PolarsError::InvalidOperation(
+            format!("{}: not a Parquet or CSV file", file.display()).into(),
+        )),
+    }
+}
+
+// The PostgreSQL type of a Polars type. Unsigned integers take the next larger type (there are
+// no unsigned types in PostgreSQL) and lists become arrays.
+pub fn sql_type(dtype: &DataType) -> PolarsResult<String> {
+    match dtype {
+        DataType::List(inner) => {
+            if matches!(**inner, DataType::List(_)) {
+                return Err(no_type(dtype));
+            }
+            Ok(format!("{}[]", element(inner)?.0))
+        }
+        _ => Ok(element(dtype)?.0),
+    }
+}
+
+// The PostgreSQL type of a value that is not a list, and its `oid` (the number PostgreSQL gives
+// to each type, needed for the elements of an array)
+fn element(dtype: &DataType) -> PolarsResult<(String, u32)> {
+    let (name, oid) = match dtype {
+        DataType::Boolean => ("boolean", 16),
+        DataType::Int8 | DataType::UInt8 | DataType::Int16 => ("smallint", 21),
+        DataType::UInt16 | DataType::Int32 => ("integer", 23),
+        DataType::UInt32 | DataType::Int64 => ("bigint", 20),
+        DataType::UInt64 => ("numeric(20, 0)", 1700),
+        DataType::Int128 | DataType::UInt128 => ("numeric(39, 0)", 1700),
+        DataType::Float32 => ("real", 700),
+        DataType::Float64 => ("double precision", 701),
+        DataType::Decimal(precision, scale) => {
+            return Ok((format!("numeric({precision}, {scale})"), 1700));
+        }
+        DataType::String | DataType::Categorical(_, _) | DataType::Enum(_, _) => ("text", 25),
+        DataType::Null => ("text", 25), // Only nulls
+        DataType::Binary | DataType::BinaryOffset => ("bytea", 17),
+        DataType::Date => ("date", 1082),
+        DataType::Time => ("time", 1083),
+        DataType::Datetime(_, None) => ("timestamp", 1114),
+        DataType::Datetime(_, Some(_)) => ("timestamp with time zone", 1184),
+        DataType::Duration(_) => ("interval", 1186),
+        _ => return Err(no_type(dtype)),
+    };
+    Ok((name.to_owned(), oid))
+}
+
+// A column as it is sent: categories as their text
+fn transport(column: &Column) -> PolarsResult<Column> {
+    sql_type(column.dtype())?;
+    let column = match column.dtype() {
+        DataType::Categorical(_, _) | DataType::Enum(_, _) => column.cast(&DataType::String)?,
+        DataType::List(inner)
+            if matches!(**inner, DataType::Categorical(_, _) | DataType::Enum(_, _)) =>
+        {
+            column.cast(&DataType::List(Box::new(DataType::String)))?
+        }
+        _ => column.clone(),
+    };
+    Ok(column.rechunk()) // Quicker `get`
+}
+
+// Microseconds between 1970-01-01 (Polars) and 2000-01-01 (PostgreSQL)
+const EPOCH_MICROSECONDS: i64 = 946_684_800_000_000;
+const EPOCH_DAYS: i32 = 10_957;
+
+// A value in PostgreSQL's binary format: its length in bytes (-1 for null), then the bytes
+fn field(value: &AnyValue, out: &mut Vec<u8>) -> PolarsResult<()> {
+    if value.is_null() {
+        out.extend_from_slice(&(-1i32).to_be_bytes());
+        return Ok(());
+    }
+    let start = out.len();
+    out.extend_from_slice(&0i32.to_be_bytes()); // The length, once known
+    match value {
+        AnyValue::Boolean(v) => out.push(*v as u8),
+        AnyValue::Int8(v) => out.extend_from_slice(&(*v as i16).to_be_bytes()),
+        AnyValue::UInt8(v) => out.extend_from_slice(&(*v as i16).to_be_bytes()),
+        AnyValue::Int16(v) => out.extend_from_slice(&v.to_be_bytes()),
+        AnyValue::UInt16(v) => out.extend_from_slice(&(*v as i32).to_be_bytes()),
+        AnyValue::Int32(v) => out.extend_from_slice(&v.to_be_bytes()),
+        AnyValue::UInt32(v) => out.extend_from_slice(&(*v as i64).to_be_bytes()),
+        AnyValue::Int64(v) => out.extend_from_slice(&v.to_be_bytes()),
+        AnyValue::UInt64(v) => numeric(*v as i128, 0, out),
+        AnyValue::Int128(v) => numeric(*v, 0, out),
+        AnyValue::UInt128(v) => numeric(i128::try_from(*v).map_err(|_| too_large(value))?, 0, out),
+        AnyValue::Decimal(v, _, scale) => numeric(*v, *scale, out),
+        AnyValue::Float32(v) => out.extend_from_slice(&v.to_be_bytes()),
+        AnyValue::Float64(v) => out.extend_from_slice(&v.to_be_bytes()),
+        AnyValue::String(v) => out.extend_from_slice(v.as_bytes()),
+        AnyValue::StringOwned(v) => out.extend_from_slice(v.as_bytes()),
+        AnyValue::Binary(v) => out.extend_from_slice(v),
+        AnyValue::BinaryOwned(v) => out.extend_from_slice(v),
+        AnyValue::Date(days) => out.extend_from_slice(&(days - EPOCH_DAYS).to_be_bytes()),
+        AnyValue::Time(nanoseconds) => out.extend_from_slice(&(nanoseconds / 1000).to_be_bytes()),
+        AnyValue::Datetime(v, unit, _) | AnyValue::DatetimeOwned(v, unit, _) => {
+            let utc = microseconds(*v, *unit) - EPOCH_MICROSECONDS;
+            out.extend_from_slice(&utc.to_be_bytes());
+        }
+        AnyValue::Duration(v, unit) => {
+            out.extend_from_slice(&microseconds(*v, *unit).to_be_bytes());
+            out.extend_from_slice(&0i32.to_be_bytes()); // Days
+            out.extend_from_slice(&0i32.to_be_bytes()); // Months
+        }
+        AnyValue::List(values) => array(values, out)?,
+        _ => return Err(no_type(&value.dtype())),
+    }
+    let length = (out.len() - start - 4) as i32;
+    out[start..start + 4].copy_from_slice(&length.to_be_bytes());
+    Ok(())
+}
+
+// A one-dimensional array: dimensions, whether there are nulls, the type of the elements, the
+// length and first index of the dimension, then each element as a field
+fn array(values: &Series, out: &mut Vec<u8>) -> PolarsResult<()> {
+    let (_, oid) = element(values.dtype())?;
+    let ndim = if values.is_empty() { 0i32 } else { 1 };
+    out.extend_from_slice(&ndim.to_be_bytes());
+    out.extend_from_slice(&((values.null_count() > 0) as i32).to_be_bytes());
+    out.extend_from_slice(&oid.to_be_bytes());
+    if ndim == 1 {
+        out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+        out.extend_from_slice(&1i32.to_be_bytes());
+    }
+    for value in values.iter() {
+        field(&value, out)?;
+    }
+    Ok(())
+}
+
+// `numeric`: digits in base 10,000, the weight of the first one (its power of 10,000), the sign
+// and the number of decimal places
+fn numeric(value: i128, scale: usize, out: &mut Vec<u8>) {
+    let digits = format!("{:0>width$}", value.unsigned_abs(), width = scale + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    let integer = format!("{}{integer}", "0".repeat((4 - integer.len() % 4) % 4));
+    let fraction = format!("{fraction}{}", "0".repeat((4 - fraction.len() % 4) % 4));
+
+    let mut groups: Vec<i16> = integer
+        .as_bytes()
+        .chunks(4)
+        .chain(fraction.as_bytes().chunks(4))
+        .map(|group| group.iter().fold(0, |n, d| n * 10 + (d - b'0') as i16))
+        .collect();
+    let mut weight = (integer.len() / 4) as i16 - 1;
+    while groups.first() == Some(&0) {
+        groups.remove(0);
+        weight -= 1;
+    }
+    while groups.last() == Some(&0) {
+        groups.pop();
+    }
+    if groups.is_empty() {
+        weight = 0;
+    }
+
+    let sign: u16 = if value < 0 { 0x4000 } else { 0 };
+    out.extend_from_slice(&(groups.len() as i16).to_be_bytes());
+    out.extend_from_slice(&weight.to_be_bytes());
+    out.extend_from_slice(&sign.to_be_bytes());
+    out.extend_from_slice(&(scale as i16).to_be_bytes());
+    for group in groups {
+        out.extend_from_slice(&group.to_be_bytes());
+    }
+}
+
+fn microseconds(value: i64, unit: TimeUnit) -> i64 {
+    match unit {
+        TimeUnit::Nanoseconds => value.div_euclid(1000),
+        TimeUnit::Microseconds => value,
+        TimeUnit::Milliseconds => value * 1000,
+    }
+}
+
+// A PostgreSQL identifier in double quotes (which are doubled inside), so that any column name
+// works, whatever its case or characters
+pub fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn quote_all(identifiers: &[String]) -> String {
+    identifiers
+        .iter()
+        .map(|i| quote(i))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn no_type(dtype: &DataType) -> PolarsError {
+    PolarsError::ComputeError(format!("no PostgreSQL type for {dtype}").into())
+}
+
+fn too_large(value: &AnyValue) -> PolarsError {
+    PolarsError::ComputeError(format!("{value} is too large for PostgreSQL").into())
+}
+
+// With the message of the server when there is one ("db error" otherwise)
+fn sql_error(e: postgres::Error) -> PolarsError {
+    let message = match e.as_db_error() {
+        Some(db) => db.to_string(),
+        None => e.to_string(),
+    };
+    PolarsError::ComputeError(format!("PostgreSQL: {message}").into())
+}
+
+fn io_error(e: std::io::Error) -> PolarsError {
+    PolarsError::ComputeError(format!("PostgreSQL: {e}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The bytes of `numeric_send` for a value with `scale` decimal places: the number of
+    // digits, the weight, the sign, the scale, then the base 10,000 digits
+    fn numeric_bytes(value: i128, scale: usize) -> Vec<i16> {
+        let mut out = vec![];
+        numeric(value, scale, &mut out);
+        out.chunks(2)
+            .map(|pair| i16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    fn field_bytes(value: AnyValue) -> Vec<u8> {
+        let mut out = vec![];
+        field(&value, &mut out).unwrap();
+        out
+    }
+
+    // A field: its length, then its bytes
+    fn sized(bytes: &[u8]) -> Vec<u8> {
+        let mut out = (bytes.len() as i32).to_be_bytes().to_vec();
+        out.extend_from_slice(bytes);
+        out
+    }
+
+    const NEGATIVE: i16 = 0x4000;
+
+    #[test]
+    fn encodes_numeric() {
+        assert_eq!(numeric_bytes(0, 0), [0, 0, 0, 0]);
+        assert_eq!(numeric_bytes(0, 2), [0, 0, 0, 2]); // 0.00
+        assert_eq!(numeric_bytes(1, 0), [1, 0, 0, 0, 1]);
+        assert_eq!(numeric_bytes(-1, 0), [1, 0, NEGATIVE, 0, 1]);
+        // 12345.678
+        assert_eq!(numeric_bytes(12_345_678, 3), [3, 1, 0, 3, 1, 2345, 6780]);
+        assert_eq!(
+            numeric_bytes(-12_345_678, 3),
+            [3, 1, NEGATIVE, 3, 1, 2345, 6780]
+        );
+        // 100,000,000: the trailing zero groups are left out, the weight keeps the magnitude
+        assert_eq!(numeric_bytes(100_000_000, 0), [1, 2, 0, 0, 1]);
+        // 0.0001 and 0.00012: the leading zero groups are left out, the weight goes negative
+        assert_eq!(numeric_bytes(1, 4), [1, -1, 0, 4, 1]);
+        assert_eq!(numeric_bytes(12, 5), [2, -1, 0, 5, 1, 2000]);
+        // 10000.0001
+        assert_eq!(numeric_bytes(100_000_001, 4), [3, 1, 0, 4, 1, 0, 1]);
+        // The largest `u64`
+        assert_eq!(
+            numeric_bytes(u64::MAX as i128, 0),
+            [5, 4, 0, 0, 1844, 6744, 737, 955, 1615]
+        );
+    }
+
+    #[test]
+    fn encodes_dates_and_times_from_2000() {
+        assert_eq!(
+            field_bytes(AnyValue::Date(EPOCH_DAYS)),
+            sized(&0i32.to_be_bytes())
+        );
+        assert_eq!(
+            field_bytes(AnyValue::Date(0)),
+            sized(&(-10_957i32).to_be_bytes())
+        );
+
+        let micro = AnyValue::Datetime(EPOCH_MICROSECONDS, TimeUnit::Microseconds, None);
+        assert_eq!(field_bytes(micro), sized(&0i64.to_be_bytes()));
+        let milli = AnyValue::Datetime(946_684_800_001, TimeUnit::Milliseconds, None);
+        assert_eq!(field_bytes(milli), sized(&1000i64.to_be_bytes()));
+        // Nanoseconds are rounded down, also before 1970
+        let nano = AnyValue::Datetime(-1, TimeUnit::Nanoseconds, None);
+        assert_eq!(
+            field_bytes(nano),
+            sized(&(-1 - EPOCH_MICROSECONDS).to_be_bytes())
+        );
+
+        // 01:00:00, in microseconds
+        let time = AnyValue::Time(3_600_000_000_000);
+        assert_eq!(field_bytes(time), sized(&3_600_000_000i64.to_be_bytes()));
+
+        // An interval: microseconds, days and months
+        let mut interval = 90_000_000i64.to_be_bytes().to_vec();
+        interval.extend_from_slice(&[0; 8]);
+        let duration = AnyValue::Duration(90_000, TimeUnit::Milliseconds);
+        assert_eq!(field_bytes(duration), sized(&interval));
+    }
+
+    #[test]
+    fn encodes_nulls_and_arrays() {
+        assert_eq!(field_bytes(AnyValue::Null), (-1i32).to_be_bytes());
+        assert_eq!(field_bytes(AnyValue::Boolean(true)), sized(&[1]));
+        assert_eq!(field_bytes(AnyValue::String("é")), sized("é".as_bytes()));
+        assert_eq!(
+            field_bytes(AnyValue::UInt8(200)),
+            sized(&200i16.to_be_bytes())
+        );
+
+        // `{1,NULL}` as `integer[]`: one dimension, with nulls, of `int4` (23), of 2 elements
+        // from index 1, then the elements
+        let list = Series::new("".into(), [Some(1i32), None]);
+        let mut array = vec![];
+        for n in [1i32, 1, 23, 2, 1, 4, 1, -1] {
+            array.extend_from_slice(&n.to_be_bytes());
+        }
+        assert_eq!(field_bytes(AnyValue::List(list)), sized(&array));
+
+        // `{}`: no dimension
+        let empty = Series::new_empty("".into(), &DataType::String);
+        let mut array = vec![];
+        for n in [0i32, 0, 25] {
+            array.extend_from_slice(&n.to_be_bytes());
+        }
+        assert_eq!(field_bytes(AnyValue::List(empty)), sized(&array));
+    }
+
+    #[test]
+    fn maps_polars_types_to_sql() {
+        let types = [
+            (DataType::Boolean, "boolean"),
+            (DataType::UInt8, "smallint"),
+            (DataType::UInt16, "integer"),
+            (DataType::UInt32, "bigint"),
+            (DataType::Int64, "bigint"),
+            (DataType::UInt64, "numeric(20, 0)"),
+            (DataType::Float64, "double precision"),
+            (DataType::Decimal(10, 2), "numeric(10, 2)"),
+            (DataType::String, "text"),
+            (DataType::Date, "date"),
+            (
+                DataType::Datetime(TimeUnit::Microseconds, None),
+                "timestamp",
+            ),
+            (
+                DataType::Datetime(TimeUnit::Microseconds, Some(TimeZone::UTC)),
+                "timestamp with time zone",
+            ),
+            (DataType::List(Box::new(DataType::Int32)), "integer[]"),
+        ];
+        for (dtype, sql) in types {
+            assert_eq!(sql_type(&dtype).unwrap(), sql, "{dtype}");
+        }
+
+        let nested = DataType::List(Box::new(DataType::List(Box::new(DataType::Int32))));
+        let record = DataType::Struct(vec![Field::new("a".into(), DataType::Int32)]);
+        for dtype in [nested, record] {
+            assert!(sql_type(&dtype).is_err(), "{dtype}");
+        }
+
+        let schema = Schema::from_iter([Field::new("a".into(), DataType::Int64)]);
+        let table = Table {
+            primary_key: vec!["b".to_owned()],
+            ..Table::named("t")
+        };
+        assert!(matches!(
+            table.create_sql(&schema),
+            Err(PolarsError::ColumnNotFound(_))
+        ));
+        assert_eq!(
+            Table::named("a \"b\"").create_sql(&schema).unwrap(),
+            "CREATE TABLE \"a \"\"b\"\"\" (\"a\" bigint);"
+        );
+    }
+
+    // A directory of its own in the temporary directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("loader_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A CSV file with a `value` column
+    fn csv(dir: &Path, name: &str, values: impl IntoIterator<Item = String>) -> PathBuf {
+        let path = dir.join(name);
+        let mut content = "value\n".to_owned();
+        for value in values {
+            content.push_str(&format!("{value}\n"));
+        }
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    // Copy the files to buffers instead of PostgreSQL, one for each file
+    fn fake_copy(files: &[PathBuf], totals: &[u64], sent: &mut Vec<Vec<u8>>) -> PolarsResult<u64> {
+        let schema = Schema::from_iter([Field::new("value".into(), DataType::Int64)]);
+        copy_files(
+            files,
+            totals,
+            2,
+            |batches, on_batch| {
+                let mut sink = vec![];
+                let rows = write_rows(&mut sink, &schema, batches, 16, on_batch);
+                sent.push(sink);
+                rows
+            },
+            |_| {},
+        )
+    }
+
+    const TRAILER: [u8; 2] = (-1i16).to_be_bytes();
+
+    #[test]
+    fn copies_files_and_reports_progress() {
+        let dir = temp_dir("progress");
+        let files = [
+            csv(&dir, "a.csv", (0..5).map(|n| n.to_string())),
+            csv(&dir, "b.csv", []),
+        ];
+
+        let mut reports = vec![];
+        let schema = Schema::from_iter([Field::new("value".into(), DataType::Int64)]);
+        let rows = copy_files(
+            &files,
+            &[5, 0],
+            2,
+            |batches, on_batch| write_rows(&mut vec![], &schema, batches, 16, on_batch),
+            |progress| reports.push((progress.file_number, progress.rows, progress.file_done())),
+        )
+        .unwrap();
+        assert_eq!(rows, 5);
+        assert_eq!(reports.first(), Some(&(1, 2, false)));
+        assert_eq!(reports[reports.len() - 2..], [(1, 5, true), (2, 5, true)]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stops_when_a_file_does_not_give_all_its_rows() {
+        let dir = temp_dir("mismatch");
+        let files = [
+            csv(&dir, "a.csv", (0..5).map(|n| n.to_string())),
+            csv(&dir, "b.csv", (0..5).map(|n| n.to_string())),
+        ];
+
+        let mut sent = vec![];
+        let error = fake_copy(&files, &[5, 6], &mut sent).unwrap_err();
+        assert!(error.to_string().contains("5 rows copied, 6 in the file"));
+        // The error comes after the second file, before anything else is sent
+        assert_eq!(sent.len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stops_on_an_error_of_the_reading_thread() {
+        let dir = temp_dir("reading");
+        // Past the rows used to infer the type, so that it fails while streaming
+        let values = (0..10_050).map(|n| n.to_string()).chain(["x".to_owned()]);
+        let files = [csv(&dir, "a.csv", values)];
+
+        let mut sent = vec![];
+        let error = fake_copy(&files, &[10_051], &mut sent).unwrap_err();
+        assert!(error.to_string().contains("x"), "{error}");
+        // Rows went out, but not the trailer that would end the `COPY`
+        assert!(sent[0].len() > 19);
+        assert!(!sent[0].ends_with(&TRAILER));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_a_panic_of_the_reading_thread() {
+        let (sender, receiver) = mpsc::sync_channel(2);
+        let thread = thread::spawn(move || {
+            sender.send(df!("value" => [1i64]).unwrap()).unwrap();
+            panic!("reading");
+        });
+        let mut batches = Batches {
+            receiver: Some(receiver),
+            thread: Some(thread),
+        };
+
+        assert!(matches!(batches.next(), Some(Ok(_))));
+        let error = batches.next().unwrap().unwrap_err();
+        assert!(error.to_string().contains("panicked"));
+        assert!(batches.next().is_none());
+
+        let schema = Schema::from_iter([Field::new("value".into(), DataType::Int64)]);
+        let batches = [Ok(df!("value" => [1i64]).unwrap()), Err(error)];
+        let mut sink = vec![];
+        assert!(write_rows(&mut sink, &schema, batches, 1, |_| {}).is_err());
+        assert!(!sink.ends_with(&TRAILER));
+    }
+}
@@ -0,0 +1,323 @@
+use crate::census::Variable;
+use polars::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution as _, LogNormal};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// Turn the 1% sample into waves of a panel (one per `chunk`), in which the same individuals
+// (same `id`) come back from one wave to the next, a little different: older, with another
+// income, and some of them gone and replaced by new entrants
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Panel {
+    pub seed: u64,
+    pub attrition: f64, // Share of the individuals that leave at each wave
+    pub attrition_by_age_group: BTreeMap<i64, f64>, // Instead of `attrition`, for some groups
+    pub entrants: f64,  // New individuals at each wave, as a share of the sample
+    pub income_growth: f64, // Average growth of the income at each wave (of its logarithm)
+    pub income_volatility: f64, // Standard deviation of that growth
+    pub ageing: BTreeMap<i64, f64>, // Chance of moving to the next age group at each wave
+}
+
+impl Default for Panel {
+    fn default() -> Panel {
+        Panel {
+            seed: 1,
+            attrition: 0.01,
+            attrition_by_age_group: BTreeMap::from([(7, 0.04)]),
+            entrants: 0.015,
+            income_growth: 0.02,
+            income_volatility: 0.1,
+            // One year older at each wave: 1 in 16 of the 0 to 15 become 16 to 24, and so on
+            ageing: BTreeMap::from([
+                (1, 1.0 / 16.0),
+                (2, 1.0 / 9.0),
+                (3, 0.1),
+                (4, 0.1),
+                (5, 0.1),
+                (6, 0.1),
+            ]),
+        }
+    }
+}
+
+impl Panel {
+    // Every wave, starting with the sample itself, each with its number in a `chunk` column
+    pub fn waves(&self, sample: DataFrame) -> Waves<'_> {
+        Waves {
+            panel: self,
+            sample,
+            previous: None,
+            wave: 0,
+        }
+    }
+
+    // The wave after `previous`: the individuals that stay, one wave older, and the entrants
+    fn next_wave(
+        &self,
+        previous: &DataFrame,
+        sample: &DataFrame,
+        wave: usize,
+    ) -> PolarsResult<DataFrame> {
+        let age_group = Variable::AgeGroup.name();
+        let income = Variable::Income.name();
+
+        // A stream of random numbers for each wave, so that a wave does not depend on how many
+        // numbers the ones before it drew
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(wave as u64);
+
+        let ages = previous.column(age_group)?.cast(&DataType::Int64)?;
+        let stay: BooleanChunked = ages
+            .i64()?
+            .iter()
+            .map(|age| {
+                let rate = age
+                    .and_then(|a| self.attrition_by_age_group.get(&a))
+                    .copied()
+                    .unwrap_or(self.attrition);
+                rng.random::<f64>() >= rate
+            })
+            .collect();
+        let mut df = previous.filter(&stay)?;
+
+        let ages: Vec<Option<i64>> = df
+            .column(age_group)?
+            .cast(&DataType::Int64)?
+            .i64()?
+            .iter()
+            .map(|age| {
+                age.map(|a| match self.ageing.get(&a) {
+                    Some(chance) if rng.random::<f64>() < *chance => a + 1,
+                    _ => a,
+                })
+            })
+            .collect();
+        df.with_column(Column::new(age_group.into(), ages))?;
+
+        let growth = LogNormal::new(self.income_growth, self.income_volatility)
+            .map_err(|e| PolarsError::ComputeError(format!("panel income: {e}").into()))?;
+        let incomes: Vec<Option<i64>> = df
+            .column(income)?
+            .cast(&DataType::Int64)?
+            .i64()?
+            .iter()
+            .map(|i| i.map(|i| (i as f64 * growth.sample(&mut rng)).round() as i64))
+            .collect();
+        df.with_column(Column::new(income.into(), incomes))?;
+
+        // Entrants look like individuals of the sample, with an `id` of their own
+        let count = (self.entrants * sample.height() as f64).round() as usize;
+        if count > 0 {
+            let mut entrants = sample.sample_n_literal(count, true, false, Some(rng.random()))?;
+            let id = Variable::Id.name();
+            let ids = match sample.column(id)?.dtype() {
+                DataType::String => {
+                    let ids: Vec<String> =
+                        (0..count).map(|i| format!("NEW{wave:03}{i:09}")).collect();
+                    Column::new(id.into(), ids)
+                }
+                // Numeric ids continue after the largest one of the sample
+                dtype => {
+                    let largest = sample.column(id)?.cast(&DataType::Int64)?.i64()?.max();
+                    let first = largest.unwrap_or(0) + 1 + ((wave - 1) * count) as i64;
+                    let ids: Vec<i64> = (0..count as i64).map(|i| first + i).collect();
+                    Column::new(id.into(), ids).cast(dtype)?
+                }
+            };
+            entrants.with_column(ids)?;
+            df.vstack_mut(&entrants.select(df.get_column_names_str())?)?;
+            df.rechunk_mut();
+        }
+        Ok(df)
+    }
+}
+
+pub struct Waves<'a> {
+    panel: &'a Panel,
+    sample: DataFrame,
+    previous: Option<DataFrame>,
+    wave: usize,
+}
+
+impl Iterator for Waves<'_> {
+    type Item = PolarsResult<DataFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let df = match &self.previous {
+            None => Ok(self.sample.clone()),
+            Some(previous) => self.panel.next_wave(previous, &self.sample, self.wave),
+        };
+        let wave = self.wave;
+        self.wave += 1;
+
+        Some(df.and_then(|df| {
+            let mut with_chunk = df.clone();
+            with_chunk.with_column(Column::new(
+                Variable::Chunk.name().into(),
+                vec![wave as i32; df.height()],
+            ))?;
+            self.previous = Some(df);
+            Ok(with_chunk)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    // `n` individuals, of the age groups 1 to 7 in turn, with numeric or text ids
+    fn sample(n: i64, text_ids: bool) -> DataFrame {
+        let ids = match text_ids {
+            true => Column::new(
+                "id".into(),
+                (0..n).map(|i| format!("{i:06}")).collect::<Vec<_>>(),
+            ),
+            false => Column::new("id".into(), (1..=n).collect::<Vec<_>>()),
+        };
+        DataFrame::new(vec![
+            ids,
+            Column::new(
+                "age_group".into(),
+                (0..n).map(|i| i % 7 + 1).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "income".into(),
+                (0..n).map(|i| 1_000 + i).collect::<Vec<_>>(),
+            ),
+        ])
+        .unwrap()
+    }
+
+    fn waves(panel: &Panel, sample: DataFrame, count: usize) -> Vec<DataFrame> {
+        panel
+            .waves(sample)
+            .take(count)
+            .collect::<PolarsResult<_>>()
+            .unwrap()
+    }
+
+    // The rows of `df` of an age group
+    fn in_group(df: &DataFrame, age_group: i64) -> usize {
+        let ages = df.column("age_group").unwrap().i64().unwrap();
+        ages.into_iter().filter(|a| *a == Some(age_group)).count()
+    }
+
+    // A panel in which nothing changes, for the tests to change one thing
+    fn still() -> Panel {
+        Panel {
+            attrition: 0.0,
+            attrition_by_age_group: BTreeMap::new(),
+            entrants: 0.0,
+            ageing: BTreeMap::new(),
+            ..Panel::default()
+        }
+    }
+
+    #[test]
+    fn leaves_at_the_rate_of_the_age_group() {
+        let panel = Panel {
+            attrition: 0.1,
+            attrition_by_age_group: BTreeMap::from([(7, 0.5), (1, 0.0)]),
+            ..still()
+        };
+        let waves = waves(&panel, sample(7_000, false), 2);
+
+        assert_eq!(waves[0].height(), 7_000);
+        assert_eq!(
+            waves[0].column("chunk").unwrap().i32().unwrap().max(),
+            Some(0)
+        );
+        assert_eq!(in_group(&waves[1], 1), 1_000);
+        let old = in_group(&waves[1], 7);
+        assert!((450..550).contains(&old), "{old}");
+        for age_group in 2..7 {
+            let stayed = in_group(&waves[1], age_group);
+            assert!((870..930).contains(&stayed), "{age_group}: {stayed}");
+        }
+        assert_eq!(
+            waves[1].column("chunk").unwrap().i32().unwrap().min(),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn ages_individuals_and_changes_their_income() {
+        let panel = Panel {
+            ageing: BTreeMap::from([(1, 1.0), (2, 0.5)]),
+            income_growth: 0.1,
+            income_volatility: 0.0,
+            ..still()
+        };
+        let waves = waves(&panel, sample(7_000, false), 2);
+
+        // Those of the group 1 all move to 2, half of those of the group 2 to 3
+        assert_eq!(in_group(&waves[1], 1), 0);
+        let moved = in_group(&waves[1], 3) - 1_000;
+        assert!((450..550).contains(&moved), "{moved}");
+        assert_eq!(in_group(&waves[1], 2), 1_000 + (1_000 - moved));
+        assert_eq!(in_group(&waves[1], 7), 1_000); // The last group
+
+        // The same individuals, in the same order, with an income 10.5% higher
+        assert_eq!(
+            waves[1].column("id").unwrap(),
+            waves[0].column("id").unwrap()
+        );
+        let incomes = waves[1].column("income").unwrap().i64().unwrap();
+        let expected = (1_000_f64 * 0.1_f64.exp()).round() as i64;
+        assert_eq!(incomes.get(0), Some(expected));
+    }
+
+    #[test]
+    fn gives_entrants_ids_of_their_own() {
+        let panel = Panel {
+            entrants: 0.1,
+            ..Panel::default()
+        };
+        for text_ids in [false, true] {
+            let waves = waves(&panel, sample(1_000, text_ids), 4);
+            let mut seen = BTreeSet::new();
+            let mut previous = BTreeSet::new();
+            for (wave, df) in waves.iter().enumerate() {
+                let ids = df.column("id").unwrap().cast(&DataType::String).unwrap();
+                let ids: BTreeSet<String> = ids
+                    .str()
+                    .unwrap()
+                    .into_no_null_iter()
+                    .map(String::from)
+                    .collect();
+                assert_eq!(ids.len(), df.height(), "duplicate ids in wave {wave}");
+
+                // Those who were not in the wave before were never in any wave
+                if wave > 0 {
+                    let entrants: Vec<_> = ids.difference(&previous).collect();
+                    assert_eq!(entrants.len(), 100, "wave {wave}");
+                    assert!(entrants.iter().all(|id| !seen.contains(*id)), "wave {wave}");
+                }
+                previous = ids.clone();
+                seen.extend(ids);
+            }
+        }
+    }
+
+    #[test]
+    fn gives_the_same_waves_with_the_same_seed() {
+        let panel = Panel::default();
+        let first = waves(&panel, sample(2_000, false), 4);
+        let again = waves(&panel, sample(2_000, false), 4);
+        for (first, again) in first.iter().zip(&again) {
+            assert!(first.equals_missing(again));
+        }
+
+        let other = Panel {
+            seed: 2,
+            ..Panel::default()
+        };
+        let other = waves(&other, sample(2_000, false), 4);
+        assert!(!first[3].equals_missing(&other[3]));
+    }
+}
@@ -0,0 +1,662 @@
+use polars::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Write a hive-partitioned Parquet dataset (a directory `key=value` for each value of each key,
+// e.g. `region=E12000001/age_group=1/00000000.parquet`), with files of a chosen size, and merge
+// the small files of a dataset with `compact`. The data goes through the streaming engine in
+// batches: only a row group of each partition is in memory at a time. Every partition keeps a
+// file open until the end, so memory grows with partitions × `row_group_size` rows.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Partitioning {
+    pub keys: Vec<String>, // The partition columns, not written in the files (they are in the path)
+    pub max_rows_per_file: Option<usize>,
+    pub max_bytes_per_file: Option<u64>, // Compressed, on disk: a file can go over by a row group
+    pub row_group_size: usize,           // Rows
+}
+
+impl Default for Partitioning {
+    fn default() -> Partitioning {
+        Partitioning {
+            keys: vec![],
+            max_rows_per_file: None,
+            max_bytes_per_file: None,
+            row_group_size: 100_000,
+        }
+    }
+}
+
+// What `write` wrote
+#[derive(Debug)]
+pub struct Written {
+    pub partitions: usize,
+    pub files: usize,
+    pub rows: usize,
+}
+
+impl fmt::Display for Written {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rows in {} file(s), {} partition(s)",
+            self.rows, self.files, self.partitions
+        )
+    }
+}
+
+// What `compact` did
+#[derive(Debug)]
+pub struct Compaction {
+    pub partitions: usize,
+    pub compacted: usize, // The partitions that were rewritten, the others are kept as they are
+    pub files_before: usize,
+    pub files_after: usize,
+}
+
+impl fmt::Display for Compaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Compacted {} of {} partition(s), from {} to {} file(s)",
+            self.compacted, self.partitions, self.files_before, self.files_after
+        )
+    }
+}
+
+impl Partitioning {
+    // A partitioning by `keys`, with the default sizes
+    pub fn by(keys: &[&str]) -> Partitioning {
+        Partitioning {
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            ..Partitioning::default()
+        }
+    }
+
+    // Write `lf` to the directory `dir`, replacing what it contained. The dataset is written in a
+    // temporary directory next to `dir` first, so that `dir` is never half written.
+    pub fn write(&self, lf: LazyFrame, dir: impl AsRef<Path>) -> PolarsResult<Written> {
+        self.check()?;
+        let dir = dir.as_ref();
+        let temp = sibling(dir, "writing")?;
+        recreate_dir(&temp)?;
+
+        let mut lf = lf;
+        let schema = lf.collect_schema()?;
+        for key in &self.keys {
+            if schema.get(key).is_none() {
+                return Err(PolarsError::ColumnNotFound(
+                    format!("partition key `{key}`").into(),
+                ));
+            }
+        }
+
+        let partitions = Arc::new(Mutex::new(Partitions {
+            root: temp.clone(),
+            options: self.clone(),
+            writers: BTreeMap::new(),
+            rows: 0,
+        }));
+        let sink = partitions.clone();
+        lf.sink_batches(
+            PlanCallback::new(move |df: DataFrame| {
+                sink.lock().unwrap().push(df)?;
+                Ok(false) // Do not stop
+            }),
+            true,
+            None,
+        )?
+        .collect_with_engine(Engine::Streaming)?;
+
+        let mut partitions = partitions.lock().unwrap();
+        let written = partitions.finish()?;
+        replace_dir(&temp, dir)?;
+        Ok(written)
+    }
+
+    // Merge the small files of each partition of `dir` (written by `write` or any other hive
+    // partitioned dataset) into files of `max_rows_per_file` rows or `max_bytes_per_file` bytes
+    // (into one file without either). A partition is only rewritten if that makes fewer files.
+    // The new dataset is written in a temporary directory, then replaces `dir`.
+    pub fn compact(&self, dir: impl AsRef<Path>) -> PolarsResult<Compaction> {
+        self.check()?;
+        let dir = dir.as_ref();
+        let temp = sibling(dir, "compacting")?;
+        recreate_dir(&temp)?;
+
+        let mut leaves = BTreeMap::new();
+        walk(dir, Path::new(""), &mut leaves)?;
+
+        let mut compaction = Compaction {
+            partitions: 0,
+            compacted: 0,
+            files_before: 0,
+            files_after: 0,
+        };
+        for (relative, leaf) in &leaves {
+            let out = temp.join(relative);
+            fs::create_dir_all(&out).map_err(|e| io_error(&out, e))?;
+            for other in &leaf.others {
+                link_or_copy(other, &out)?;
+            }
+            if leaf.parquet.is_empty() {
+                continue;
+            }
+            compaction.partitions += 1;
+            compaction.files_before += leaf.parquet.len();
+
+            let mut rows = 0;
+            let mut bytes = 0;
+            for file in &leaf.parquet {
+                let f = File::open(file).map_err(|e| io_error(file, e))?;
+                rows += ParquetReader::new(f).num_rows()?;
+                bytes += fs::metadata(file).map_err(|e| io_error(file, e))?.len();
+            }
+            let by_rows = self.max_rows_per_file.map_or(1, |max| rows.div_ceil(max));
+            let by_bytes = self
+                .max_bytes_per_file
+                .map_or(1, |max| bytes.div_ceil(max) as usize);
+            let needed = by_rows.max(by_bytes).max(1);
+
+            if needed >= leaf.parquet.len() {
+                for file in &leaf.parquet {
+                    link_or_copy(file, &out)?;
+                }
+                compaction.files_after += leaf.parquet.len();
+                continue;
+            }
+
+            // Read the files of the partition one after the other, in batches, into new files
+            let writer = Arc::new(Mutex::new(Writer::new(out, self.clone())));
+            for file in &leaf.parquet {
+                let mut args = ScanArgsParquet::default();
+                args.hive_options.enabled = Some(false); // The keys stay in the path
+                let sink = writer.clone();
+                LazyFrame::scan_parquet(PlPath::from_string(file.display().to_string()), args)?
+                    .sink_batches(
+                        PlanCallback::new(move |df: DataFrame| {
+                            sink.lock().unwrap().push(df)?;
+                            Ok(false)
+                        }),
+                        true,
+                        None,
+                    )?
+                    .collect_with_engine(Engine::Streaming)?;
+            }
+            let mut writer = writer.lock().unwrap();
+            writer.finish()?;
+            compaction.compacted += 1;
+            compaction.files_after += writer.files;
+        }
+
+        replace_dir(&temp, dir)?;
+        Ok(compaction)
+    }
+
+    fn check(&self) -> PolarsResult<()> {
+        if self.row_group_size == 0
+            || self.max_rows_per_file == Some(0)
+            || self.max_bytes_per_file == Some(0)
+        {
+            return Err(PolarsError::ComputeError(
+                "partitioning: the row group and file sizes must be more than 0".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// The writers of every partition met so far, by directory
+struct Partitions {
+    root: PathBuf,
+    options: Partitioning,
+    writers: BTreeMap<PathBuf, Writer>,
+    rows: usize,
+}
+
+impl Partitions {
+    fn push(&mut self, batch: DataFrame) -> PolarsResult<()> {
+        self.rows += batch.height();
+        let parts = if self.options.keys.is_empty() {
+            vec![batch]
+        } else {
+            batch.partition_by_stable(&self.options.keys, true)?
+        };
+
+        for part in parts {
+            if part.height() == 0 {
+                continue;
+            }
+            let mut relative = PathBuf::new();
+            for key in &self.options.keys {
+                let value = part.column(key)?.head(Some(1)).cast(&DataType::String)?;
+                let value = value
+                    .str()?
+                    .get(0)
+                    .map(escape)
+                    .unwrap_or_else(|| "__HIVE_DEFAULT_PARTITION__".to_owned());
+                relative.push(format!("{key}={value}"));
+            }
+            let part = part.drop_many(&self.options.keys);
+
+            let writer = self
+                .writers
+                .entry(relative.clone())
+                .or_insert_with(|| Writer::new(self.root.join(&relative), self.options.clone()));
+            writer.push(part)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> PolarsResult<Written> {
+        let mut files = 0;
+        for writer in self.writers.values_mut() {
+            writer.finish()?;
+            files += writer.files;
+        }
+        Ok(Written {
+            partitions: self.writers.len(),
+            files,
+            rows: self.rows,
+        })
+    }
+}
+
+// The files of one partition: rows are buffered until there is a row group, written to the open
+// file, and a new file is started when it has enough rows or bytes
+struct Writer {
+    dir: PathBuf,
+    options: Partitioning,
+    buffer: Option<DataFrame>,
+    file: Option<OpenFile>,
+    files: usize,
+}
+
+struct OpenFile {
+    writer: polars::io::parquet::write::BatchedWriter<Counted>,
+    rows: usize,
+    bytes: Arc<AtomicU64>,
+}
+
+impl Writer {
+    fn new(dir: PathBuf, options: Partitioning) -> Writer {
+        Writer {
+            dir,
+            options,
+            buffer: None,
+            file: None,
+            files: 0,
+        }
+    }
+
+    fn push(&mut self, df: DataFrame) -> PolarsResult<()> {
+        match &mut self.buffer {
+            Some(buffer) => {
+                buffer.vstack_mut(&df)?;
+            }
+            None => self.buffer = Some(df),
+        }
+        while self.buffered() >= self.options.row_group_size {
+            self.write_group()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> PolarsResult<()> {
+        while self.buffered() > 0 {
+            self.write_group()?;
+        }
+        self.close()
+    }
+
+    fn buffered(&self) -> usize {
+        self.buffer.as_ref().map_or(0, |b| b.height())
+    }
+
+    // Write a row group (or what is left of the buffer), smaller if the file is almost full
+    fn write_group(&mut self) -> PolarsResult<()> {
+        let Some(buffer) = self.buffer.take() else {
+            return Ok(());
+        };
+        if self.file.is_none() {
+            self.open(buffer.schema())?;
+        }
+        let file = self.file.as_mut().unwrap();
+
+        let room = self
+            .options
+            .max_rows_per_file
+            .map_or(usize::MAX, |max| max - file.rows);
+        let n = self.options.row_group_size.min(room).min(buffer.height());
+        let mut group = buffer.slice(0, n);
+        group.rechunk_mut();
+        file.writer.write_batch(&group)?;
+        file.rows += n;
+        if n < buffer.height() {
+            self.buffer = Some(buffer.slice(n as i64, buffer.height() - n));
+        }
+
+        let full_rows = self
+            .options
+            .max_rows_per_file
+            .is_some_and(|max| file.rows >= max);
+        let full_bytes = self
+            .options
+            .max_bytes_per_file
+            .is_some_and(|max| file.bytes.load(Ordering::Relaxed) >= max);
+        if full_rows || full_bytes {
+            self.close()?;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, schema: &Schema) -> PolarsResult<()> {
+        fs::create_dir_all(&self.dir).map_err(|e| io_error(&self.dir, e))?;
+        let path = self.dir.join(format!("{:08}.parquet", self.files));
+        let bytes = Arc::new(AtomicU64::new(0));
+        let file = Counted {
+            file: File::create(&path).map_err(|e| io_error(&path, e))?,
+            bytes: bytes.clone(),
+        };
+        let writer = ParquetWriter::new(file)
+            .with_row_group_size(Some(self.options.row_group_size))
+            .batched(schema)?;
+        self.file = Some(OpenFile {
+            writer,
+            rows: 0,
+            bytes,
+        });
+        self.files += 1;
+        Ok(())
+    }
+
+    fn close(&mut self) -> PolarsResult<()> {
+        if let Some(file) = self.file.take() {
+            file.writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+// A file that counts the bytes written to it
+struct Counted {
+    file: File,
+    bytes: Arc<AtomicU64>,
+}
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.bytes.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// The files of a partition (a directory with no sub-directory), and the files that are not Parquet
+#[derive(Default)]
+struct Leaf {
+    parquet: Vec<PathBuf>,
+    others: Vec<PathBuf>,
+}
+
+fn walk(dir: &Path, relative: &Path, leaves: &mut BTreeMap<PathBuf, Leaf>) -> PolarsResult<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| io_error(dir, e))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()
+        .map_err(|e| io_error(dir, e))?;
+    entries.sort();
+
+    let mut leaf = Leaf::default();
+    for path in entries {
+        if path.is_dir() {
+            let name = path.file_name().unwrap_or_default();
+            walk(&path, &relative.join(name), leaves)?;
+        } else if path.extension().is_some_and(|e| e == "parquet") {
+            leaf.parquet.push(path);
+        } else {
+            leaf.others.push(path);
+        }
+    }
+    if !leaf.parquet.is_empty() || !leaf.others.is_empty() {
+        leaves.insert(relative.to_path_buf(), leaf);
+    }
+    Ok(())
+}
+
+// Like Polars, keep letters, digits and `-_.` of a value and percent-encode the rest
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.".contains(&byte) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    escaped
+}
+
+// A hidden directory next to `dir`, e.g. `.partitioned.writing` for `partitioned`
+fn sibling(dir: &Path, suffix: &str) -> PolarsResult<PathBuf> {
+    let name = dir.file_name().ok_or_else(|| {
+        PolarsError::ComputeError(format!("{}: not a directory name", dir.display()).into())
+    })?;
+    let parent = dir.parent().unwrap_or(Path::new(""));
+    Ok(parent.join(format!(".{}.{suffix}", name.to_string_lossy())))
+}
+
+fn recreate_dir(dir: &Path) -> PolarsResult<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir).map_err(|e| io_error(dir, e))?;
+    }
+    fs::create_dir_all(dir).map_err(|e| io_error(dir, e))
+}
+
+// Put the finished `temp` directory in place of `dir` with two renames. The swap is not
+// atomic: between them, `dir` does not exist, and a reader opening it then fails (a complete
+// dataset is always either at `dir` or at its `old` sibling). If the second rename fails, the
+// old dataset is put back.
+fn replace_dir(temp: &Path, dir: &Path) -> PolarsResult<()> {
+    if dir.exists() {
+        let old = sibling(dir, "old")?;
+        if old.exists() {
+            fs::remove_dir_all(&old).map_err(|e| io_error(&old, e))?;
+        }
+        fs::rename(dir, &old).map_err(|e| io_error(dir, e))?;
+        if let Err(e) = fs::rename(temp, dir) {
+            let _ = fs::rename(&old, dir);
+            return Err(io_error(temp, e));
+        }
+        fs::remove_dir_all(&old).map_err(|e| io_error(&old, e))
+    } else {
+        fs::rename(temp, dir).map_err(|e| io_error(temp, e))
+    }
+}
+
+// Keep a file in the new dataset without copying it when possible
+fn link_or_copy(file: &Path, dir: &Path) -> PolarsResult<()> {
+    let target = dir.join(file.file_name().unwrap_or_default());
+    if fs::hard_link(file, &target).is_err() {
+        fs::copy(file, &target).map_err(|e| io_error(file, e))?;
+    }
+    Ok(())
+}
+
+fn io_error(path: &Path, e: io::Error) -> PolarsError {
+    PolarsError::ComputeError(format!("{}: {e}", path.display()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A directory of its own in the temporary directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("partition_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // `rows` rows, split evenly between the regions
+    fn census(regions: &[&str], rows: usize) -> LazyFrame {
+        let region: Vec<&str> = (0..rows).map(|i| regions[i % regions.len()]).collect();
+        let value: Vec<i64> = (0..rows as i64).collect();
+        df!("region" => region, "value" => value).unwrap().lazy()
+    }
+
+    // The Parquet files of a dataset, without the other files
+    fn read(dir: &Path) -> DataFrame {
+        let path = PlPath::from_string(format!("{}/**/*.parquet", dir.display()));
+        LazyFrame::scan_parquet(path, ScanArgsParquet::default())
+            .unwrap()
+            .sort(["value"], SortMultipleOptions::default())
+            .collect()
+            .unwrap()
+    }
+
+    // The Parquet files under `dir`, by partition
+    fn files(dir: &Path) -> BTreeMap<PathBuf, usize> {
+        let mut leaves = BTreeMap::new();
+        walk(dir, Path::new(""), &mut leaves).unwrap();
+        leaves
+            .into_iter()
+            .map(|(relative, leaf)| (relative, leaf.parquet.len()))
+            .collect()
+    }
+
+    #[test]
+    fn splits_files_by_rows_and_by_bytes() {
+        let dir = temp_dir("split").join("dataset");
+
+        let by_rows = Partitioning {
+            max_rows_per_file: Some(25),
+            row_group_size: 10,
+            ..Partitioning::by(&["region"])
+        };
+        let written = by_rows.write(census(&["A", "B"], 100), &dir).unwrap();
+        assert_eq!(
+            (written.partitions, written.files, written.rows),
+            (2, 4, 100)
+        );
+        let expected = [("region=A", 2), ("region=B", 2)];
+        let expected = expected.map(|(p, n)| (PathBuf::from(p), n));
+        assert_eq!(files(&dir), BTreeMap::from(expected));
+
+        let df = read(&dir);
+        assert_eq!(df.height(), 100);
+        let value = df.column("value").unwrap().i64().unwrap();
+        assert!(value.into_no_null_iter().eq(0..100));
+
+        // Every row group goes over one byte, so each one is a file
+        let by_bytes = Partitioning {
+            max_bytes_per_file: Some(1),
+            row_group_size: 10,
+            ..Partitioning::default()
+        };
+        let written = by_bytes.write(census(&["A"], 95), &dir).unwrap();
+        assert_eq!((written.partitions, written.files), (1, 10));
+        assert_eq!(read(&dir).height(), 95);
+
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn compacts_small_files_and_leaves_the_other_partitions() {
+        let dir = temp_dir("compact").join("dataset");
+        let small = Partitioning {
+            max_rows_per_file: Some(10),
+            row_group_size: 10,
+            ..Partitioning::by(&["region"])
+        };
+        small.write(census(&["A", "B"], 100), &dir).unwrap();
+
+        // `C` is already a single file, and a file that is not Parquet is kept
+        let single = dir.join("region=C");
+        Partitioning::default()
+            .write(census(&["C"], 5).select([col("value")]), &single)
+            .unwrap();
+        let kept = single.join("00000000.parquet");
+        let before = fs::read(&kept).unwrap();
+        fs::write(dir.join("region=A").join("notes.txt"), "kept").unwrap();
+
+        let compaction = Partitioning::default().compact(&dir).unwrap();
+        assert_eq!(compaction.partitions, 3);
+        assert_eq!(compaction.compacted, 2);
+        assert_eq!((compaction.files_before, compaction.files_after), (11, 3));
+
+        let expected = [("region=A", 1), ("region=B", 1), ("region=C", 1)];
+        let expected = expected.map(|(p, n)| (PathBuf::from(p), n));
+        assert_eq!(files(&dir), BTreeMap::from(expected));
+        assert_eq!(fs::read(&kept).unwrap(), before);
+        assert_eq!(
+            fs::read_to_string(dir.join("region=A").join("notes.txt")).unwrap(),
+            "kept"
+        );
+        assert_eq!(read(&dir).height(), 105);
+        assert!(!sibling(&dir, "compacting").unwrap().exists());
+
+        // Nothing to do the second time
+        let compaction = Partitioning::default().compact(&dir).unwrap();
+        assert_eq!(compaction.compacted, 0);
+        assert_eq!(read(&dir).height(), 105);
+
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn restores_the_original_when_the_swap_fails() {
+        let root = temp_dir("restore");
+        let dir = root.join("dataset");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("00000000.parquet"), "original").unwrap();
+
+        // The new dataset cannot be moved, as it does not exist
+        let missing = root.join(".dataset.writing");
+        assert!(replace_dir(&missing, &dir).is_err());
+        assert_eq!(
+            fs::read_to_string(dir.join("00000000.parquet")).unwrap(),
+            "original"
+        );
+        assert!(!sibling(&dir, "old").unwrap().exists());
+
+        // And it is replaced when it does
+        fs::create_dir_all(&missing).unwrap();
+        fs::write(missing.join("00000000.parquet"), "new").unwrap();
+        replace_dir(&missing, &dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("00000000.parquet")).unwrap(),
+            "new"
+        );
+        assert!(!missing.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rejects_missing_keys_and_empty_sizes() {
+        let dir = temp_dir("invalid").join("dataset");
+        let missing = Partitioning::by(&["age_group"]).write(census(&["A"], 5), &dir);
+        assert!(matches!(missing, Err(PolarsError::ColumnNotFound(_))));
+
+        let empty = Partitioning {
+            row_group_size: 0,
+            ..Partitioning::default()
+        };
+        assert!(empty.write(census(&["A"], 5), &dir).is_err());
+        assert!(!dir.exists());
+
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
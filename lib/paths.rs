@@ -0,0 +1,69 @@
+// Where the examples read and write their data, relative to the root of the repository.
+// The `get-data` recipe creates every path below, in the order they are listed.
+
+// The archive with the census and its codeset
+pub const ZIP: &str = "./zip/data.zip";
+
+// Everything the examples create
+pub const DATA: &str = "./data";
+
+// The census, as extracted from the archive, renamed and with synthetic income and weight
+pub const RAW: &str = "./data/raw";
+pub const RAW_CENSUS: &str = "./data/raw/census.csv";
+
+// The values (and their labels) of each census variable
+pub const CODESET_DIR: &str = "./data/codeset";
+pub const CODESET: &str = "./data/codeset/codeset.csv";
+
+// 100 copies of the census, one per `chunk`, as CSV and as Parquet
+pub const CSV: &str = "./data/csv";
+pub const PARQUET: &str = "./data/parquet";
+
+// All the chunks together, in a single file and partitioned by region
+pub const LARGE: &str = "./data/large";
+pub const LARGE_CSV: &str = "./data/large/census.csv";
+pub const LARGE_PARQUET: &str = "./data/large/census.parquet";
+pub const PARTITIONED: &str = "./data/large/partitioned";
+
+// Scratch space for the examples that write data, the local S3 bucket and the publications
+pub const TEMP: &str = "./data/temp_data";
+pub const MINIO: &str = "./data/minio";
+pub const OUTPUT: &str = "./data/output";
+
+// The directories `1_2_1_extract` creates, parents first
+pub const DIRECTORIES: [&str; 9] = [
+    DATA,
+    RAW,
+    CODESET_DIR,
+    CSV,
+    PARQUET,
+    LARGE,
+    TEMP,
+    MINIO,
+    OUTPUT,
+];
+
+// One chunk of the census, e.g. `./data/csv/census_0.csv`
+pub fn csv_chunk(chunk: usize) -> String {
+    format!("./data/csv/census_{chunk}.csv")
+}
+
+// One chunk of the census, e.g. `./data/parquet/census_0.parquet`
+pub fn parquet_chunk(chunk: usize) -> String {
+    format!("./data/parquet/census_{chunk}.parquet")
+}
+
+// The Parquet copy of a file of `./data/csv`, e.g. `./data/parquet/census_0.parquet` for `census_0`
+pub fn parquet_file(stem: &str) -> String {
+    format!("./data/parquet/{stem}.parquet")
+}
+
+// A file written by the examples that show how to write data, e.g. `./data/temp_data/census_0.csv`
+pub fn temp(file: &str) -> String {
+    format!("./data/temp_data/{file}")
+}
+
+// A file written by the publication examples, e.g. `./data/output/report.html`
+pub fn output(file: &str) -> String {
+    format!("./data/output/{file}")
+}
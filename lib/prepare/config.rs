@@ -0,0 +1,157 @@
+use super::error::{Error, ErrorKind};
+use super::expand::Expand;
+use super::extract::Extract;
+use super::large::Large;
+use super::parquet::Parquet;
+use super::rename::Rename;
+use super::s3::S3;
+use super::sql::Sql;
+use super::synthesize::Synthesize;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Where the progress is recorded when the pipeline does not say
+const DEFAULT_STATE: &str = "./data/prepare_state.toml";
+
+// The steps of `prepare.toml`, in the order they are written
+#[derive(Debug)]
+pub struct Pipeline {
+    pub state: PathBuf,
+    pub directories: Vec<PathBuf>, // Created before any step, for the examples that write data
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawPipeline {
+    state: Option<PathBuf>,
+    #[serde(default)]
+    directories: Vec<PathBuf>,
+    #[serde(default, rename = "step")]
+    steps: Vec<toml::Table>,
+}
+
+// A `[[step]]` of the pipeline
+#[derive(Debug, Deserialize)]
+pub struct Step {
+    pub name: String,
+    #[serde(default)]
+    pub after: Vec<String>, // Steps to run first, on top of those producing the inputs
+    #[serde(default)]
+    pub optional: bool, // Only run when asked for by name (e.g. needs a server)
+    #[serde(flatten)]
+    pub action: Action,
+    #[serde(skip)]
+    pub fingerprint: String, // The step as written, to notice when it changes
+}
+
+// What a step does, from its `kind`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Action {
+    Extract(Extract),
+    Rename(Rename),
+    Synthesize(Synthesize),
+    Expand(Expand),
+    Parquet(Parquet),
+    Large(Large),
+    Sql(Sql),
+    S3(S3),
+}
+
+impl Pipeline {
+    pub fn load(path: &Path) -> Result<Pipeline, Error> {
+        let content = fs::read_to_string(path).map_err(|e| Error::new(ErrorKind::io(path, e)))?;
+        let raw: RawPipeline = toml::from_str(&content)
+            .map_err(|e| Error::new(ErrorKind::Config(e.message().to_owned())))?;
+
+        let mut steps: Vec<Step> = vec![];
+        for table in raw.steps {
+            let fingerprint = table.to_string();
+            let name = table
+                .get("name")
+                .and_then(toml::Value::as_str)
+                .unwrap_or("(unnamed)")
+                .to_owned();
+            let mut step: Step =
+                toml::Value::Table(table)
+                    .try_into()
+                    .map_err(|e: toml::de::Error| {
+                        Error::in_step(&name, ErrorKind::Config(e.message().to_owned()))
+                    })?;
+            step.fingerprint = fingerprint;
+
+            if steps.iter().any(|s| s.name == step.name) {
+                let reason = format!("{} is defined twice", step.name);
+                return Err(Error::new(ErrorKind::Config(reason)));
+            }
+            steps.push(step);
+        }
+
+        for step in &steps {
+            if let Some(unknown) = step
+                .after
+                .iter()
+                .find(|a| !steps.iter().any(|s| s.name == **a))
+            {
+                let kind = ErrorKind::UnknownStep(unknown.clone());
+                return Err(Error::in_step(&step.name, kind));
+            }
+        }
+
+        Ok(Pipeline {
+            state: raw.state.unwrap_or_else(|| PathBuf::from(DEFAULT_STATE)),
+            directories: raw.directories,
+            steps,
+        })
+    }
+
+    pub fn step(&self, name: &str) -> Result<&Step, Error> {
+        self.steps
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| Error::new(ErrorKind::UnknownStep(name.to_owned())))
+    }
+}
+
+impl Action {
+    // The files and directories the step reads
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        match self {
+            Action::Extract(step) => step.inputs(),
+            Action::Rename(step) => step.inputs(),
+            Action::Synthesize(step) => step.inputs(),
+            Action::Expand(step) => step.inputs(),
+            Action::Parquet(step) => step.inputs(),
+            Action::Large(step) => step.inputs(),
+            Action::Sql(step) => step.inputs(),
+            Action::S3(step) => step.inputs(),
+        }
+    }
+
+    // The files and directories the step writes (none for the SQL and S3 uploads)
+    pub fn outputs(&self) -> Vec<PathBuf> {
+        match self {
+            Action::Extract(step) => step.outputs(),
+            Action::Rename(step) => step.outputs(),
+            Action::Synthesize(step) => step.outputs(),
+            Action::Expand(step) => step.outputs(),
+            Action::Parquet(step) => step.outputs(),
+            Action::Large(step) => step.outputs(),
+            Action::Sql(_) | Action::S3(_) => vec![],
+        }
+    }
+
+    pub fn run(&self) -> Result<(), ErrorKind> {
+        match self {
+            Action::Extract(step) => step.run(),
+            Action::Rename(step) => step.run(),
+            Action::Synthesize(step) => step.run(),
+            Action::Expand(step) => step.run(),
+            Action::Parquet(step) => step.run(),
+            Action::Large(step) => step.run(),
+            Action::Sql(step) => step.run(),
+            Action::S3(step) => step.run(),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use polars::prelude::PolarsError;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Everything that can go wrong while preparing the data
+#[derive(Debug)]
+pub enum ErrorKind {
+    Io(PathBuf, io::Error),
+    Config(String),
+    UnknownStep(String),
+    Cycle(Vec<String>),
+    Archive(PathBuf, String),
+    Polars(PolarsError),
+    Sql(String),
+    S3(String),
+}
+
+impl ErrorKind {
+    pub fn io(path: &Path, err: io::Error) -> ErrorKind {
+        ErrorKind::Io(path.to_path_buf(), err)
+    }
+}
+
+impl From<PolarsError> for ErrorKind {
+    fn from(err: PolarsError) -> ErrorKind {
+        ErrorKind::Polars(err)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Io(path, err) => write!(f, "could not access {}: {err}", path.display()),
+            ErrorKind::Config(reason) => write!(f, "invalid pipeline: {reason}"),
+            ErrorKind::UnknownStep(name) => write!(f, "no step called {name}"),
+            ErrorKind::Cycle(steps) => {
+                write!(f, "steps depend on each other: {}", steps.join(", "))
+            }
+            ErrorKind::Archive(path, reason) => {
+                write!(f, "could not extract {}: {reason}", path.display())
+            }
+            ErrorKind::Polars(err) => write!(f, "{err}"),
+            ErrorKind::Sql(reason) => write!(f, "PostgreSQL: {reason}"),
+            ErrorKind::S3(reason) => write!(f, "S3: {reason}"),
+        }
+    }
+}
+
+// An error, with the step that caused it
+#[derive(Debug)]
+pub struct Error {
+    pub step: Option<String>,
+    pub kind: ErrorKind,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Error {
+        Error { step: None, kind }
+    }
+
+    pub fn in_step(step: &str, kind: ErrorKind) -> Error {
+        Error {
+            step: Some(step.to_owned()),
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ERROR")?;
+        if let Some(step) = &self.step {
+            write!(f, " in step {step}")?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,57 @@
+use super::error::ErrorKind;
+use super::files;
+use crate::panel::Panel;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+// Copy the 1% sample many times (`census_0.csv`, `census_1.csv`, ...), with the copy number in
+// a `chunk` column. With a `panel`, each copy is the next wave of a panel instead of the same
+// individuals again.
+#[derive(Debug, Deserialize)]
+pub struct Expand {
+    pub input: PathBuf,
+    pub output_dir: PathBuf,
+    #[serde(default = "default_copies")]
+    pub copies: usize,
+    pub panel: Option<Panel>,
+}
+
+fn default_copies() -> usize {
+    100
+}
+
+impl Expand {
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        vec![self.input.clone()]
+    }
+
+    pub fn outputs(&self) -> Vec<PathBuf> {
+        vec![self.output_dir.clone()]
+    }
+
+    pub fn run(&self) -> Result<(), ErrorKind> {
+        let lf = files::read_csv(&self.input)?;
+        files::recreate_dir(&self.output_dir)?;
+
+        if let Some(panel) = &self.panel {
+            let waves = panel.waves(lf.collect()?).take(self.copies);
+            for (chunk, df) in waves.enumerate() {
+                let path = self.output_dir.join(format!("census_{chunk}.csv"));
+                files::write_csv(&mut df?, &path)?;
+            }
+            return Ok(());
+        }
+
+        for chunk in 0..self.copies {
+            let mut df = lf
+                .clone()
+                .with_column(lit(chunk as i32).alias("chunk"))
+                .collect()?;
+            let path = self.output_dir.join(format!("census_{chunk}.csv"));
+            files::write_csv(&mut df, &path)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,472 @@
+use super::error::ErrorKind;
+use super::files;
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+// Copy files out of an archive (ZIP, tar, or a gzip or zstd file that holds a tar or a single
+// file), one entry at a time, without holding any of them in memory
+#[derive(Debug, Deserialize)]
+pub struct Extract {
+    pub archive: PathBuf,
+    #[serde(default)]
+    pub format: Option<Format>, // Detected from the first bytes of the archive when left out
+    // Name in the archive, and where to write it. A glob pattern (e.g. `*.csv`) is matched
+    // against every entry, and writes them in a folder, under their name in the archive.
+    pub files: BTreeMap<String, PathBuf>,
+    // A `sha256sum` file (`<sum>  <name>` on each line). Every extracted entry must be in it,
+    // and so must the archive itself (by file name) if it is to be checked before extraction.
+    #[serde(default)]
+    pub checksums: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Zip,
+    Tar,
+    Gzip,
+    Zstd,
+}
+
+impl Extract {
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        let mut inputs = vec![self.archive.clone()];
+        inputs.extend(self.checksums.clone());
+        inputs
+    }
+
+    pub fn outputs(&self) -> Vec<PathBuf> {
+        self.files.values().cloned().collect()
+    }
+
+    pub fn run(&self) -> Result<(), ErrorKind> {
+        let checksums = match &self.checksums {
+            Some(path) => Some(read_checksums(path)?),
+            None => None,
+        };
+
+        // Check the archive itself first, if the manifest lists it
+        let archive_name = file_name(&self.archive);
+        if let Some(expected) = checksums.as_ref().and_then(|c| c.get(&archive_name)) {
+            let mut file = self.open()?;
+            let mut hasher = Sha256::new();
+            io::copy(&mut file, &mut hasher).map_err(|e| ErrorKind::io(&self.archive, e))?;
+            self.verify(&archive_name, expected, hasher)?;
+        }
+
+        let mut extraction = Extraction {
+            step: self,
+            targets: self.targets()?,
+            checksums,
+            matched: BTreeSet::new(),
+        };
+
+        let format = match self.format {
+            Some(format) => format,
+            None => self.detect()?,
+        };
+        match format {
+            Format::Zip => self.extract_zip(&mut extraction)?,
+            Format::Tar => self.extract_tar(self.open()?, &mut extraction)?,
+            Format::Gzip => {
+                let reader = flate2::read::MultiGzDecoder::new(self.open()?);
+                self.extract_compressed(reader, &mut extraction)?;
+            }
+            Format::Zstd => {
+                let reader = zstd::Decoder::new(self.open()?)
+                    .map_err(|e| ErrorKind::io(&self.archive, e))?;
+                self.extract_compressed(reader, &mut extraction)?;
+            }
+        }
+
+        // A name or a pattern that matched nothing is most likely a typo, or a new release
+        // that renamed its files
+        let unmatched: Vec<&str> = self
+            .files
+            .keys()
+            .filter(|n| !extraction.matched.contains(n.as_str()))
+            .map(|n| n.as_str())
+            .collect();
+        if !unmatched.is_empty() {
+            return Err(self.error(format!("nothing matches {}", unmatched.join(", "))));
+        }
+
+        Ok(())
+    }
+
+    fn open(&self) -> Result<BufReader<File>, ErrorKind> {
+        let file = File::open(&self.archive).map_err(|e| ErrorKind::io(&self.archive, e))?;
+        Ok(BufReader::new(file))
+    }
+
+    fn error(&self, reason: String) -> ErrorKind {
+        ErrorKind::Archive(self.archive.clone(), reason)
+    }
+
+    fn verify(&self, name: &str, expected: &str, hasher: Sha256) -> Result<(), ErrorKind> {
+        let actual = format!("{:x}", hasher.finalize());
+        if actual == expected {
+            Ok(())
+        } else {
+            let reason = format!("the SHA-256 sum of {name} is {actual}, not {expected}");
+            Err(self.error(reason))
+        }
+    }
+
+    // The names to extract, and the patterns to match (exact names first)
+    fn targets(&self) -> Result<Vec<Target<'_>>, ErrorKind> {
+        let mut targets = vec![];
+        for (name, output) in &self.files {
+            let pattern = if name.contains(['*', '?', '[']) {
+                let pattern = Pattern::new(name)
+                    .map_err(|e| ErrorKind::Config(format!("pattern {name}: {e}")))?;
+                Some(pattern)
+            } else {
+                None
+            };
+            targets.push(Target {
+                name,
+                pattern,
+                output,
+            });
+        }
+        targets.sort_by_key(|t| t.pattern.is_some());
+        Ok(targets)
+    }
+
+    // Every compressed format starts with its own magic number, and a tar file has `ustar`
+    // at the end of the name of its first entry
+    fn detect(&self) -> Result<Format, ErrorKind> {
+        let head = read_head(&mut self.open()?).map_err(|e| ErrorKind::io(&self.archive, e))?;
+        if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+            Ok(Format::Zip)
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            Ok(Format::Gzip)
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Ok(Format::Zstd)
+        } else if is_tar(&head) {
+            Ok(Format::Tar)
+        } else {
+            Err(self.error("not a ZIP, tar, gzip or zstd archive".to_owned()))
+        }
+    }
+
+    fn extract_zip(&self, extraction: &mut Extraction) -> Result<(), ErrorKind> {
+        let zip_error = |e: zip::result::ZipError| self.error(e.to_string());
+        let mut archive = zip::ZipArchive::new(self.open()?).map_err(zip_error)?;
+
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(zip_error)?;
+            if entry.is_dir() {
+                continue;
+            }
+            let name = entry.name().to_owned();
+            extraction.write(&name, &mut entry)?;
+        }
+        Ok(())
+    }
+
+    // Only regular files are extracted: a link could point outside of the destination
+    fn extract_tar(&self, reader: impl Read, extraction: &mut Extraction) -> Result<(), ErrorKind> {
+        let tar_error = |e: io::Error| self.error(e.to_string());
+        let mut archive = tar::Archive::new(reader);
+
+        for entry in archive.entries().map_err(tar_error)? {
+            let mut entry = entry.map_err(tar_error)?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            extraction.write(&name, &mut entry)?;
+        }
+        Ok(())
+    }
+
+    // A compressed file holds either a tar archive or a single file, named like the archive
+    // without its extension (e.g. `census.csv` in `census.csv.gz`)
+    fn extract_compressed(
+        &self,
+        mut reader: impl Read,
+        extraction: &mut Extraction,
+    ) -> Result<(), ErrorKind> {
+        let head = read_head(&mut reader).map_err(|e| ErrorKind::io(&self.archive, e))?;
+        let mut reader = Cursor::new(head).chain(reader);
+
+        if is_tar(reader.get_ref().0.get_ref()) {
+            self.extract_tar(reader, extraction)
+        } else {
+            let name = self
+                .archive
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            extraction.write(&name, &mut reader)
+        }
+    }
+}
+
+struct Target<'a> {
+    name: &'a str,
+    pattern: Option<Pattern>,
+    output: &'a PathBuf,
+}
+
+struct Extraction<'a> {
+    step: &'a Extract,
+    targets: Vec<Target<'a>>,
+    checksums: Option<BTreeMap<String, String>>,
+    matched: BTreeSet<&'a str>,
+}
+
+impl Extraction<'_> {
+    // Stream an entry to its destination, if it has one
+    fn write(&mut self, name: &str, entry: &mut dyn Read) -> Result<(), ErrorKind> {
+        let Some(relative) = safe_name(name) else {
+            let reason =
+                format!("refusing entry {name}, which would be written outside of its folder");
+            return Err(self.step.error(reason));
+        };
+
+        // `*` does not match `/`, so that `*.csv` only matches the top of the archive
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::default()
+        };
+        let Some(target) = self.targets.iter().find(|t| match &t.pattern {
+            Some(pattern) => pattern.matches_with(name, options),
+            None => t.name == name,
+        }) else {
+            return Ok(());
+        };
+        let output = match target.pattern {
+            Some(_) => target.output.join(relative),
+            None => target.output.clone(),
+        };
+        self.matched.insert(target.name);
+
+        let expected = match &self.checksums {
+            Some(checksums) => match checksums.get(name) {
+                Some(expected) => Some(expected.as_str()),
+                None => return Err(self.step.error(format!("{name} is not in the checksums"))),
+            },
+            None => None,
+        };
+
+        files::create_parent(&output)?;
+        let file = File::create(&output).map_err(|e| ErrorKind::io(&output, e))?;
+        let mut writer = HashingWriter {
+            file,
+            hasher: Sha256::new(),
+        };
+        io::copy(entry, &mut writer).map_err(|e| ErrorKind::io(&output, e))?;
+
+        if let Some(expected) = expected
+            && let Err(e) = self.step.verify(name, expected, writer.hasher)
+        {
+            let _ = fs::remove_file(&output);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+// Write to a file and compute its SHA-256 sum at the same time
+struct HashingWriter {
+    file: File,
+    hasher: Sha256,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// The name of an entry as a relative path, or `None` if it is absolute or goes up with `..`
+fn safe_name(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+// The first block of a file, or all of it if it is shorter
+fn read_head(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut head = vec![];
+    reader.take(512).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+fn is_tar(head: &[u8]) -> bool {
+    head.get(257..262) == Some(b"ustar".as_slice())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// Read a `sha256sum` file: `<sum>  <name>`, or `<sum> *<name>` for binary mode
+fn read_checksums(path: &Path) -> Result<BTreeMap<String, String>, ErrorKind> {
+    let text = fs::read_to_string(path).map_err(|e| ErrorKind::io(path, e))?;
+    let mut checksums = BTreeMap::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((sum, name)) = line.split_once(char::is_whitespace) else {
+            let reason = format!("{}: no file name in `{line}`", path.display());
+            return Err(ErrorKind::Config(reason));
+        };
+        let name = name.trim_start();
+        let name = name.strip_prefix('*').unwrap_or(name);
+        checksums.insert(name.to_owned(), sum.to_lowercase());
+    }
+    Ok(checksums)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A directory of its own in the temporary directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("extract_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A tar archive of `entries`, written without the checks of `tar::Builder` (which would
+    // refuse `..`)
+    fn tar(dir: &Path, entries: &[(&str, &str)]) -> PathBuf {
+        let path = dir.join("archive.tar");
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        for (name, content) in entries {
+            let mut header = tar::Header::new_ustar();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+        path
+    }
+
+    fn extract(archive: PathBuf, files: &[(&str, PathBuf)]) -> Extract {
+        Extract {
+            archive,
+            format: None,
+            files: files
+                .iter()
+                .map(|(n, p)| (n.to_string(), p.clone()))
+                .collect(),
+            checksums: None,
+        }
+    }
+
+    #[test]
+    fn safe_names_stay_in_their_folder() {
+        assert_eq!(safe_name("census.csv"), Some(PathBuf::from("census.csv")));
+        assert_eq!(
+            safe_name("./data/census.csv"),
+            Some(PathBuf::from("data/census.csv"))
+        );
+        for unsafe_name in [
+            "../census.csv",
+            "data/../../census.csv",
+            "/etc/passwd",
+            "",
+            ".",
+        ] {
+            assert_eq!(safe_name(unsafe_name), None, "{unsafe_name}");
+        }
+    }
+
+    #[test]
+    fn matches_names_then_patterns() {
+        let dir = temp_dir("patterns");
+        let archive = tar(
+            &dir,
+            &[
+                ("census.csv", "census"),
+                ("codeset.csv", "codeset"),
+                ("notes.txt", "notes"),
+                ("data/nested.csv", "nested"),
+            ],
+        );
+        let step = extract(
+            archive,
+            &[
+                ("*.csv", dir.join("all")),
+                ("census.csv", dir.join("census.csv")),
+                ("[cd]ata/*.csv", dir.join("nested")),
+            ],
+        );
+        step.run().unwrap();
+
+        // An exact name wins over a pattern, and `*` does not go into folders
+        let read = |path: &str| fs::read_to_string(dir.join(path)).unwrap();
+        assert_eq!(read("census.csv"), "census");
+        assert_eq!(read("all/codeset.csv"), "codeset");
+        assert!(!dir.join("all/census.csv").exists());
+        assert!(!dir.join("all/data").exists());
+        assert_eq!(read("nested/data/nested.csv"), "nested");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_entries_outside_of_the_folder() {
+        let dir = temp_dir("traversal");
+        let archive = tar(&dir, &[("../escaped.csv", "escaped")]);
+        let error = extract(archive, &[("*.csv", dir.join("out"))])
+            .run()
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("refusing entry ../escaped.csv"),
+            "{error}"
+        );
+        assert!(!dir.join("escaped.csv").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_what_matches_nothing() {
+        let dir = temp_dir("unmatched");
+        let archive = tar(&dir, &[("census.csv", "census")]);
+        let step = extract(archive.clone(), &[("*.parquet", dir.join("out"))]);
+        let error = step.run().unwrap_err();
+        assert!(
+            error.to_string().contains("nothing matches *.parquet"),
+            "{error}"
+        );
+
+        let error = extract(archive, &[("[.csv", dir.join("out"))])
+            .run()
+            .unwrap_err();
+        assert!(matches!(error, ErrorKind::Config(_)), "{error}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
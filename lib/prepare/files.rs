@@ -0,0 +1,83 @@
+use super::error::ErrorKind;
+use polars::prelude::*;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// When a file, or the newest file of a directory, was last modified
+pub fn modified(path: &Path) -> Result<u64, ErrorKind> {
+    let metadata = fs::metadata(path).map_err(|e| ErrorKind::io(path, e))?;
+    let mut newest = nanos(metadata.modified().map_err(|e| ErrorKind::io(path, e))?);
+
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).map_err(|e| ErrorKind::io(path, e))? {
+            let entry = entry.map_err(|e| ErrorKind::io(path, e))?;
+            newest = newest.max(modified(&entry.path())?);
+        }
+    }
+    Ok(newest)
+}
+
+// When the newest of several files and directories was last modified (0 if there are none)
+pub fn newest(paths: &[PathBuf]) -> Result<u64, ErrorKind> {
+    paths
+        .iter()
+        .map(|p| modified(p))
+        .try_fold(0, |newest, m| Ok(newest.max(m?)))
+}
+
+// Nanoseconds since the epoch: seconds would miss a file rewritten within the same second
+fn nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX))
+}
+
+pub fn create_parent(path: &Path) -> Result<(), ErrorKind> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            fs::create_dir_all(parent).map_err(|e| ErrorKind::io(parent, e))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Start a directory over, without what a failed run left in it
+pub fn recreate_dir(dir: &Path) -> Result<(), ErrorKind> {
+    match fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(ErrorKind::io(dir, e)),
+        _ => {}
+    }
+    fs::create_dir_all(dir).map_err(|e| ErrorKind::io(dir, e))
+}
+
+// The files of a directory with an extension, sorted by name
+pub fn list(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, ErrorKind> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir).map_err(|e| ErrorKind::io(dir, e))? {
+        let path = entry.map_err(|e| ErrorKind::io(dir, e))?.path();
+        if path.extension().is_some_and(|e| e == extension) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub fn pl_path(path: &Path) -> PlPath {
+    PlPath::from_string(path.display().to_string())
+}
+
+pub fn read_csv(path: &Path) -> Result<LazyFrame, ErrorKind> {
+    let lf = LazyCsvReader::new(pl_path(path))
+        .with_infer_schema_length(Some(10_000)) // Default 100, missing = String
+        .with_has_header(true)
+        .finish()?;
+    Ok(lf)
+}
+
+pub fn write_csv(df: &mut DataFrame, path: &Path) -> Result<(), ErrorKind> {
+    create_parent(path)?;
+    let mut file = File::create(path).map_err(|e| ErrorKind::io(path, e))?;
+    CsvWriter::new(&mut file).finish(df)?;
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use super::error::ErrorKind;
+use super::files;
+use crate::partition::Partitioning;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+// Put every Parquet file of a directory together in a large CSV, a large Parquet file and a
+// partitioned Parquet dataset, with the streaming engine so that it is never all in memory
+#[derive(Debug, Deserialize)]
+pub struct Large {
+    pub input_dir: PathBuf,
+    pub csv: PathBuf,
+    pub parquet: PathBuf,
+    pub partitioned: PathBuf,
+    #[serde(default = "default_partitioning")]
+    pub partitioning: Partitioning, // Keys and file sizes of the partitioned dataset
+    #[serde(default)]
+    pub drop: Vec<String>, // Columns left out of the large files
+}
+
+fn default_partitioning() -> Partitioning {
+    Partitioning::by(&["region", "age_group"])
+}
+
+impl Large {
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        vec![self.input_dir.clone()]
+    }
+
+    pub fn outputs(&self) -> Vec<PathBuf> {
+        vec![
+            self.csv.clone(),
+            self.parquet.clone(),
+            self.partitioned.clone(),
+        ]
+    }
+
+    pub fn run(&self) -> Result<(), ErrorKind> {
+        let mut lf_vec = vec![];
+        for parquet in files::list(&self.input_dir, "parquet")? {
+            let args = ScanArgsParquet::default();
+            lf_vec.push(LazyFrame::scan_parquet(files::pl_path(&parquet), args)?);
+        }
+        let lf = concat(lf_vec, UnionArgs::default())?;
+        let lf = lf.select([all().exclude_cols(self.drop.clone()).as_expr()]);
+
+        // Each output is a streaming query of its own: the input files are read again in batches
+        // for each of them, and never all in memory
+        files::create_parent(&self.parquet)?;
+        lf.clone()
+            .sink_parquet(
+                SinkTarget::Path(files::pl_path(&self.parquet)),
+                ParquetWriteOptions::default(),
+                None,
+                SinkOptions::default(),
+            )?
+            .collect_with_engine(Engine::Streaming)?;
+
+        files::create_parent(&self.csv)?;
+        lf.clone()
+            .sink_csv(
+                SinkTarget::Path(files::pl_path(&self.csv)),
+                CsvWriterOptions::default(),
+                None,
+                SinkOptions::default(),
+            )?
+            .collect_with_engine(Engine::Streaming)?;
+
+        // A directory `name=value` for each value of the partition keys, in the same order
+        let written = self.partitioning.write(lf, &self.partitioned)?;
+        println!("Partitioned: {written}");
+
+        Ok(())
+    }
+}
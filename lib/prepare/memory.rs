@@ -0,0 +1,28 @@
+use std::fs;
+
+// The peak memory (resident set size) of the process, on Linux: `VmHWM` in `/proc/self/status`.
+// Writing 5 to `/proc/self/clear_refs` resets it, so that it can be measured for each step.
+
+pub fn reset_peak() {
+    let _ = fs::write("/proc/self/clear_refs", "5");
+}
+
+pub fn peak() -> Option<Memory> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(Memory(kb * 1024))
+}
+
+pub struct Memory(u64);
+
+impl std::fmt::Display for Memory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mb = self.0 as f64 / (1024.0 * 1024.0);
+        if mb >= 1024.0 {
+            write!(f, "{:.1} GB", mb / 1024.0)
+        } else {
+            write!(f, "{mb:.0} MB")
+        }
+    }
+}
@@ -0,0 +1,337 @@
+// The `prepare` binary: create `./data` from the steps of a TOML pipeline (`prepare.toml`),
+// like the `1_2_x` examples do one after the other
+
+mod config;
+mod error;
+mod expand;
+mod extract;
+mod files;
+mod large;
+mod memory;
+mod parquet;
+mod rename;
+mod s3;
+mod sql;
+mod state;
+mod synthesize;
+
+pub use config::{Action, Pipeline, Step};
+pub use error::{Error, ErrorKind};
+pub use state::State;
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+
+// How to run a pipeline
+#[derive(Debug, Default)]
+pub struct Options {
+    pub steps: Vec<String>, // Only these steps and what they depend on (all but optional if empty)
+    pub all: bool,          // Also run the optional steps
+    pub force: bool,        // Run the steps even if their outputs are up to date
+    pub dry_run: bool,      // Only print what would run
+}
+
+// Run the steps of a pipeline in dependency order, skipping those that are up to date. The
+// state is saved after each step, so a failed run resumes at the step that failed.
+pub fn run(pipeline: &Pipeline, options: &Options) -> Result<(), Error> {
+    let order = order(pipeline)?;
+    let selected = selected(pipeline, options)?;
+    let mut state = State::load(&pipeline.state)?;
+
+    if !options.dry_run {
+        for dir in &pipeline.directories {
+            fs::create_dir_all(dir).map_err(|e| Error::new(ErrorKind::io(dir, e)))?;
+        }
+    }
+
+    let mut ran: HashSet<&str> = HashSet::new();
+    for step in order
+        .into_iter()
+        .filter(|s| selected.contains(s.name.as_str()))
+    {
+        let name = step.name.as_str();
+        let inputs = step.action.inputs();
+        let outputs = step.action.outputs();
+
+        let dependency_ran = dependencies(pipeline, step).iter().any(|d| ran.contains(d));
+        let reason = if options.force {
+            Some("it was forced")
+        } else if dependency_ran {
+            Some("a step it depends on ran")
+        } else {
+            state.stale(name, &step.fingerprint, &inputs, &outputs)?
+        };
+        let Some(reason) = reason else {
+            eprintln!("Up to date: {name}");
+            continue;
+        };
+
+        ran.insert(name);
+        if options.dry_run {
+            eprintln!("Would run: {name} ({reason})");
+            continue;
+        }
+
+        eprintln!("Running: {name} ({reason})");
+        if let Some(missing) = inputs.iter().find(|i| !i.exists()) {
+            let err = std::io::Error::from(std::io::ErrorKind::NotFound);
+            return Err(Error::in_step(name, ErrorKind::io(missing, err)));
+        }
+        state.forget(name)?;
+        let newest_input = files::newest(&inputs).map_err(|kind| Error::in_step(name, kind))?;
+        memory::reset_peak();
+        let start = Instant::now();
+        step.action
+            .run()
+            .map_err(|kind| Error::in_step(name, kind))?;
+        let seconds = start.elapsed().as_secs_f64();
+        match memory::peak() {
+            Some(peak) => eprintln!("Done: {name} in {seconds:.1} s, peak memory {peak}"),
+            None => eprintln!("Done: {name} in {seconds:.1} s"),
+        }
+        state.complete(name, &step.fingerprint, newest_input)?;
+    }
+
+    Ok(())
+}
+
+// The steps in an order where every step comes after those it depends on
+fn order(pipeline: &Pipeline) -> Result<Vec<&Step>, Error> {
+    let mut order: Vec<&Step> = vec![];
+    let mut placed: HashSet<&str> = HashSet::new();
+
+    while order.len() < pipeline.steps.len() {
+        let ready: Vec<&Step> = pipeline
+            .steps
+            .iter()
+            .filter(|s| !placed.contains(s.name.as_str()))
+            .filter(|s| dependencies(pipeline, s).iter().all(|d| placed.contains(d)))
+            .collect();
+        if ready.is_empty() {
+            let cycle = pipeline
+                .steps
+                .iter()
+                .filter(|s| !placed.contains(s.name.as_str()))
+                .map(|s| s.name.clone())
+                .collect();
+            return Err(Error::new(ErrorKind::Cycle(cycle)));
+        }
+
+        // Keep the order of the file among the steps that are ready
+        placed.insert(ready[0].name.as_str());
+        order.push(ready[0]);
+    }
+
+    Ok(order)
+}
+
+// The steps a step depends on: its `after` steps and those producing its inputs
+fn dependencies<'a>(pipeline: &'a Pipeline, step: &Step) -> Vec<&'a str> {
+    let inputs = step.action.inputs();
+    pipeline
+        .steps
+        .iter()
+        .filter(|other| other.name != step.name)
+        .filter(|other| {
+            step.after.contains(&other.name)
+                || other
+                    .action
+                    .outputs()
+                    .iter()
+                    .any(|output| inputs.iter().any(|input| overlaps(input, output)))
+        })
+        .map(|other| other.name.as_str())
+        .collect()
+}
+
+// Whether one path is the other, or a directory containing it
+fn overlaps(a: &Path, b: &Path) -> bool {
+    let (a, b) = (normalize(a), normalize(b));
+    a.starts_with(&b) || b.starts_with(&a)
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+// The names of the steps to run, with the steps they depend on
+fn selected<'a>(pipeline: &'a Pipeline, options: &Options) -> Result<BTreeSet<&'a str>, Error> {
+    let mut selected = BTreeSet::new();
+    let mut pending: Vec<&Step> = match options.steps.is_empty() {
+        true => pipeline
+            .steps
+            .iter()
+            .filter(|s| options.all || !s.optional)
+            .collect(),
+        false => options
+            .steps
+            .iter()
+            .map(|name| pipeline.step(name))
+            .collect::<Result<_, _>>()?,
+    };
+
+    while let Some(step) = pending.pop() {
+        if selected.insert(step.name.as_str()) {
+            for dependency in dependencies(pipeline, step) {
+                pending.push(pipeline.step(dependency)?);
+            }
+        }
+    }
+
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A directory of its own in the temporary directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("prepare_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A pipeline of `parquet` steps, each `(name, input_dir, output_dir, extra)` under `dir`
+    fn pipeline(dir: &Path, steps: &[(&str, &str, &str, &str)]) -> Result<Pipeline, Error> {
+        let mut toml = format!("state = {:?}\n", dir.join("state.toml"));
+        for (name, input, output, extra) in steps {
+            toml.push_str(&format!(
+                "[[step]]\nname = {name:?}\nkind = \"parquet\"\ninput_dir = {:?}\noutput_dir = {:?}\n{extra}\n",
+                dir.join(input),
+                dir.join(output)
+            ));
+        }
+        let path = dir.join("prepare.toml");
+        fs::write(&path, toml).unwrap();
+        Pipeline::load(&path)
+    }
+
+    fn names<'a>(steps: impl IntoIterator<Item = &'a Step>) -> Vec<&'a str> {
+        steps.into_iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn orders_steps_after_their_inputs() {
+        let dir = temp_dir("order");
+        let pipeline = pipeline(
+            &dir,
+            &[
+                ("last", "c", "d", "after = [\"other\"]"),
+                ("middle", "b/sub", "c", ""),
+                ("first", "a", "b", ""),
+                ("other", "x", "y", ""),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            names(order(&pipeline).unwrap()),
+            ["first", "middle", "other", "last"]
+        );
+        let step = pipeline.step("last").unwrap();
+        assert_eq!(dependencies(&pipeline, step), ["middle", "other"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_cycles_and_unknown_steps() {
+        let dir = temp_dir("cycle");
+        let cyclic = pipeline(&dir, &[("a", "x", "y", ""), ("b", "y", "x", "")]).unwrap();
+        let error = order(&cyclic).unwrap_err();
+        assert!(matches!(&error.kind, ErrorKind::Cycle(steps) if steps == &["a", "b"]));
+
+        let error = pipeline(&dir, &[("a", "x", "y", "after = [\"missing\"]")]).unwrap_err();
+        assert_eq!(error.step.as_deref(), Some("a"));
+        assert!(matches!(&error.kind, ErrorKind::UnknownStep(name) if name == "missing"));
+
+        let valid = pipeline(&dir, &[("a", "x", "y", "")]).unwrap();
+        let options = Options {
+            steps: vec!["missing".to_owned()],
+            ..Options::default()
+        };
+        let error = selected(&valid, &options).unwrap_err();
+        assert!(matches!(&error.kind, ErrorKind::UnknownStep(name) if name == "missing"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn selects_steps_with_their_dependencies() {
+        let dir = temp_dir("selected");
+        let pipeline = pipeline(
+            &dir,
+            &[
+                ("first", "a", "b", ""),
+                ("second", "b", "c", ""),
+                ("upload", "c", "d", "optional = true"),
+                ("apart", "x", "y", ""),
+            ],
+        )
+        .unwrap();
+
+        let select = |steps: &[&str], all| {
+            let options = Options {
+                steps: steps.iter().map(|s| s.to_string()).collect(),
+                all,
+                ..Options::default()
+            };
+            selected(&pipeline, &options)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select(&[], false), ["apart", "first", "second"]);
+        assert_eq!(select(&[], true), ["apart", "first", "second", "upload"]);
+        assert_eq!(select(&["upload"], false), ["first", "second", "upload"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skips_up_to_date_steps_and_resumes_at_the_failed_one() {
+        let dir = temp_dir("resume");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        let pipeline = pipeline(
+            &dir,
+            &[
+                ("first", "a", "b", ""),
+                ("second", "other", "c", "after = [\"first\"]"),
+            ],
+        )
+        .unwrap();
+        let options = Options::default();
+
+        // `first` recreates `b`, which removes the marker
+        let marker = dir.join("b").join("marker");
+        let first_ran = || !marker.exists();
+
+        // `second` fails, as its input does not exist
+        let error = run(&pipeline, &options).unwrap_err();
+        assert_eq!(error.step.as_deref(), Some("second"));
+        fs::write(&marker, "").unwrap();
+
+        // The state kept `first`, so the next run starts at `second`
+        fs::create_dir_all(dir.join("other")).unwrap();
+        run(&pipeline, &options).unwrap();
+        assert!(!first_ran());
+        assert!(dir.join("c").exists());
+
+        // Nothing changed
+        run(&pipeline, &options).unwrap();
+        assert!(!first_ran());
+
+        // An input changed
+        fs::write(dir.join("a").join("new.txt"), "").unwrap();
+        run(&pipeline, &options).unwrap();
+        assert!(first_ran());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+use super::error::ErrorKind;
+use super::files;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+// Convert every CSV of a directory to a Parquet file of the same name, streaming it in batches
+// so that a file is never all in memory
+#[derive(Debug, Deserialize)]
+pub struct Parquet {
+    pub input_dir: PathBuf,
+    pub output_dir: PathBuf,
+}
+
+impl Parquet {
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        vec![self.input_dir.clone()]
+    }
+
+    pub fn outputs(&self) -> Vec<PathBuf> {
+        vec![self.output_dir.clone()]
+    }
+
+    pub fn run(&self) -> Result<(), ErrorKind> {
+        files::recreate_dir(&self.output_dir)?;
+
+        for csv in files::list(&self.input_dir, "csv")? {
+            let parquet = self.output_dir.join(
+                csv.with_extension("parquet")
+                    .file_name()
+                    .unwrap_or_default(),
+            );
+
+            files::read_csv(&csv)?
+                .sink_parquet(
+                    SinkTarget::Path(files::pl_path(&parquet)),
+                    ParquetWriteOptions::default(),
+                    None,
+                    SinkOptions::default(),
+                )?
+                .collect_with_engine(Engine::Streaming)?;
+        }
+
+        Ok(())
+    }
+}
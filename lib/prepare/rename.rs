@@ -0,0 +1,59 @@
+use super::error::ErrorKind;
+use super::files;
+use crate::census::Variable;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+// Give the census variables their short names, in the data and in the codeset
+#[derive(Debug, Deserialize)]
+pub struct Rename {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub codeset_input: PathBuf,
+    pub codeset_output: PathBuf,
+}
+
+impl Rename {
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        vec![self.input.clone(), self.codeset_input.clone()]
+    }
+
+    pub fn outputs(&self) -> Vec<PathBuf> {
+        vec![self.output.clone(), self.codeset_output.clone()]
+    }
+
+    pub fn run(&self) -> Result<(), ErrorKind> {
+        let renamed: Vec<(&str, &str)> = Variable::ALL
+            .into_iter()
+            .filter_map(|v| v.original_name().map(|original| (original, v.name())))
+            .collect();
+
+        // Data
+        let columns: Vec<Expr> = renamed
+            .iter()
+            .map(|(original, name)| col(*original).alias(*name))
+            .collect();
+        let mut df = files::read_csv(&self.input)?.select(columns).collect()?;
+        files::write_csv(&mut df, &self.output)?;
+
+        // Codeset, read as text so that the codes are written back as they are
+        let lf = LazyCsvReader::new(files::pl_path(&self.codeset_input))
+            .with_infer_schema_length(Some(0))
+            .with_has_header(true)
+            .finish()?;
+        let originals: Vec<&str> = renamed.iter().map(|(original, _)| *original).collect();
+        let names: Vec<&str> = renamed.iter().map(|(_, name)| *name).collect();
+        let mut df = lf
+            .with_column(col("variable").replace_strict(
+                lit(Series::from_iter(originals)),
+                lit(Series::from_iter(names)),
+                None,
+                Some(DataType::String),
+            ))
+            .collect()?;
+        files::write_csv(&mut df, &self.codeset_output)?;
+
+        Ok(())
+    }
+}
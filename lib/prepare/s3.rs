@@ -0,0 +1,102 @@
+use super::error::ErrorKind;
+use crate::sync::{self, SyncOptions};
+use crate::upload;
+use aws_sdk_s3::Client;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+// Sync files and directories to an S3 bucket (e.g. a local MinIO server), created if needed:
+// only what changed since the last run is uploaded
+#[derive(Debug, Deserialize)]
+pub struct S3 {
+    pub files: Vec<PathBuf>, // Directories are synced with everything in them
+    pub root: PathBuf,       // The keys are the paths relative to it
+    #[serde(default = "default_bucket")]
+    pub bucket: String,
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(default = "default_credential")]
+    pub username: String,
+    #[serde(default = "default_credential")]
+    pub password: String,
+    #[serde(default)]
+    pub sync: SyncOptions, // Deletion of the extra objects, dry run and uploads
+}
+
+fn default_bucket() -> String {
+    "census".to_owned()
+}
+
+fn default_endpoint() -> String {
+    "http://127.0.0.1:9000".to_owned()
+}
+
+fn default_region() -> String {
+    "us-east-1".to_owned()
+}
+
+fn default_credential() -> String {
+    "minioadmin".to_owned()
+}
+
+fn s3_error(e: impl std::error::Error) -> ErrorKind {
+    ErrorKind::S3(aws_sdk_s3::error::DisplayErrorContext(e).to_string())
+}
+
+impl S3 {
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        self.files.clone()
+    }
+
+    pub fn run(&self) -> Result<(), ErrorKind> {
+        let runtime = tokio::runtime::Runtime::new().map_err(|e| ErrorKind::S3(e.to_string()))?;
+        runtime.block_on(self.upload())
+    }
+
+    async fn upload(&self) -> Result<(), ErrorKind> {
+        let client = upload::client(&self.endpoint, &self.region, &self.username, &self.password);
+
+        self.create_bucket(&client).await?;
+
+        for path in &self.files {
+            let key = path
+                .strip_prefix(&self.root)
+                .map_err(|_| {
+                    let reason = format!("{} is not in {}", path.display(), self.root.display());
+                    ErrorKind::Config(reason)
+                })?
+                .to_string_lossy()
+                .replace('\\', "/");
+            let synced = sync::sync(&client, path, &self.bucket, &key, &self.sync).await?;
+            println!("Synced {}: {synced}", path.display());
+        }
+
+        Ok(())
+    }
+
+    // The bucket is kept between runs, with its unfinished uploads, so that an upload
+    // interrupted by the previous run is resumed
+    async fn create_bucket(&self, client: &Client) -> Result<(), ErrorKind> {
+        let bucket = self.bucket.as_str();
+        let buckets = client.list_buckets().send().await.map_err(s3_error)?;
+        if buckets.buckets().iter().any(|b| b.name() == Some(bucket)) {
+            return Ok(());
+        }
+
+        let constraint = aws_sdk_s3::types::BucketLocationConstraint::from(self.region.as_str());
+        let cfg = aws_sdk_s3::types::CreateBucketConfiguration::builder()
+            .location_constraint(constraint)
+            .build();
+        client
+            .create_bucket()
+            .create_bucket_configuration(cfg)
+            .bucket(bucket)
+            .send()
+            .await
+            .map_err(s3_error)?;
+
+        Ok(())
+    }
+}
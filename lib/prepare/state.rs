@@ -0,0 +1,192 @@
+use super::error::{Error, ErrorKind};
+use super::files;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The steps that completed, saved after each one so that a failed run can resume
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    steps: BTreeMap<String, Record>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    fingerprint: String,
+    inputs: u64, // When the newest input was last modified as the step started (`files::newest`)
+}
+
+impl State {
+    pub fn load(path: &Path) -> Result<State, Error> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(Error::new(ErrorKind::io(path, e))),
+        };
+
+        // An unreadable state only means that every step runs again
+        let mut state: State = toml::from_str(&content).unwrap_or_default();
+        state.path = path.to_path_buf();
+        Ok(state)
+    }
+
+    // Why a step has to run, or `None` if its outputs are up to date
+    pub fn stale(
+        &self,
+        name: &str,
+        fingerprint: &str,
+        inputs: &[PathBuf],
+        outputs: &[PathBuf],
+    ) -> Result<Option<&'static str>, Error> {
+        let Some(record) = self.steps.get(name) else {
+            return Ok(Some("it never ran"));
+        };
+        if record.fingerprint != fingerprint {
+            return Ok(Some("its configuration changed"));
+        }
+        if outputs.iter().any(|output| !output.exists()) {
+            return Ok(Some("an output is missing"));
+        }
+        // Compared for equality, as a file written during the step can have an older time
+        let newest = files::newest(inputs).map_err(|kind| Error::in_step(name, kind))?;
+        if newest != record.inputs {
+            return Ok(Some("an input changed"));
+        }
+        Ok(None)
+    }
+
+    // Record a completed step, with `files::newest` of its inputs as it started, and save the state
+    pub fn complete(&mut self, name: &str, fingerprint: &str, inputs: u64) -> Result<(), Error> {
+        let record = Record {
+            fingerprint: fingerprint.to_owned(),
+            inputs,
+        };
+        self.steps.insert(name.to_owned(), record);
+        self.save()
+    }
+
+    // Forget a step, so that it runs again even if it fails half-way
+    pub fn forget(&mut self, name: &str) -> Result<(), Error> {
+        if self.steps.remove(name).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let content = toml::to_string(self).expect("a state is always valid TOML");
+        files::create_parent(&self.path).map_err(Error::new)?;
+        fs::write(&self.path, content).map_err(|e| Error::new(ErrorKind::io(&self.path, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    // A directory of its own in the temporary directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("state_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Write a file last modified at a given time
+    fn write(path: &Path, time: SystemTime) {
+        fs::write(path, "a").unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn finds_stale_steps() {
+        let dir = temp_dir("stale");
+        let (input, output) = (dir.join("input.csv"), dir.join("output.csv"));
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        write(&input, time);
+        write(&output, time);
+        let (inputs, outputs) = ([input.clone()], [output.clone()]);
+        let stale = |state: &State, fingerprint| {
+            state.stale("step", fingerprint, &inputs, &outputs).unwrap()
+        };
+
+        let mut state = State::load(&dir.join("state.toml")).unwrap();
+        assert_eq!(stale(&state, "v1"), Some("it never ran"));
+
+        let newest = files::newest(&inputs).unwrap();
+        state.complete("step", "v1", newest).unwrap();
+        assert_eq!(stale(&state, "v1"), None);
+        assert_eq!(stale(&state, "v2"), Some("its configuration changed"));
+
+        // Saved, and read back
+        let state = State::load(&dir.join("state.toml")).unwrap();
+        assert_eq!(stale(&state, "v1"), None);
+
+        // Rewritten within the same second
+        write(&input, time + Duration::from_millis(500));
+        assert_eq!(stale(&state, "v1"), Some("an input changed"));
+        write(&input, time);
+        assert_eq!(stale(&state, "v1"), None);
+
+        fs::remove_file(&output).unwrap();
+        assert_eq!(stale(&state, "v1"), Some("an output is missing"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finds_changes_in_directories() {
+        let dir = temp_dir("directory");
+        let input = dir.join("input");
+        fs::create_dir_all(input.join("sub")).unwrap();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        write(&input.join("sub").join("file.csv"), time);
+
+        let mut state = State::load(&dir.join("state.toml")).unwrap();
+        let inputs = [input.clone()];
+        state
+            .complete("step", "v1", files::newest(&inputs).unwrap())
+            .unwrap();
+        assert_eq!(state.stale("step", "v1", &inputs, &[]).unwrap(), None);
+
+        write(&input.join("sub").join("new.csv"), time);
+        assert_eq!(
+            state.stale("step", "v1", &inputs, &[]).unwrap(),
+            Some("an input changed")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn forgets_steps_and_ignores_an_unreadable_state() {
+        let dir = temp_dir("forget");
+        let path = dir.join("state.toml");
+
+        let mut state = State::load(&path).unwrap();
+        state.complete("step", "v1", 0).unwrap();
+        state.forget("step").unwrap();
+        let state = State::load(&path).unwrap();
+        assert_eq!(
+            state.stale("step", "v1", &[], &[]).unwrap(),
+            Some("it never ran")
+        );
+
+        fs::write(&path, "not = [valid").unwrap();
+        let state = State::load(&path).unwrap();
+        assert!(state.steps.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
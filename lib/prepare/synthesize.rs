@@ -0,0 +1,39 @@
+use super::error::ErrorKind;
+use super::files;
+use crate::synthetic::Synthetic;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+// Add the synthetic variables of a specification (e.g. a yearly income and a survey weight)
+// to every individual
+#[derive(Debug, Deserialize)]
+pub struct Synthesize {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    #[serde(default = "default_config")]
+    pub config: PathBuf,
+}
+
+fn default_config() -> PathBuf {
+    PathBuf::from("./synthetic.toml")
+}
+
+impl Synthesize {
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        vec![self.input.clone(), self.config.clone()]
+    }
+
+    pub fn outputs(&self) -> Vec<PathBuf> {
+        vec![self.output.clone()]
+    }
+
+    pub fn run(&self) -> Result<(), ErrorKind> {
+        let synthetic = Synthetic::load(&self.config.to_string_lossy())?;
+        let df = files::read_csv(&self.input)?.collect()?;
+        let mut df = synthetic.generate(&df)?;
+        if let Some(report) = synthetic.calibrate(&mut df)? {
+            println!("{report}");
+        }
+        files::write_csv(&mut df, &self.output)
+    }
+}
@@ -0,0 +1,430 @@
+use crate::upload::{self, Failure, Upload, failure, s3_error};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use md5::{Digest, Md5};
+use polars::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use tokio::task::JoinSet;
+
+// `delete_objects` takes at most 1,000 keys
+const DELETE_BATCH: usize = 1_000;
+
+// Make an S3 prefix match a local file or directory: only the files that are not in the bucket,
+// or whose size or ETag differ, are uploaded (with `upload`), and the objects that are not
+// local are deleted with `delete`. With `dry_run`, nothing changes: the result lists what would.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SyncOptions {
+    pub delete: bool,  // Delete the objects under the prefix that are not local
+    pub dry_run: bool, // Only compare
+    pub upload: Upload,
+}
+
+// What `sync` did, or would do in a dry run
+#[derive(Debug, Clone, Default)]
+pub struct Synced {
+    pub new: Vec<String>,     // Uploaded, not in the bucket before
+    pub changed: Vec<String>, // Uploaded, with another size or ETag in the bucket
+    pub unchanged: usize,
+    pub extra: Vec<String>, // In the bucket, not local: deleted with `delete`
+    pub deleted: usize,
+    pub dry_run: bool,
+}
+
+impl fmt::Display for Synced {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} new, {} changed, {} unchanged, {} extra ({} deleted)",
+            self.new.len(),
+            self.changed.len(),
+            self.unchanged,
+            self.extra.len(),
+            self.deleted
+        )?;
+        if self.dry_run {
+            write!(f, ", dry run")?;
+        }
+        Ok(())
+    }
+}
+
+// An object of a bucket, as listed
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub size: i64,
+    pub etag: String, // Without the quotes
+}
+
+// Make `key` of `bucket` match `local`: a file is compared with the object `key`, a directory
+// with the objects under `key/` (the whole bucket if `key` is empty), its files keeping their
+// path relative to it (e.g. `partitioned/region=1/00000000.parquet`)
+pub async fn sync(
+    client: &Client,
+    local: &Path,
+    bucket: &str,
+    key: &str,
+    options: &SyncOptions,
+) -> PolarsResult<Synced> {
+    let key = key.trim_matches('/');
+    let (files, mut remote) = if local.is_dir() {
+        let prefix = match key {
+            "" => String::new(),
+            key => format!("{key}/"),
+        };
+        let mut files = BTreeMap::new();
+        walk(local, &prefix, &mut files)?;
+        (files, list(client, bucket, &prefix, &options.upload).await?)
+    } else {
+        let mut remote = list(client, bucket, key, &options.upload).await?;
+        remote.retain(|k, _| k == key); // Not `key.bak`
+        (
+            BTreeMap::from([(key.to_owned(), local.to_path_buf())]),
+            remote,
+        )
+    };
+
+    let mut synced = Synced {
+        dry_run: options.dry_run,
+        ..Synced::default()
+    };
+    for (key, file) in &files {
+        match remote.remove(key) {
+            None => synced.new.push(key.clone()),
+            Some(object) => {
+                if same(file, &object, options.upload.part_size).await? {
+                    synced.unchanged += 1;
+                } else {
+                    synced.changed.push(key.clone());
+                }
+            }
+        }
+    }
+    synced.extra = remote.into_keys().collect();
+
+    if options.dry_run {
+        return Ok(synced);
+    }
+
+    // `concurrency` files at a time, which matters with many small files (each of them is one
+    // part, sent after the previous one)
+    let mut uploads = synced.new.iter().chain(&synced.changed);
+    let mut tasks = JoinSet::new();
+    loop {
+        while tasks.len() < options.upload.concurrency {
+            let Some(key) = uploads.next() else {
+                break;
+            };
+            let (upload, client) = (options.upload.clone(), client.clone());
+            let (file, bucket, key) = (files[key].clone(), bucket.to_owned(), key.clone());
+            tasks.spawn(async move { upload.file(&client, &file, &bucket, &key).await });
+        }
+        let result = match tasks.join_next().await {
+            Some(Ok(result)) => result,
+            Some(Err(e)) => Err(PolarsError::ComputeError(
+                format!("upload failed: {e}").into(),
+            )),
+            None => break,
+        };
+        if let Err(e) = result {
+            tasks.shutdown().await;
+            return Err(e);
+        }
+    }
+    if options.delete {
+        synced.deleted = delete(client, bucket, &synced.extra, &options.upload).await?;
+    }
+    Ok(synced)
+}
+
+// Every object whose key starts with `prefix`, by key, following the continuation tokens (S3
+// returns 1,000 keys at a time)
+pub async fn list(
+    client: &Client,
+    bucket: &str,
+    prefix: &str,
+    retries: &Upload,
+) -> PolarsResult<BTreeMap<String, Object>> {
+    let mut objects = BTreeMap::new();
+    let mut token: Option<String> = None;
+    loop {
+        let page = retries
+            .retry(|| async {
+                client
+                    .list_objects_v2()
+                    .bucket(bucket)
+                    .prefix(prefix)
+                    .set_continuation_token(token.clone())
+                    .send()
+                    .await
+                    .map_err(failure)
+            })
+            .await
+            .map_err(|e| s3_error(e.context(bucket)))?;
+        for object in page.contents() {
+            if let Some(key) = object.key() {
+                let etag = upload::unquote(object.e_tag().unwrap_or_default());
+                let size = object.size().unwrap_or_default();
+                objects.insert(key.to_owned(), Object { size, etag });
+            }
+        }
+        match page.next_continuation_token() {
+            Some(next) if page.is_truncated() == Some(true) => token = Some(next.to_owned()),
+            _ => return Ok(objects),
+        }
+    }
+}
+
+// Delete `keys`, 1,000 at a time. Returns the number deleted; the first key that could not be
+// deleted is an error.
+pub async fn delete(
+    client: &Client,
+    bucket: &str,
+    keys: &[String],
+    retries: &Upload,
+) -> PolarsResult<usize> {
+    let mut deleted = 0;
+    for batch in keys.chunks(DELETE_BATCH) {
+        let objects = batch
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(key).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| PolarsError::ComputeError(format!("S3: {e}").into()))?;
+        let request = Delete::builder()
+            .set_objects(Some(objects))
+            .quiet(true) // Only the errors
+            .build()
+            .map_err(|e| PolarsError::ComputeError(format!("S3: {e}").into()))?;
+        let output = retries
+            .retry(|| async {
+                client
+                    .delete_objects()
+                    .bucket(bucket)
+                    .delete(request.clone())
+                    .send()
+                    .await
+                    .map_err(failure)
+            })
+            .await
+            .map_err(|e| s3_error(e.context(bucket)))?;
+        if let Some(error) = output.errors().first() {
+            let failure = Failure::Fatal(format!(
+                "{}: {}",
+                error.code().unwrap_or("error"),
+                error.message().unwrap_or_default()
+            ));
+            return Err(s3_error(failure.context(error.key().unwrap_or(bucket))));
+        }
+        deleted += batch.len();
+    }
+    Ok(deleted)
+}
+
+// Whether `file` has the size and ETag of `object`. The ETag of an object uploaded at once is
+// the MD5 of the file; for a multipart upload it is the MD5 of the MD5s of the parts, with the
+// number of parts (e.g. `...-3`), which can only be compared with the same part size.
+async fn same(file: &Path, object: &Object, part_size: u64) -> PolarsResult<bool> {
+    let size = tokio::fs::metadata(file)
+        .await
+        .map_err(|e| upload::io_error(file, e))?
+        .len();
+    if size as i64 != object.size {
+        return Ok(false);
+    }
+    let etag = match object.etag.split_once('-') {
+        None => etag(file, size, None).await?,
+        Some((_, parts)) => {
+            if parts.parse::<u64>().ok() != Some(size.div_ceil(part_size).max(1)) {
+                return Ok(false);
+            }
+            etag(file, size, Some(part_size)).await?
+        }
+    };
+    Ok(etag == object.etag)
+}
+
+// The ETag of `file`: its MD5, or the one of a multipart upload with parts of `part_size`
+async fn etag(file: &Path, size: u64, part_size: Option<u64>) -> PolarsResult<String> {
+    let step = part_size.unwrap_or(size).max(1);
+    let mut md5s = vec![];
+    let mut offset = 0;
+    loop {
+        let length = step.min(size - offset);
+        let bytes = upload::read_part(file, offset, length)
+            .await
+            .map_err(|e| upload::io_error(file, e))?;
+        md5s.push(<[u8; 16]>::from(Md5::digest(&bytes)));
+        offset += length;
+        if offset >= size {
+            break;
+        }
+    }
+    match part_size {
+        None => Ok(upload::hex(&md5s[0])),
+        Some(_) => {
+            let mut md5 = Md5::new();
+            for part in &md5s {
+                md5.update(part);
+            }
+            Ok(format!("{}-{}", upload::hex(&md5.finalize()), md5s.len()))
+        }
+    }
+}
+
+// The files of `dir`, by key (`prefix` and the path relative to `dir`, with `/`)
+fn walk(
+    dir: &Path,
+    prefix: &str,
+    files: &mut BTreeMap<String, std::path::PathBuf>,
+) -> PolarsResult<()> {
+    let entries = std::fs::read_dir(dir).map_err(|e| upload::io_error(dir, e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| upload::io_error(dir, e))?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let key = format!("{prefix}{name}");
+        if path.is_dir() {
+            walk(&path, &format!("{key}/"), files)?;
+        } else {
+            files.insert(key, path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upload::tests::{Server, options};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sync_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn md5(bytes: &[u8]) -> String {
+        upload::hex(&Md5::digest(bytes))
+    }
+
+    // One file at a time: the server holds one upload
+    fn sync_options(delete: bool, dry_run: bool) -> SyncOptions {
+        SyncOptions {
+            delete,
+            dry_run,
+            upload: Upload {
+                concurrency: 1,
+                ..options()
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_every_page() {
+        let server = Server::default();
+        {
+            let mut state = server.0.lock().unwrap();
+            state.page = 2;
+            for key in ["dir/a", "dir/b", "dir/c/d", "dir/e", "dir/f", "directory/g"] {
+                state
+                    .objects
+                    .insert(key.to_owned(), (1, md5(key.as_bytes())));
+            }
+        }
+        let objects = list(&server.client(), "bucket", "dir/", &options())
+            .await
+            .unwrap();
+
+        let keys: Vec<_> = objects.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["dir/a", "dir/b", "dir/c/d", "dir/e", "dir/f"]);
+        assert_eq!(objects["dir/a"].etag, md5(b"dir/a"));
+        assert_eq!(server.requests("list objects"), 3);
+    }
+
+    #[tokio::test]
+    async fn uploads_and_deletes_only_the_differences() {
+        let dir = temp_dir("differences");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in [
+            "same",
+            "multipart",
+            "other_etag",
+            "other_size",
+            "new",
+            "sub/same",
+        ] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        let server = Server::default();
+        {
+            let mut state = server.0.lock().unwrap();
+            let multipart = md5(&Md5::digest(b"multipart"));
+            let objects = [
+                ("dir/same", 4, md5(b"same")),
+                ("dir/multipart", 9, format!("{multipart}-1")),
+                ("dir/other_etag", 10, md5(b"other_eta_")),
+                ("dir/other_size", 3, md5(b"other_size")),
+                ("dir/sub/same", 8, md5(b"sub/same")),
+                ("dir/sub/extra", 1, md5(b"x")),
+                ("dir.bak", 1, md5(b"x")), // Not under the prefix
+            ];
+            for (key, size, etag) in objects {
+                state.objects.insert(key.to_owned(), (size, etag));
+            }
+        }
+
+        // Nothing changes in a dry run
+        let client = server.client();
+        let synced = sync(&client, &dir, "bucket", "dir", &sync_options(true, true))
+            .await
+            .unwrap();
+        assert_eq!(synced.new, ["dir/new"]);
+        assert_eq!(synced.changed, ["dir/other_etag", "dir/other_size"]);
+        assert_eq!((synced.unchanged, synced.deleted), (3, 0));
+        assert_eq!(synced.extra, ["dir/sub/extra"]);
+        assert_eq!(server.requests("create"), 0);
+        assert_eq!(server.requests("delete objects"), 0);
+
+        let synced = sync(&client, &dir, "bucket", "dir", &sync_options(true, false))
+            .await
+            .unwrap();
+        assert_eq!(
+            synced.to_string(),
+            "1 new, 2 changed, 3 unchanged, 1 extra (1 deleted)"
+        );
+        let state = server.0.lock().unwrap();
+        assert_eq!(
+            state.completed,
+            ["dir/new", "dir/other_etag", "dir/other_size"]
+        );
+        assert_eq!(state.deleted, [1]);
+        assert!(!state.objects.contains_key("dir/sub/extra"));
+        assert!(state.objects.contains_key("dir.bak"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn deletes_1000_keys_at_a_time() {
+        let server = Server::default();
+        let keys: Vec<_> = (0..2_500).map(|i| format!("key{i:04}")).collect();
+        {
+            let mut state = server.0.lock().unwrap();
+            for key in &keys {
+                state.objects.insert(key.clone(), (1, md5(b"x")));
+            }
+        }
+        let deleted = delete(&server.client(), "bucket", &keys, &options())
+            .await
+            .unwrap();
+
+        assert_eq!(deleted, 2_500);
+        let state = server.0.lock().unwrap();
+        assert_eq!(state.deleted, [1_000, 1_000, 500]);
+        assert!(state.objects.is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+use crate::calibration::{Calibration, Report};
+use polars::prelude::*;
+use rand::SeedableRng;
+use rand::distr::weighted::WeightedIndex;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution as _, Gamma, LogNormal, Normal, Uniform};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+// Variables that the census does not have (e.g. a yearly income or a survey weight), drawn at
+// random for every individual, from a specification like `./synthetic.toml`
+#[derive(Debug, Deserialize)]
+pub struct Synthetic {
+    #[serde(default = "default_seed")]
+    pub seed: u64,
+    #[serde(rename = "variable")]
+    pub variables: Vec<Variable>,
+    pub calibration: Option<Calibration>, // Of the weight, once every variable is drawn
+}
+
+// A variable and how to draw it. The value is drawn from the distribution of the first case
+// whose conditions hold (or the default one), multiplied by the factors of the individual,
+// kept between `min` and `max` and then rounded if `integer`.
+#[derive(Debug, Deserialize)]
+pub struct Variable {
+    pub name: String,
+    pub distribution: Distribution,
+    #[serde(default, rename = "case")]
+    pub cases: Vec<Case>,
+    // Column, then code, then factor (e.g. `factors.sex = { 1 = 0.85, 2 = 1.15 }`)
+    #[serde(default)]
+    pub factors: BTreeMap<String, BTreeMap<String, f64>>,
+    // Null when every column has one of the codes (e.g. `null_if = { econ = [-8, 5] }`)
+    #[serde(default)]
+    pub null_if: BTreeMap<String, Vec<Code>>,
+    #[serde(default)]
+    pub integer: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+// Another distribution, for the individuals that have one of the codes in every column
+#[derive(Debug, Deserialize)]
+pub struct Case {
+    pub when: BTreeMap<String, Vec<Code>>,
+    pub distribution: Distribution,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Distribution {
+    Uniform { low: f64, high: f64 }, // From `low` (included) to `high` (excluded)
+    Normal { mean: f64, sd: f64 },
+    LogNormal { mu: f64, sigma: f64 }, // Of the logarithm: the median is `exp(mu)`
+    Gamma { shape: f64, scale: f64 },  // The mean is `shape * scale`
+    Categorical { values: Vec<f64>, weights: Vec<f64> },
+}
+
+// A code of a census column, written as a number or as text
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Code {
+    Int(i64),
+    Text(String),
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Code::Int(code) => write!(f, "{code}"),
+            Code::Text(code) => write!(f, "{code}"),
+        }
+    }
+}
+
+fn default_seed() -> u64 {
+    1
+}
+
+fn invalid(variable: &str, reason: impl fmt::Display) -> PolarsError {
+    PolarsError::ComputeError(format!("synthetic variable {variable}: {reason}").into())
+}
+
+impl Synthetic {
+    pub fn load(path: &str) -> PolarsResult<Synthetic> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| PolarsError::ComputeError(format!("{path}: {e}").into()))
+    }
+
+    // Add (or replace) every variable, in order, so that a variable can depend on the ones
+    // before it. Each variable has its own stream of random numbers: the same seed always
+    // gives the same values, even if other variables are added or changed.
+    pub fn generate(&self, df: &DataFrame) -> PolarsResult<DataFrame> {
+        let mut df = df.clone();
+        for (stream, variable) in self.variables.iter().enumerate() {
+            let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+            rng.set_stream(stream as u64);
+            let column = variable.generate(&df, &mut rng)?;
+            df.with_column(column)?;
+        }
+        Ok(df)
+    }
+
+    // Calibrate the weight to the population totals, if the specification has a calibration
+    pub fn calibrate(&self, df: &mut DataFrame) -> PolarsResult<Option<Report>> {
+        self.calibration.as_ref().map(|c| c.apply(df)).transpose()
+    }
+}
+
+impl Variable {
+    fn generate(&self, df: &DataFrame, rng: &mut ChaCha8Rng) -> PolarsResult<Column> {
+        let default = Sampler::new(&self.distribution).map_err(|e| invalid(&self.name, e))?;
+        let mut cases = vec![];
+        for case in &self.cases {
+            let sampler = Sampler::new(&case.distribution).map_err(|e| invalid(&self.name, e))?;
+            cases.push((Conditions::new(df, &case.when)?, sampler));
+        }
+        let nulls = Conditions::new(df, &self.null_if)?;
+        let mut factors = vec![];
+        for (name, by_code) in &self.factors {
+            factors.push((codes(df, name)?, by_code));
+        }
+
+        // Every individual gets a draw, even a null one, so that the values of the others do
+        // not depend on `null_if`
+        let values: Vec<Option<f64>> = (0..df.height())
+            .map(|row| {
+                let sampler = cases
+                    .iter()
+                    .find(|(conditions, _)| conditions.hold(row))
+                    .map_or(&default, |(_, sampler)| sampler);
+                let mut value = sampler.sample(rng);
+                for (codes, by_code) in &factors {
+                    if let Some(factor) = codes.get(row).and_then(|c| by_code.get(c)) {
+                        value *= factor;
+                    }
+                }
+                if let Some(min) = self.min {
+                    value = value.max(min);
+                }
+                if let Some(max) = self.max {
+                    value = value.min(max);
+                }
+                (self.null_if.is_empty() || !nulls.hold(row)).then_some(value)
+            })
+            .collect();
+
+        let name = self.name.as_str().into();
+        if self.integer {
+            let values: Vec<Option<i64>> = values
+                .into_iter()
+                .map(|v| v.map(|v| v.round() as i64))
+                .collect();
+            Ok(Column::new(name, values))
+        } else {
+            Ok(Column::new(name, values))
+        }
+    }
+}
+
+// The codes of a column, as text, so that they compare with codes written either way
+fn codes(df: &DataFrame, name: &str) -> PolarsResult<StringChunked> {
+    Ok(df.column(name)?.cast(&DataType::String)?.str()?.clone())
+}
+
+struct Conditions(Vec<(StringChunked, HashSet<String>)>);
+
+impl Conditions {
+    fn new(df: &DataFrame, when: &BTreeMap<String, Vec<Code>>) -> PolarsResult<Conditions> {
+        let mut conditions = vec![];
+        for (name, values) in when {
+            let values = values.iter().map(|v| v.to_string()).collect();
+            conditions.push((codes(df, name)?, values));
+        }
+        Ok(Conditions(conditions))
+    }
+
+    fn hold(&self, row: usize) -> bool {
+        self.0
+            .iter()
+            .all(|(codes, values)| codes.get(row).is_some_and(|c| values.contains(c)))
+    }
+}
+
+enum Sampler {
+    Uniform(Uniform<f64>),
+    Normal(Normal<f64>),
+    LogNormal(LogNormal<f64>),
+    Gamma(Gamma<f64>),
+    Categorical(WeightedIndex<f64>, Vec<f64>),
+}
+
+impl Sampler {
+    fn new(distribution: &Distribution) -> Result<Sampler, String> {
+        Ok(match *distribution {
+            Distribution::Uniform { low, high } => {
+                Sampler::Uniform(Uniform::new(low, high).map_err(|e| e.to_string())?)
+            }
+            Distribution::Normal { mean, sd } => {
+                Sampler::Normal(Normal::new(mean, sd).map_err(|e| e.to_string())?)
+            }
+            Distribution::LogNormal { mu, sigma } => {
+                Sampler::LogNormal(LogNormal::new(mu, sigma).map_err(|e| e.to_string())?)
+            }
+            Distribution::Gamma { shape, scale } => {
+                Sampler::Gamma(Gamma::new(shape, scale).map_err(|e| e.to_string())?)
+            }
+            Distribution::Categorical {
+                ref values,
+                ref weights,
+            } => {
+                if values.len() != weights.len() {
+                    return Err("a categorical needs as many weights as values".to_owned());
+                }
+                let index = WeightedIndex::new(weights).map_err(|e| e.to_string())?;
+                Sampler::Categorical(index, values.clone())
+            }
+        })
+    }
+
+    fn sample(&self, rng: &mut ChaCha8Rng) -> f64 {
+        match self {
+            Sampler::Uniform(d) => d.sample(rng),
+            Sampler::Normal(d) => d.sample(rng),
+            Sampler::LogNormal(d) => d.sample(rng),
+            Sampler::Gamma(d) => d.sample(rng),
+            Sampler::Categorical(d, values) => values[d.sample(rng)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Four individuals: every pair of `sex` and `region`, and `econ` -8 for those of W1
+    fn people() -> DataFrame {
+        df!(
+            "id" => [1, 2, 3, 4],
+            "sex" => [1, 1, 2, 2],
+            "region" => ["E1", "W1", "E1", "W1"],
+            "econ" => [1, -8, 1, -8],
+        )
+        .unwrap()
+    }
+
+    fn spec(toml: &str) -> Synthetic {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn values(df: &DataFrame, name: &str) -> Vec<Option<f64>> {
+        let column = df.column(name).unwrap().cast(&DataType::Float64).unwrap();
+        column.f64().unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn draws_the_same_values_with_the_same_seed() {
+        let first = r#"
+            [[variable]]
+            name = "x"
+            distribution = { kind = "normal", mean = 0, sd = 1 }
+            [[variable]]
+            name = "y"
+            distribution = { kind = "uniform", low = 0, high = 1 }
+        "#;
+        let df = spec(first).generate(&people()).unwrap();
+        assert!(df.equals_missing(&spec(first).generate(&people()).unwrap()));
+
+        // `y` changed and `z` added: `x` is drawn from the same stream
+        let second = r#"
+            [[variable]]
+            name = "x"
+            distribution = { kind = "normal", mean = 0, sd = 1 }
+            [[variable]]
+            name = "y"
+            distribution = { kind = "gamma", shape = 2, scale = 1 }
+            [[variable]]
+            name = "z"
+            distribution = { kind = "log_normal", mu = 0, sigma = 1 }
+        "#;
+        let other = spec(second).generate(&people()).unwrap();
+        assert_eq!(values(&other, "x"), values(&df, "x"));
+        assert_ne!(values(&other, "y"), values(&df, "y"));
+
+        let seeded = spec(&format!("seed = 2\n{first}"));
+        let seeded = seeded.generate(&people()).unwrap();
+        assert_ne!(values(&seeded, "x"), values(&df, "x"));
+    }
+
+    #[test]
+    fn draws_from_the_first_case_that_holds() {
+        let synthetic = spec(
+            r#"
+            [[variable]]
+            name = "x"
+            distribution = { kind = "categorical", values = [0], weights = [1] }
+            [[variable.case]]
+            when = { sex = [1] }
+            distribution = { kind = "categorical", values = [10], weights = [1] }
+            [[variable.case]]
+            when = { sex = [1, 2], region = ["E1"] }
+            distribution = { kind = "categorical", values = [20], weights = [1] }
+        "#,
+        );
+        let df = synthetic.generate(&people()).unwrap();
+        assert_eq!(
+            values(&df, "x"),
+            [Some(10.0), Some(10.0), Some(20.0), Some(0.0)]
+        );
+    }
+
+    #[test]
+    fn applies_factors_limits_rounding_and_nulls() {
+        let synthetic = spec(
+            r#"
+            [[variable]]
+            name = "x"
+            distribution = { kind = "categorical", values = [10], weights = [1] }
+            factors.sex = { 1 = 0.85, 2 = 1.16 }
+            factors.region = { E1 = 2 }
+            min = 9
+            max = 20
+            integer = true
+            [[variable]]
+            name = "y"
+            distribution = { kind = "categorical", values = [1.5], weights = [1] }
+            null_if = { econ = [-8], sex = [2] }
+        "#,
+        );
+        let df = synthetic.generate(&people()).unwrap();
+
+        // 17, 8.5 raised to 9, 23.2 lowered to 20 and 11.6 rounded to 12
+        assert_eq!(df.column("x").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(
+            values(&df, "x"),
+            [Some(17.0), Some(9.0), Some(20.0), Some(12.0)]
+        );
+        // Null only when both codes match
+        assert_eq!(values(&df, "y"), [Some(1.5), Some(1.5), Some(1.5), None]);
+    }
+
+    #[test]
+    fn rejects_a_categorical_without_a_weight_for_each_value() {
+        let synthetic = spec(
+            r#"
+            [[variable]]
+            name = "x"
+            distribution = { kind = "categorical", values = [1, 2], weights = [1] }
+        "#,
+        );
+        let error = synthetic.generate(&people()).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("synthetic variable x: a categorical needs as many weights as values"),
+            "{error}"
+        );
+    }
+}
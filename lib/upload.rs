@@ -0,0 +1,949 @@
+use aws_sdk_s3::Client;
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use base64::Engine as _;
+use md5::{Digest, Md5};
+use polars::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::task::JoinSet;
+
+// S3 limits: the parts but the last one are at least 5 MB, and there are at most 10,000
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+const MAX_PARTS: u64 = 10_000;
+
+// Upload files to S3 (or MinIO) in parts, several at a time. A part that fails on a network or
+// server error is sent again after a growing wait; any other error aborts the upload. Each part
+// is checked against its MD5 (`Content-MD5`, and the ETag S3 returns), and so is the whole file
+// (the ETag of a multipart upload is the MD5 of the MD5s of its parts): an object that does not
+// match is deleted. An unfinished upload of the same key is resumed: its parts that match the
+// file are kept.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Upload {
+    pub part_size: u64,     // Bytes, 5 MB to 5 GB
+    pub concurrency: usize, // Parts sent at the same time
+    pub retries: u32,       // For each request, after the first attempt
+    pub backoff_ms: u64,    // Wait before the first retry, doubled after each one
+    pub resume: bool,       // Continue an unfinished upload of the key
+}
+
+impl Default for Upload {
+    fn default() -> Upload {
+        Upload {
+            part_size: 10 * 1024 * 1024,
+            concurrency: 4,
+            retries: 5,
+            backoff_ms: 200,
+            resume: true,
+        }
+    }
+}
+
+// What `Upload::file` did
+#[derive(Debug, Clone)]
+pub struct Uploaded {
+    pub key: String,
+    pub bytes: u64,
+    pub parts: usize,
+    pub resumed: usize, // Parts already uploaded by an unfinished upload
+    pub etag: String,
+}
+
+impl fmt::Display for Uploaded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} bytes in {} parts ({} resumed)",
+            self.key, self.bytes, self.parts, self.resumed
+        )
+    }
+}
+
+// A client for an S3 endpoint with static credentials (e.g. a local MinIO server)
+pub fn client(endpoint: &str, region: &str, username: &str, password: &str) -> Client {
+    let credentials =
+        aws_sdk_s3::config::Credentials::new(username, password, None, None, "loaded-from-code");
+    let config = aws_sdk_s3::config::Builder::new()
+        .endpoint_url(endpoint)
+        .credentials_provider(credentials)
+        .region(aws_sdk_s3::config::Region::new(region.to_owned()))
+        .build();
+    Client::from_conf(config)
+}
+
+// Why a request failed: worth another attempt (network, timeout, server error, corrupted
+// part), or not (access denied, no such bucket, ...)
+#[derive(Debug)]
+pub(crate) enum Failure {
+    Retry(String),
+    Fatal(String),
+}
+
+impl Failure {
+    pub(crate) fn message(&self) -> &str {
+        match self {
+            Failure::Retry(message) | Failure::Fatal(message) => message,
+        }
+    }
+
+    // The same failure, with what failed in front of the message
+    pub(crate) fn context(self, what: &str) -> Failure {
+        match self {
+            Failure::Retry(message) => Failure::Retry(format!("{what}: {message}")),
+            Failure::Fatal(message) => Failure::Fatal(format!("{what}: {message}")),
+        }
+    }
+}
+
+pub(crate) fn failure<E>(e: SdkError<E, HttpResponse>) -> Failure
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    let (retry, message) = match &e {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            (true, DisplayErrorContext(&e).to_string())
+        }
+        SdkError::ServiceError(service) => {
+            let status = service.raw().status().as_u16();
+            let code = service.err().code();
+            let retry = status >= 500
+                || status == 408
+                || status == 429
+                || matches!(
+                    code,
+                    Some("RequestTimeout" | "SlowDown" | "BadDigest" | "InternalError")
+                );
+            let message = format!(
+                "{} ({status}): {}",
+                code.unwrap_or("error"),
+                service.err().message().unwrap_or_default()
+            );
+            (retry, message)
+        }
+        _ => (false, DisplayErrorContext(&e).to_string()),
+    };
+    if retry {
+        Failure::Retry(message)
+    } else {
+        Failure::Fatal(message)
+    }
+}
+
+// The file and the upload its parts belong to, shared by the tasks that send them
+struct Target {
+    path: PathBuf,
+    bucket: String,
+    key: String,
+    upload_id: String,
+}
+
+impl Upload {
+    // Upload `path` as `key` of `bucket`
+    pub async fn file(
+        &self,
+        client: &Client,
+        path: &Path,
+        bucket: &str,
+        key: &str,
+    ) -> PolarsResult<Uploaded> {
+        self.check()?;
+        let size = tokio::fs::metadata(path)
+            .await
+            .map_err(|e| io_error(path, e))?
+            .len();
+        let count = size.div_ceil(self.part_size).max(1);
+        if count > MAX_PARTS {
+            return Err(PolarsError::InvalidOperation(
+                format!(
+                    "{}: {count} parts of {} bytes, S3 allows {MAX_PARTS}",
+                    path.display(),
+                    self.part_size
+                )
+                .into(),
+            ));
+        }
+
+        // An unfinished upload of the key, with its parts, or a new one
+        let unfinished = match self.resume {
+            true => self
+                .unfinished(client, bucket, key)
+                .await
+                .map_err(|e| s3_error(e.context(key)))?,
+            false => None,
+        };
+        let (upload_id, existing) = match unfinished {
+            Some(upload_id) => {
+                let parts = self
+                    .list_parts(client, bucket, key, &upload_id)
+                    .await
+                    .map_err(|e| s3_error(e.context(key)))?;
+                (upload_id, parts)
+            }
+            None => {
+                let created = self
+                    .retry(|| async {
+                        client
+                            .create_multipart_upload()
+                            .bucket(bucket)
+                            .key(key)
+                            .send()
+                            .await
+                            .map_err(failure)
+                    })
+                    .await
+                    .map_err(|e| s3_error(e.context(key)))?;
+                let upload_id = created.upload_id().unwrap_or_default().to_owned();
+                (upload_id, BTreeMap::new())
+            }
+        };
+        let target = Arc::new(Target {
+            path: path.to_path_buf(),
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            upload_id,
+        });
+
+        let result = match self.parts(client, &target, size, count, existing).await {
+            Ok(parts) => self.complete(client, &target, parts).await,
+            Err(error) => Err(error),
+        };
+        let (etag, expected, parts, resumed) = match result {
+            Ok(completed) => completed,
+            Err(error) => return Err(self.abandon(client, &target, error.context(key)).await),
+        };
+
+        // The upload is complete, there is nothing left to abort: the object does not match the
+        // file, and is deleted
+        if etag != expected {
+            let deleted = self
+                .retry(|| async {
+                    client
+                        .delete_object()
+                        .bucket(bucket)
+                        .key(key)
+                        .send()
+                        .await
+                        .map_err(failure)
+                })
+                .await;
+            let outcome = match deleted {
+                Ok(_) => "object deleted".to_owned(),
+                Err(e) => format!("could not delete the object: {}", e.message()),
+            };
+            return Err(PolarsError::ComputeError(
+                format!("S3: {key}: ETag {etag}, expected {expected} ({outcome})").into(),
+            ));
+        }
+        Ok(Uploaded {
+            key: key.to_owned(),
+            bytes: size,
+            parts,
+            resumed,
+            etag,
+        })
+    }
+
+    // The error of an upload that failed before it was complete. Its parts are kept to resume
+    // later, unless the error would happen again: then the upload is aborted.
+    async fn abandon(&self, client: &Client, target: &Target, error: Failure) -> PolarsError {
+        if let Failure::Retry(message) = &error
+            && self.resume
+        {
+            return PolarsError::ComputeError(
+                format!("S3: {message} (upload again to resume)").into(),
+            );
+        }
+        let aborted = self
+            .retry(|| async {
+                client
+                    .abort_multipart_upload()
+                    .bucket(&target.bucket)
+                    .key(&target.key)
+                    .upload_id(&target.upload_id)
+                    .send()
+                    .await
+                    .map_err(failure)
+            })
+            .await;
+        let message = match aborted {
+            Ok(_) => format!("{} (upload aborted)", error.message()),
+            Err(e) => format!("{} (could not abort: {})", error.message(), e.message()),
+        };
+        PolarsError::ComputeError(format!("S3: {message}").into())
+    }
+
+    fn check(&self) -> PolarsResult<()> {
+        if !(MIN_PART_SIZE..=MAX_PART_SIZE).contains(&self.part_size) || self.concurrency == 0 {
+            return Err(PolarsError::InvalidOperation(
+                "part_size must be 5 MB to 5 GB, and concurrency more than 0".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Send `request` until it succeeds, it fails with an error that is not worth retrying or
+    // there are no retries left, waiting `backoff_ms`, twice that, ... between attempts
+    pub(crate) async fn retry<T, F, R>(&self, mut request: F) -> Result<T, Failure>
+    where
+        F: FnMut() -> R,
+        R: Future<Output = Result<T, Failure>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match request().await {
+                Err(Failure::Retry(_)) if attempt < self.retries => {
+                    let wait = self.backoff_ms.saturating_mul(1 << attempt.min(16));
+                    tokio::time::sleep(Duration::from_millis(wait)).await;
+                    attempt += 1;
+                }
+                Err(Failure::Retry(message)) if attempt > 0 => {
+                    return Err(Failure::Retry(format!(
+                        "{message} (after {attempt} retries)"
+                    )));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // The most recent unfinished upload of `key`
+    async fn unfinished(
+        &self,
+        client: &Client,
+        bucket: &str,
+        key: &str,
+    ) -> Result<Option<String>, Failure> {
+        let mut latest = None;
+        let mut markers: (Option<String>, Option<String>) = (None, None);
+        loop {
+            let page = self
+                .retry(|| async {
+                    client
+                        .list_multipart_uploads()
+                        .bucket(bucket)
+                        .prefix(key)
+                        .set_key_marker(markers.0.clone())
+                        .set_upload_id_marker(markers.1.clone())
+                        .send()
+                        .await
+                        .map_err(failure)
+                })
+                .await?;
+            // In the order they were started
+            for upload in page.uploads() {
+                if upload.key() == Some(key) {
+                    latest = upload.upload_id().map(str::to_owned);
+                }
+            }
+            if page.is_truncated() != Some(true) {
+                return Ok(latest);
+            }
+            markers = (
+                page.next_key_marker().map(str::to_owned),
+                page.next_upload_id_marker().map(str::to_owned),
+            );
+        }
+    }
+
+    // The parts of an upload (their size and ETag, by number), one page at a time
+    async fn list_parts(
+        &self,
+        client: &Client,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<BTreeMap<i32, (i64, String)>, Failure> {
+        let mut parts = BTreeMap::new();
+        let mut marker: Option<String> = None;
+        loop {
+            let page = self
+                .retry(|| async {
+                    client
+                        .list_parts()
+                        .bucket(bucket)
+                        .key(key)
+                        .upload_id(upload_id)
+                        .set_part_number_marker(marker.clone())
+                        .send()
+                        .await
+                        .map_err(failure)
+                })
+                .await?;
+            for part in page.parts() {
+                if let (Some(number), Some(size), Some(etag)) =
+                    (part.part_number(), part.size(), part.e_tag())
+                {
+                    parts.insert(number, (size, unquote(etag)));
+                }
+            }
+            if page.is_truncated() != Some(true) {
+                return Ok(parts);
+            }
+            marker = page.next_part_number_marker().map(str::to_owned);
+        }
+    }
+
+    // Send the parts, `concurrency` at a time, skipping those of `existing` that match the
+    // file. Stops at the first part that fails (after its retries). Returns the parts, with
+    // their MD5, and whether each was resumed.
+    async fn parts(
+        &self,
+        client: &Client,
+        target: &Arc<Target>,
+        size: u64,
+        count: u64,
+        mut existing: BTreeMap<i32, (i64, String)>,
+    ) -> Result<Vec<(CompletedPart, [u8; 16], bool)>, Failure> {
+        let mut tasks = JoinSet::new();
+        let mut parts = vec![];
+        let mut next = 0;
+        while next < count || !tasks.is_empty() {
+            while next < count && tasks.len() < self.concurrency {
+                let offset = next * self.part_size;
+                let length = self.part_size.min(size - offset);
+                let number = next as i32 + 1; // Part numbers start at 1
+                let previous = existing.remove(&number);
+                let (options, client, target) = (self.clone(), client.clone(), target.clone());
+                tasks.spawn(async move {
+                    options
+                        .part(&client, &target, number, offset, length, previous)
+                        .await
+                });
+                next += 1;
+            }
+            let result = match tasks.join_next().await {
+                Some(Ok(result)) => result,
+                Some(Err(e)) => Err(Failure::Fatal(format!("part task failed: {e}"))),
+                None => continue,
+            };
+            match result {
+                Ok(part) => parts.push(part),
+                Err(error) => {
+                    // No part may still be sent once the upload is aborted
+                    tasks.shutdown().await;
+                    return Err(error);
+                }
+            }
+        }
+        parts.sort_by_key(|(part, _, _)| part.part_number());
+        Ok(parts)
+    }
+
+    // Read a part from the file and send it, unless `previous` (the same part of an unfinished
+    // upload) has the same size and MD5
+    async fn part(
+        &self,
+        client: &Client,
+        target: &Target,
+        number: i32,
+        offset: u64,
+        length: u64,
+        previous: Option<(i64, String)>,
+    ) -> Result<(CompletedPart, [u8; 16], bool), Failure> {
+        let bytes = read_part(&target.path, offset, length)
+            .await
+            .map_err(|e| Failure::Fatal(format!("{}: {e}", target.path.display())))?;
+        let md5: [u8; 16] = Md5::digest(&bytes).into();
+        let md5_hex = hex(&md5);
+        let completed = |etag: &str| {
+            CompletedPart::builder()
+                .e_tag(format!("\"{etag}\""))
+                .part_number(number)
+                .build()
+        };
+
+        if let Some((size, etag)) = previous
+            && size == length as i64
+            && etag == md5_hex
+        {
+            return Ok((completed(&etag), md5, true));
+        }
+
+        let content_md5 = base64::engine::general_purpose::STANDARD.encode(md5);
+        self.retry(|| async {
+            let sent = client
+                .upload_part()
+                .bucket(&target.bucket)
+                .key(&target.key)
+                .upload_id(&target.upload_id)
+                .part_number(number)
+                .content_md5(&content_md5)
+                .body(ByteStream::from(bytes.clone()))
+                .send()
+                .await
+                .map_err(failure)?;
+            let etag = unquote(sent.e_tag().unwrap_or_default());
+            if etag != md5_hex {
+                return Err(Failure::Retry(format!("ETag {etag}, MD5 {md5_hex}")));
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|e| e.context(&format!("part {number}")))?;
+        Ok((completed(&md5_hex), md5, false))
+    }
+
+    // Assemble the parts. Returns the ETag of the object, the one expected from the MD5s of the
+    // parts, the number of parts and how many were resumed.
+    async fn complete(
+        &self,
+        client: &Client,
+        target: &Target,
+        parts: Vec<(CompletedPart, [u8; 16], bool)>,
+    ) -> Result<(String, String, usize, usize), Failure> {
+        let count = parts.len();
+        let resumed = parts.iter().filter(|(_, _, resumed)| *resumed).count();
+        let mut md5s = Md5::new();
+        for (_, md5, _) in &parts {
+            md5s.update(md5);
+        }
+        let expected = format!("{}-{count}", hex(&md5s.finalize()));
+
+        let completed = CompletedMultipartUpload::builder()
+            .set_parts(Some(parts.into_iter().map(|(part, _, _)| part).collect()))
+            .build();
+        let output = self
+            .retry(|| async {
+                client
+                    .complete_multipart_upload()
+                    .bucket(&target.bucket)
+                    .key(&target.key)
+                    .upload_id(&target.upload_id)
+                    .multipart_upload(completed.clone())
+                    .send()
+                    .await
+                    .map_err(failure)
+            })
+            .await?;
+
+        // Not an MD5 with some server-side encryptions (SSE-KMS), which MinIO does not use
+        let etag = unquote(output.e_tag().unwrap_or_default());
+        Ok((etag, expected, count, resumed))
+    }
+}
+
+pub(crate) async fn read_part(path: &Path, offset: u64, length: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    let mut bytes = vec![0; length as usize];
+    file.read_exact(&mut bytes).await?;
+    Ok(bytes)
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(crate) fn unquote(etag: &str) -> String {
+    etag.trim_matches('"').to_owned()
+}
+
+pub(crate) fn s3_error(failure: Failure) -> PolarsError {
+    PolarsError::ComputeError(format!("S3: {}", failure.message()).into())
+}
+
+pub(crate) fn io_error(path: &Path, e: std::io::Error) -> PolarsError {
+    PolarsError::ComputeError(format!("{}: {e}", path.display()).into())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use aws_sdk_s3::config::retry::RetryConfig;
+    use aws_sdk_s3::config::{
+        Credentials, Region, RequestChecksumCalculation, RuntimeComponents, SharedHttpClient,
+    };
+    use aws_sdk_s3::primitives::SdkBody;
+    use aws_smithy_runtime_api::client::http::{
+        HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector,
+    };
+    use aws_smithy_runtime_api::client::orchestrator::HttpRequest;
+    use aws_smithy_runtime_api::http::StatusCode;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    const MB: usize = 1024 * 1024;
+
+    // An S3 server for one multipart upload at a time, which answers some requests with errors.
+    // It also lists and deletes `objects` (for `sync`).
+    #[derive(Debug, Default)]
+    pub(crate) struct State {
+        // e.g. `part 2`, `complete`, `abort`
+        pub(crate) requests: Vec<String>,
+        // Statuses to answer, by request, before succeeding
+        pub(crate) errors: BTreeMap<String, Vec<u16>>,
+        // The parts of the upload
+        pub(crate) parts: BTreeMap<i32, Vec<u8>>,
+        // An upload of the key was started before
+        pub(crate) unfinished: bool,
+        // The completed object is not the one expected
+        pub(crate) wrong_etag: bool,
+        // Size and ETag, by key
+        pub(crate) objects: BTreeMap<String, (i64, String)>,
+        // Objects listed at a time (all of them with 0)
+        pub(crate) page: usize,
+        // The keys of the completed uploads
+        pub(crate) completed: Vec<String>,
+        // The number of keys of each `delete_objects`
+        pub(crate) deleted: Vec<usize>,
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct Server(pub(crate) Arc<Mutex<State>>);
+
+    impl Server {
+        pub(crate) fn client(&self) -> Client {
+            let config = aws_sdk_s3::config::Builder::new()
+                .behavior_version_latest()
+                .endpoint_url("http://s3.test")
+                .force_path_style(true)
+                .region(Region::new("us-east-1"))
+                .credentials_provider(Credentials::new("user", "password", None, None, "test"))
+                .retry_config(RetryConfig::disabled()) // Only the retries of `Upload`
+                .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+                .http_client(SharedHttpClient::new(self.clone()))
+                .build();
+            Client::from_conf(config)
+        }
+
+        pub(crate) fn requests(&self, name: &str) -> usize {
+            let state = self.0.lock().unwrap();
+            state.requests.iter().filter(|r| *r == name).count()
+        }
+
+        fn answer(&self, request: &HttpRequest) -> (u16, String, Option<String>) {
+            let query = request.uri().split_once('?').map_or("", |(_, query)| query);
+            let parameter = |name: &str| {
+                query.split('&').find_map(|pair| {
+                    let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    (key == name).then(|| value.to_owned())
+                })
+            };
+            let name = match (request.method(), parameter("partNumber")) {
+                ("GET", _) if parameter("list-type").is_some() => "list objects".to_owned(),
+                ("POST", _) if parameter("delete").is_some() => "delete objects".to_owned(),
+                ("GET", _) if parameter("uploads").is_some() => "list uploads".to_owned(),
+                ("POST", _) if parameter("uploads").is_some() => "create".to_owned(),
+                ("GET", _) => "list parts".to_owned(),
+                ("PUT", Some(number)) => format!("part {number}"),
+                ("POST", _) => "complete".to_owned(),
+                ("DELETE", _) if parameter("uploadId").is_some() => "abort".to_owned(),
+                _ => "delete".to_owned(),
+            };
+
+            let mut state = self.0.lock().unwrap();
+            state.requests.push(name.clone());
+            if let Some(status) = state.errors.get_mut(&name).and_then(|e| e.pop()) {
+                let code = if status == 503 {
+                    "SlowDown"
+                } else {
+                    "AccessDenied"
+                };
+                let body = format!("<Error><Code>{code}</Code><Message>test</Message></Error>");
+                return (status, body, None);
+            }
+            match name.as_str() {
+                // `page` objects at a time, the continuation token being the last key listed
+                "list objects" => {
+                    let prefix = decode(&parameter("prefix").unwrap_or_default());
+                    let after = parameter("continuation-token").map(|t| decode(&t));
+                    let mut objects = state
+                        .objects
+                        .iter()
+                        .filter(|(key, _)| key.starts_with(&prefix))
+                        .filter(|(key, _)| after.as_ref().is_none_or(|after| *key > after));
+                    let page = match state.page {
+                        0 => usize::MAX,
+                        page => page,
+                    };
+                    let mut body = String::new();
+                    let mut last = "";
+                    for (key, (size, etag)) in objects.by_ref().take(page) {
+                        body.push_str(&format!(
+                            "<Contents><Key>{key}</Key><ETag>\"{etag}\"</ETag>\
+                             <Size>{size}</Size></Contents>"
+                        ));
+                        last = key;
+                    }
+                    let truncated = objects.next().is_some();
+                    let body = format!(
+                        "<ListBucketResult><IsTruncated>{truncated}</IsTruncated>\
+                         <NextContinuationToken>{last}</NextContinuationToken>{body}\
+                         </ListBucketResult>"
+                    );
+                    (200, body, None)
+                }
+                "delete objects" => {
+                    let body = String::from_utf8(request.body().bytes().unwrap().to_vec()).unwrap();
+                    let keys: Vec<_> = body
+                        .split("<Key>")
+                        .skip(1)
+                        .filter_map(|key| key.split_once("</Key>"))
+                        .map(|(key, _)| key.to_owned())
+                        .collect();
+                    for key in &keys {
+                        state.objects.remove(key);
+                    }
+                    state.deleted.push(keys.len());
+                    (200, "<DeleteResult></DeleteResult>".to_owned(), None)
+                }
+                "list uploads" => {
+                    let upload = match state.unfinished {
+                        true => "<Upload><Key>key</Key><UploadId>1</UploadId></Upload>",
+                        false => "",
+                    };
+                    let body = format!(
+                        "<ListMultipartUploadsResult><IsTruncated>false</IsTruncated>{upload}\
+                         </ListMultipartUploadsResult>"
+                    );
+                    (200, body, None)
+                }
+                "create" => {
+                    state.parts.clear();
+                    let body = "<InitiateMultipartUploadResult><Key>key</Key>\
+                                <UploadId>1</UploadId></InitiateMultipartUploadResult>";
+                    (200, body.to_owned(), None)
+                }
+                // One part at a time, to follow the markers
+                "list parts" => {
+                    let marker = parameter("part-number-marker").map_or(0, |m| m.parse().unwrap());
+                    let mut parts = state.parts.range(marker + 1..);
+                    let body = match parts.next() {
+                        Some((number, bytes)) => format!(
+                            "<ListPartsResult><IsTruncated>{}</IsTruncated>\
+                             <NextPartNumberMarker>{number}</NextPartNumberMarker><Part>\
+                             <PartNumber>{number}</PartNumber><ETag>\"{}\"</ETag>\
+                             <Size>{}</Size></Part></ListPartsResult>",
+                            parts.next().is_some(),
+                            hex(&Md5::digest(bytes)),
+                            bytes.len()
+                        ),
+                        None => "<ListPartsResult><IsTruncated>false</IsTruncated>\
+                                 </ListPartsResult>"
+                            .to_owned(),
+                    };
+                    (200, body, None)
+                }
+                "complete" => {
+                    let mut md5s = Md5::new();
+                    for bytes in state.parts.values() {
+                        md5s.update(Md5::digest(bytes));
+                    }
+                    if state.wrong_etag {
+                        md5s.update(b"wrong");
+                    }
+                    let etag = format!("{}-{}", hex(&md5s.finalize()), state.parts.len());
+                    let path = request.uri().split('?').next().unwrap();
+                    let key = path.splitn(5, '/').nth(4).unwrap_or_default();
+                    state.completed.push(decode(key));
+                    let body = format!(
+                        "<CompleteMultipartUploadResult><Key>key</Key><ETag>\"{etag}\"</ETag>\
+                         </CompleteMultipartUploadResult>"
+                    );
+                    (200, body, None)
+                }
+                "abort" | "delete" => (204, String::new(), None),
+                part => {
+                    let number = part["part ".len()..].parse().unwrap();
+                    let bytes = request.body().bytes().unwrap().to_vec();
+                    let etag = format!("\"{}\"", hex(&Md5::digest(&bytes)));
+                    state.parts.insert(number, bytes);
+                    (200, String::new(), Some(etag))
+                }
+            }
+        }
+    }
+
+    impl HttpConnector for Server {
+        fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+            let (status, body, etag) = self.answer(&request);
+            let mut response =
+                HttpResponse::new(StatusCode::try_from(status).unwrap(), SdkBody::from(body));
+            if let Some(etag) = etag {
+                response.headers_mut().insert("ETag", etag);
+            }
+            HttpConnectorFuture::ready(Ok(response))
+        }
+    }
+
+    impl HttpClient for Server {
+        fn http_connector(
+            &self,
+            _: &HttpConnectorSettings,
+            _: &RuntimeComponents,
+        ) -> SharedHttpConnector {
+            SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    // The few characters of the keys of the tests that are escaped
+    fn decode(value: &str) -> String {
+        value.replace("%2F", "/").replace("%3D", "=")
+    }
+
+    // A file of 12 MB: 3 parts of 5 MB (the last one of 2 MB)
+    fn file(name: &str) -> (PathBuf, Vec<u8>) {
+        let bytes: Vec<u8> = (0..12 * MB).map(|i| (i % 251) as u8).collect();
+        let path = std::env::temp_dir().join(format!("upload_{name}_{}", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        (path, bytes)
+    }
+
+    pub(crate) fn options() -> Upload {
+        Upload {
+            part_size: 5 * MB as u64,
+            concurrency: 2,
+            retries: 2,
+            backoff_ms: 1,
+            resume: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_with_backoff() {
+        let options = Upload {
+            backoff_ms: 20,
+            ..options()
+        };
+        let attempts = Mutex::new(0);
+        let start = Instant::now();
+        let result: Result<(), Failure> = options
+            .retry(|| async {
+                *attempts.lock().unwrap() += 1;
+                Err(Failure::Retry("SlowDown".to_owned()))
+            })
+            .await;
+        assert_eq!(*attempts.lock().unwrap(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(20 + 40));
+        assert_eq!(result.unwrap_err().message(), "SlowDown (after 2 retries)");
+
+        // Not again after an error that would happen again
+        let result: Result<(), Failure> = options
+            .retry(|| async {
+                *attempts.lock().unwrap() += 1;
+                Err(Failure::Fatal("AccessDenied".to_owned()))
+            })
+            .await;
+        assert_eq!(*attempts.lock().unwrap(), 4);
+        assert_eq!(result.unwrap_err().message(), "AccessDenied");
+    }
+
+    #[tokio::test]
+    async fn uploads_after_server_errors() {
+        let server = Server::default();
+        server.0.lock().unwrap().errors = BTreeMap::from([("part 2".to_owned(), vec![503, 503])]);
+        let (path, bytes) = file("retry");
+        let uploaded = options()
+            .file(&server.client(), &path, "bucket", "key")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            (uploaded.bytes, uploaded.parts, uploaded.resumed),
+            (12 * MB as u64, 3, 0)
+        );
+        assert_eq!(server.requests("part 2"), 3);
+        let state = server.0.lock().unwrap();
+        assert_eq!(
+            state.parts.values().flatten().copied().collect::<Vec<_>>(),
+            bytes
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_the_parts_after_the_retries() {
+        let server = Server::default();
+        server.0.lock().unwrap().errors = BTreeMap::from([("part 3".to_owned(), vec![503; 3])]);
+        let (path, _) = file("resume_later");
+        let error = options()
+            .file(&server.client(), &path, "bucket", "key")
+            .await
+            .unwrap_err();
+
+        assert!(
+            error.to_string().ends_with("(upload again to resume)"),
+            "{error}"
+        );
+        assert_eq!(server.requests("abort"), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn aborts_on_a_fatal_error() {
+        let server = Server::default();
+        server.0.lock().unwrap().errors = BTreeMap::from([("part 2".to_owned(), vec![403])]);
+        let (path, _) = file("fatal");
+        let error = options()
+            .file(&server.client(), &path, "bucket", "key")
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("AccessDenied (403)"), "{error}");
+        assert!(error.to_string().ends_with("(upload aborted)"), "{error}");
+        assert_eq!(server.requests("part 2"), 1);
+        assert_eq!(
+            (server.requests("abort"), server.requests("complete")),
+            (1, 0)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_an_unfinished_upload() {
+        let server = Server::default();
+        let (path, bytes) = file("resume");
+        {
+            // The first two parts were sent, the third one is not the one of the file
+            let mut state = server.0.lock().unwrap();
+            state.unfinished = true;
+            state.parts = BTreeMap::from([
+                (1, bytes[..5 * MB].to_vec()),
+                (2, bytes[5 * MB..10 * MB].to_vec()),
+                (3, vec![0; 2 * MB]),
+            ]);
+        }
+        let uploaded = options()
+            .file(&server.client(), &path, "bucket", "key")
+            .await
+            .unwrap();
+
+        assert_eq!((uploaded.parts, uploaded.resumed), (3, 2));
+        assert_eq!(server.requests("list parts"), 3);
+        assert_eq!(server.requests("create"), 0);
+        assert_eq!(
+            (server.requests("part 1"), server.requests("part 3")),
+            (0, 1)
+        );
+        assert_eq!(server.0.lock().unwrap().parts[&3], bytes[10 * MB..]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn deletes_an_object_with_another_etag() {
+        let server = Server::default();
+        server.0.lock().unwrap().wrong_etag = true;
+        let (path, _) = file("etag");
+        let error = options()
+            .file(&server.client(), &path, "bucket", "key")
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().ends_with("(object deleted)"), "{error}");
+        assert_eq!(
+            (server.requests("delete"), server.requests("abort")),
+            (1, 0)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}